@@ -0,0 +1,430 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::auth;
+use crate::auth::Claims;
+use crate::db;
+use crate::ws::handler::AppState;
+use crate::ws::room::RoomEvent;
+
+/// Longest note a requester can leave for the board's owners
+const MAX_MESSAGE_LENGTH: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateAccessRequest {
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ApproveAccessRequest {
+    pub role: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> Response {
+    tracing::error!("{} error: {}", context, e);
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &format!("{} failed", context),
+    )
+}
+
+fn get_claims(request: &axum::extract::Request) -> Option<Claims> {
+    auth::middleware::extract_claims(request.extensions())
+}
+
+/// Read an optional JSON body; an empty body gives the defaults
+async fn read_body<T: Default + serde::de::DeserializeOwned>(
+    request: axum::extract::Request,
+) -> Option<T> {
+    let bytes = axum::body::to_bytes(request.into_body(), 1024 * 16)
+        .await
+        .ok()?;
+    if bytes.is_empty() {
+        return Some(T::default());
+    }
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Only the board's owners and admins see and decide access requests
+async fn require_manager(state: &AppState, board_id: Uuid, user_id: Uuid) -> Option<Response> {
+    match db::boards::user_has_access(&state.pool, board_id, user_id).await {
+        Ok(Some(role)) if role == "owner" || role == "admin" => None,
+        Ok(_) => Some(error(
+            StatusCode::FORBIDDEN,
+            "Not authorized to manage access requests",
+        )),
+        Err(e) => Some(internal_error("Access request", e)),
+    }
+}
+
+/// Ask the board's owners for access. Asking again while a request is
+/// pending replaces its message.
+pub async fn request_access(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let body: CreateAccessRequest = match read_body(request).await {
+        Some(b) => b,
+        None => return error(StatusCode::BAD_REQUEST, "Invalid request body"),
+    };
+    let message = body
+        .message
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty());
+    if message.is_some_and(|m| m.chars().count() > MAX_MESSAGE_LENGTH) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!("Message must be at most {} characters", MAX_MESSAGE_LENGTH),
+        );
+    }
+
+    match db::boards::get_board(&state.pool, board_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Board not found"),
+        Err(e) => return internal_error("Request access", e),
+    }
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return error(
+                StatusCode::CONFLICT,
+                "You already have access to this board",
+            )
+        }
+        Err(e) => return internal_error("Request access", e),
+    }
+
+    let access_request =
+        match db::access_requests::create_request(&state.pool, board_id, claims.sub, message).await
+        {
+            Ok(r) => r,
+            Err(e) => return internal_error("Request access", e),
+        };
+    state
+        .room_manager
+        .notify(
+            &board_id,
+            RoomEvent::AccessRequested {
+                request_id: access_request.id,
+                user_id: access_request.user_id,
+                username: access_request.username.clone(),
+                message: access_request.message.clone(),
+            },
+        )
+        .await;
+    (
+        StatusCode::CREATED,
+        Json(serde_json::to_value(access_request).unwrap()),
+    )
+        .into_response()
+}
+
+/// Requests for access to the board awaiting a decision
+pub async fn list_access_requests(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Some(response) = require_manager(&state, board_id, claims.sub).await {
+        return response;
+    }
+    match db::access_requests::list_pending(&state.pool, board_id).await {
+        Ok(requests) => Json(serde_json::to_value(requests).unwrap()).into_response(),
+        Err(e) => internal_error("List access requests", e),
+    }
+}
+
+/// Let the requester in with the given role, editor by default
+pub async fn approve_access_request(
+    State(state): State<Arc<AppState>>,
+    Path((board_id, request_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Some(response) = require_manager(&state, board_id, claims.sub).await {
+        return response;
+    }
+    let body: ApproveAccessRequest = match read_body(request).await {
+        Some(b) => b,
+        None => return error(StatusCode::BAD_REQUEST, "Invalid request body"),
+    };
+    let role = body.role.unwrap_or_else(|| "editor".to_string());
+    if !db::boards::COLLABORATOR_ROLES.contains(&role.as_str()) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "Role must be admin, editor or viewer",
+        );
+    }
+
+    let access_request =
+        match db::access_requests::approve(&state.pool, board_id, request_id, &role, claims.sub)
+            .await
+        {
+            Ok(Some(r)) => r,
+            Ok(None) => return error(StatusCode::NOT_FOUND, "Access request not found"),
+            Err(e) => return internal_error("Approve access request", e),
+        };
+    if let Ok(Some(role)) =
+        db::boards::user_has_access(&state.pool, board_id, access_request.user_id).await
+    {
+        state
+            .room_manager
+            .notify(
+                &board_id,
+                RoomEvent::RoleChanged {
+                    user_id: access_request.user_id,
+                    role,
+                },
+            )
+            .await;
+    }
+    notify_requester(
+        &state,
+        &access_request,
+        db::notifications::ACCESS_APPROVED,
+        claims.sub,
+    )
+    .await;
+    Json(serde_json::to_value(access_request).unwrap()).into_response()
+}
+
+pub async fn deny_access_request(
+    State(state): State<Arc<AppState>>,
+    Path((board_id, request_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Some(response) = require_manager(&state, board_id, claims.sub).await {
+        return response;
+    }
+    let access_request =
+        match db::access_requests::deny(&state.pool, board_id, request_id, claims.sub).await {
+            Ok(Some(r)) => r,
+            Ok(None) => return error(StatusCode::NOT_FOUND, "Access request not found"),
+            Err(e) => return internal_error("Deny access request", e),
+        };
+    notify_requester(
+        &state,
+        &access_request,
+        db::notifications::ACCESS_DENIED,
+        claims.sub,
+    )
+    .await;
+    Json(serde_json::to_value(access_request).unwrap()).into_response()
+}
+
+/// Tell the requester about the decision. The decision stands if this fails.
+async fn notify_requester(
+    state: &AppState,
+    access_request: &db::access_requests::AccessRequest,
+    kind: &str,
+    actor_id: Uuid,
+) {
+    if let Err(e) = db::notifications::create_notification(
+        &state.pool,
+        access_request.user_id,
+        kind,
+        Some(access_request.board_id),
+        Some(actor_id),
+        access_request.role.as_deref(),
+    )
+    .await
+    {
+        tracing::error!("Create notification error: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::ws::test_support::{
+        connect, create_user, delete_users, listener, next_json, serve, test_pool, test_state,
+        token_query,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_access_request_is_denied_then_approved() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "request_owner").await;
+        let requester = create_user(&pool, "requester").await;
+        let board = db::boards::create_board(&pool, "Requests", owner.id, None)
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let ws_addr = serve(state.clone()).await;
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state.clone());
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let owner_query = token_query(&pool, &owner).await;
+        let owner_jwt = owner_query["token=".len()..].to_string();
+        let requester_jwt = token_query(&pool, &requester).await["token=".len()..].to_string();
+        let url = format!("http://{}/api/boards/{}/access-requests", addr, board.id);
+        let ask = |message: &'static str| {
+            client
+                .post(&url)
+                .bearer_auth(&requester_jwt)
+                .json(&json!({ "message": message }))
+                .send()
+        };
+        let pending = || async {
+            client
+                .get(&url)
+                .bearer_auth(&owner_jwt)
+                .send()
+                .await
+                .unwrap()
+                .json::<Value>()
+                .await
+                .unwrap()
+        };
+        let notifications = || async {
+            client
+                .get(format!("http://{}/api/me/notifications", addr))
+                .bearer_auth(&requester_jwt)
+                .send()
+                .await
+                .unwrap()
+                .json::<Value>()
+                .await
+                .unwrap()
+        };
+
+        // The owner is on the board and hears about the request live
+        let mut ws = connect(ws_addr, board.id, &owner_query).await.unwrap();
+        next_json(&mut ws, "sync_state").await.unwrap();
+        assert_eq!(ask("Please").await.unwrap().status(), 201);
+        let live = next_json(&mut ws, "access_requested").await.unwrap();
+        assert_eq!(live["user_id"], requester.id.to_string());
+        assert_eq!(live["message"], "Please");
+
+        // Asking again updates the pending request rather than adding one
+        let response = ask("Please, I need it").await.unwrap();
+        assert_eq!(response.status(), 201);
+        let listed = pending().await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["message"], "Please, I need it");
+        assert_eq!(listed[0]["username"], requester.username);
+        let response = client
+            .get(&url)
+            .bearer_auth(&requester_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+
+        let response = client
+            .post(format!(
+                "{}/{}/deny",
+                url,
+                listed[0]["id"].as_str().unwrap()
+            ))
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(pending().await.as_array().unwrap().is_empty());
+        let notes = notifications().await;
+        assert_eq!(notes[0]["kind"], db::notifications::ACCESS_DENIED);
+        assert_eq!(notes[0]["board_name"], "Requests");
+
+        let request: Value = ask("Second try").await.unwrap().json().await.unwrap();
+        let approve_url = format!("{}/{}/approve", url, request["id"].as_str().unwrap());
+        let response = client
+            .post(&approve_url)
+            .bearer_auth(&owner_jwt)
+            .json(&json!({ "role": "owner" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let response = client
+            .post(&approve_url)
+            .bearer_auth(&owner_jwt)
+            .json(&json!({ "role": "viewer" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            db::boards::user_has_access(&pool, board.id, requester.id)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("viewer")
+        );
+        let notes = notifications().await;
+        assert_eq!(notes.as_array().unwrap().len(), 2);
+        assert_eq!(notes[0]["kind"], db::notifications::ACCESS_APPROVED);
+        assert_eq!(notes[0]["role"], "viewer");
+        assert_eq!(notes[0]["actor_username"], owner.username);
+
+        // Deciding twice finds nothing pending, and members cannot ask
+        let response = client
+            .post(&approve_url)
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        assert_eq!(ask("Again").await.unwrap().status(), 409);
+
+        let response = client
+            .post(format!(
+                "http://{}/api/me/notifications/{}/read",
+                addr,
+                notes[0]["id"].as_str().unwrap()
+            ))
+            .bearer_auth(&requester_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+        let unread: Value = client
+            .get(format!("http://{}/api/me/notifications?unread=true", addr))
+            .bearer_auth(&requester_jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(unread.as_array().unwrap().len(), 1);
+
+        delete_users(&pool, &[&owner, &requester]).await;
+    }
+}
@@ -0,0 +1,613 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::users::is_valid_email;
+use crate::auth;
+use crate::auth::oidc::NO_PASSWORD;
+use crate::auth::password::Verification;
+use crate::auth::Claims;
+use crate::db;
+use crate::ws::handler::AppState;
+use crate::ws::room::RoomEvent;
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileRequest {
+    /// Left out to keep, empty to clear
+    pub display_name: Option<String>,
+    pub avatar_color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: Option<String>,
+    /// Two-factor or recovery code, for accounts without a password
+    pub code: Option<String>,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeEmailRequest {
+    pub email: String,
+    pub password: Option<String>,
+    /// Two-factor or recovery code, for accounts without a password
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: Option<String>,
+    /// Two-factor or recovery code, for accounts without a password
+    pub code: Option<String>,
+    /// Confirms that boards nobody else collaborates on are deleted too
+    #[serde(default)]
+    pub delete_boards: bool,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> Response {
+    tracing::error!("{} error: {}", context, e);
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &format!("{} failed", context),
+    )
+}
+
+/// The signed-in user and the request's JSON body
+async fn user_and_body<T: DeserializeOwned>(
+    state: &AppState,
+    request: axum::extract::Request,
+) -> Result<(Claims, db::users::User, T), Response> {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return Err(error(StatusCode::UNAUTHORIZED, "Not authenticated")),
+    };
+    let user = match db::users::find_by_id(&state.pool, claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(error(StatusCode::UNAUTHORIZED, "Not authenticated")),
+        Err(e) => return Err(internal_error("Account", e)),
+    };
+    let bytes = axum::body::to_bytes(request.into_body(), 1024 * 16)
+        .await
+        .map_err(|_| error(StatusCode::BAD_REQUEST, "Failed to read body"))?;
+    let body = serde_json::from_slice(&bytes)
+        .map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid request body"))?;
+    Ok((claims, user, body))
+}
+
+/// How recently an account without a password must have signed in to make
+/// sensitive changes without a code
+const REAUTH_WINDOW_MINUTES: i64 = 5;
+
+/// Sensitive changes need the current password. Accounts that only sign in
+/// through single sign-on have none to give, so they confirm with a
+/// two-factor or recovery code, or by having just signed in on this session.
+async fn identity_confirmed(
+    state: &AppState,
+    claims: &Claims,
+    user: &db::users::User,
+    password: Option<&str>,
+    code: Option<&str>,
+) -> anyhow::Result<bool> {
+    if user.password_hash != NO_PASSWORD {
+        return Ok(match password {
+            Some(password) => {
+                auth::password::verify(&state.config, password, &user.password_hash)
+                    != Verification::Invalid
+            }
+            None => false,
+        });
+    }
+    if let Some(code) = code {
+        return auth::two_factor::verify_code(state, user.id, code).await;
+    }
+    // Personal access tokens have no session, so they never count as fresh
+    let started_at = db::sessions::started_at(&state.pool, claims.sid).await?;
+    Ok(started_at.is_some_and(|started_at| {
+        chrono::Utc::now() - started_at < chrono::Duration::minutes(REAUTH_WINDOW_MINUTES)
+    }))
+}
+
+/// 403 for a failed confirmation, telling accounts without a password how
+/// they can confirm instead
+fn not_confirmed(user: &db::users::User, message: &str) -> Response {
+    if user.password_hash == NO_PASSWORD {
+        return error(
+            StatusCode::FORBIDDEN,
+            "Sign in again or enter a two-factor code to confirm",
+        );
+    }
+    error(StatusCode::FORBIDDEN, message)
+}
+
+/// `#rrggbb`
+fn is_valid_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Change the display name and avatar colour
+pub async fn update_profile(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let (_, user, req) = match user_and_body::<UpdateProfileRequest>(&state, request).await {
+        Ok(parts) => parts,
+        Err(response) => return response,
+    };
+
+    let display_name = match req.display_name.as_deref().map(str::trim) {
+        None => user.display_name.clone(),
+        Some("") => None,
+        Some(name) if name.chars().count() > 100 => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "Display name must be at most 100 characters",
+            )
+        }
+        Some(name) => Some(name.to_string()),
+    };
+    let avatar_color = match req.avatar_color.as_deref().map(str::trim) {
+        None => user.avatar_color.clone(),
+        Some("") => None,
+        Some(color) if !is_valid_color(color) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "Avatar colour must look like #1a2b3c",
+            )
+        }
+        Some(color) => Some(color.to_ascii_lowercase()),
+    };
+
+    match db::users::update_profile(
+        &state.pool,
+        user.id,
+        display_name.as_deref(),
+        avatar_color.as_deref(),
+    )
+    .await
+    {
+        Ok(Some(user)) => {
+            let public: db::users::UserPublic = user.into();
+            Json(serde_json::to_value(public).unwrap()).into_response()
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, "User not found"),
+        Err(e) => internal_error("Update profile", e),
+    }
+}
+
+/// Set a new password after confirming the current one. Other sessions end.
+pub async fn change_password(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let (claims, user, req) = match user_and_body::<ChangePasswordRequest>(&state, request).await {
+        Ok(parts) => parts,
+        Err(response) => return response,
+    };
+
+    let confirmed = identity_confirmed(
+        &state,
+        &claims,
+        &user,
+        req.current_password.as_deref(),
+        req.code.as_deref(),
+    )
+    .await;
+    match confirmed {
+        Ok(true) => {}
+        Ok(false) => return not_confirmed(&user, "Current password is incorrect"),
+        Err(e) => return internal_error("Change password", e),
+    }
+    if req.new_password.len() < 6 {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "Password must be at least 6 characters",
+        );
+    }
+
+    let password_hash = match auth::password::hash(&state.config, &req.new_password) {
+        Ok(hash) => hash,
+        Err(e) => return internal_error("Change password", e),
+    };
+    if let Err(e) = db::users::update_password(&state.pool, user.id, &password_hash).await {
+        return internal_error("Change password", e);
+    }
+    if let Err(e) = db::sessions::revoke_other_sessions(&state.pool, user.id, claims.sid).await {
+        return internal_error("Change password", e);
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Start an email change. The address only changes once the link sent to
+/// it is opened.
+pub async fn change_email(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let (claims, user, req) = match user_and_body::<ChangeEmailRequest>(&state, request).await {
+        Ok(parts) => parts,
+        Err(response) => return response,
+    };
+
+    let confirmed = identity_confirmed(
+        &state,
+        &claims,
+        &user,
+        req.password.as_deref(),
+        req.code.as_deref(),
+    )
+    .await;
+    match confirmed {
+        Ok(true) => {}
+        Ok(false) => return not_confirmed(&user, "Password is incorrect"),
+        Err(e) => return internal_error("Change email", e),
+    }
+    let email = req.email.trim();
+    if !is_valid_email(email) {
+        return error(StatusCode::BAD_REQUEST, "Invalid email");
+    }
+    if email.eq_ignore_ascii_case(&user.email) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "That is already your email address",
+        );
+    }
+    match db::users::find_by_email(&state.pool, email).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return error(StatusCode::CONFLICT, "Email is already in use"),
+        Err(e) => return internal_error("Change email", e),
+    }
+
+    match auth::email::send_email_change(&state, &user, email).await {
+        Ok(()) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({ "pending_email": email })),
+        )
+            .into_response(),
+        Err(e) => internal_error("Change email", e),
+    }
+}
+
+/// Delete the signed-in account. Shared boards pass to a collaborator;
+/// boards nobody else uses are only deleted with `delete_boards`.
+pub async fn delete_account(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let (claims, user, req) = match user_and_body::<DeleteAccountRequest>(&state, request).await {
+        Ok(parts) => parts,
+        Err(response) => return response,
+    };
+
+    let confirmed = identity_confirmed(
+        &state,
+        &claims,
+        &user,
+        req.password.as_deref(),
+        req.code.as_deref(),
+    )
+    .await;
+    match confirmed {
+        Ok(true) => {}
+        Ok(false) => return not_confirmed(&user, "Password is incorrect"),
+        Err(e) => return internal_error("Delete account", e),
+    }
+
+    if !req.delete_boards {
+        match db::boards::list_unshared_owned(&state.pool, user.id).await {
+            Ok(boards) if !boards.is_empty() => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(serde_json::json!({
+                        "error": "Deleting the account also deletes these boards; \
+                                  confirm with delete_boards",
+                        "boards": boards,
+                    })),
+                )
+                    .into_response()
+            }
+            Ok(_) => {}
+            Err(e) => return internal_error("Delete account", e),
+        }
+    }
+
+    match db::users::delete_user(&state.pool, user.id).await {
+        Ok(deleted) => {
+            for board_id in &deleted.deleted_boards {
+                state
+                    .room_manager
+                    .notify(board_id, RoomEvent::BoardDeleted)
+                    .await;
+            }
+            for (board_id, owner_id) in &deleted.transferred_boards {
+                state
+                    .room_manager
+                    .notify(
+                        board_id,
+                        RoomEvent::RoleChanged {
+                            user_id: *owner_id,
+                            role: "owner".to_string(),
+                        },
+                    )
+                    .await;
+            }
+            let still_open = deleted
+                .transferred_boards
+                .iter()
+                .map(|(board_id, _)| board_id)
+                .chain(&deleted.left_boards);
+            for board_id in still_open {
+                state
+                    .room_manager
+                    .notify(board_id, RoomEvent::AccessRevoked { user_id: user.id })
+                    .await;
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => internal_error("Delete account", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use super::*;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state, token_query,
+    };
+
+    #[test]
+    fn test_is_valid_color() {
+        assert!(is_valid_color("#1a2B3c"));
+        assert!(!is_valid_color("1a2b3c"));
+        assert!(!is_valid_color("#1a2b3"));
+        assert!(!is_valid_color("#1a2b3g"));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_profile_password_and_deletion() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "account").await;
+        let editor = create_user(&pool, "account_editor").await;
+        let viewer = create_user(&pool, "account_viewer").await;
+        let state = test_state(pool.clone());
+        let hash = auth::password::hash(&state.config, "hunter22").unwrap();
+        db::users::update_password(&pool, owner.id, &hash)
+            .await
+            .unwrap();
+        let shared = db::boards::create_board(&pool, "Shared", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, shared.id, viewer.id, "viewer")
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, shared.id, editor.id, "editor")
+            .await
+            .unwrap();
+        let private = db::boards::create_board(&pool, "Private", owner.id, None)
+            .await
+            .unwrap();
+
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+        let jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+
+        let profile: serde_json::Value = client
+            .patch(url("/api/me"))
+            .bearer_auth(&jwt)
+            .json(&serde_json::json!({"display_name": " Jane ", "avatar_color": "#AABBCC"}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(profile["display_name"], "Jane");
+        assert_eq!(profile["avatar_color"], "#aabbcc");
+        let bad_color = client
+            .patch(url("/api/me"))
+            .bearer_auth(&jwt)
+            .json(&serde_json::json!({"avatar_color": "red"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(bad_color.status().as_u16(), 400);
+
+        let change_password = |current: &str| {
+            client
+                .post(url("/api/me/password"))
+                .bearer_auth(&jwt)
+                .json(&serde_json::json!({"current_password": current, "new_password": "correct horse"}))
+                .send()
+        };
+        assert_eq!(
+            change_password("wrong").await.unwrap().status().as_u16(),
+            403
+        );
+        assert_eq!(
+            change_password("hunter22").await.unwrap().status().as_u16(),
+            204
+        );
+
+        let delete_account = |body: serde_json::Value| {
+            client
+                .delete(url("/api/me"))
+                .bearer_auth(&jwt)
+                .json(&body)
+                .send()
+        };
+        assert_eq!(
+            delete_account(serde_json::json!({"password": "hunter22"}))
+                .await
+                .unwrap()
+                .status()
+                .as_u16(),
+            403
+        );
+        // The unshared board needs explicit confirmation
+        let refused = delete_account(serde_json::json!({"password": "correct horse"}))
+            .await
+            .unwrap();
+        assert_eq!(refused.status().as_u16(), 409);
+        let refused: serde_json::Value = refused.json().await.unwrap();
+        assert_eq!(refused["boards"][0]["id"], private.id.to_string());
+
+        let deleted =
+            delete_account(serde_json::json!({"password": "correct horse", "delete_boards": true}))
+                .await
+                .unwrap();
+        assert_eq!(deleted.status().as_u16(), 204);
+
+        assert!(db::users::find_by_id(&pool, owner.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(db::boards::get_board(&pool, private.id)
+            .await
+            .unwrap()
+            .is_none());
+        // The editor takes over the shared board
+        let shared = db::boards::get_board(&pool, shared.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(shared.owner_id, editor.id);
+        assert_eq!(
+            db::boards::user_has_access(&pool, shared.id, editor.id)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("owner")
+        );
+        assert_eq!(
+            db::boards::user_has_access(&pool, shared.id, viewer.id)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("viewer")
+        );
+
+        delete_users(&pool, &[&editor, &viewer]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_single_sign_on_account_must_confirm() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "account_sso").await;
+        db::users::update_password(&pool, user.id, NO_PASSWORD)
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state.clone());
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+
+        // A session from a sign-in long ago, as a stolen access token would carry
+        let stale = token_query(&pool, &user).await["token=".len()..].to_string();
+        sqlx::query(
+            "UPDATE sessions SET created_at = NOW() - INTERVAL '1 hour' WHERE user_id = $1",
+        )
+        .bind(user.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let send = |method: reqwest::Method, path: &str, jwt: &str, body: serde_json::Value| {
+            client
+                .request(method, url(path))
+                .bearer_auth(jwt)
+                .json(&body)
+                .send()
+        };
+        let email = format!("moved_{}@example.com", user.id.simple());
+        let attempts = [
+            (
+                reqwest::Method::POST,
+                "/api/me/password",
+                serde_json::json!({"new_password": "correct horse"}),
+            ),
+            (
+                reqwest::Method::POST,
+                "/api/me/email",
+                serde_json::json!({ "email": email }),
+            ),
+            (
+                reqwest::Method::DELETE,
+                "/api/me",
+                serde_json::json!({"delete_boards": true}),
+            ),
+        ];
+        for (method, path, body) in &attempts {
+            let response = send(method.clone(), path, &stale, body.clone())
+                .await
+                .unwrap();
+            assert_eq!(response.status().as_u16(), 403, "{}", path);
+        }
+        let unchanged = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(unchanged.password_hash, NO_PASSWORD);
+
+        // Having just signed in confirms it
+        let fresh = token_query(&pool, &user).await["token=".len()..].to_string();
+        let (method, path, body) = &attempts[1];
+        let response = send(method.clone(), path, &fresh, body.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 202);
+
+        // So does a two-factor code, on any session
+        let setup = auth::two_factor::begin_setup(&state, &user)
+            .await
+            .unwrap()
+            .unwrap();
+        let recovery = auth::two_factor::confirm_setup(
+            &state,
+            user.id,
+            &auth::totp::code_at(&setup.secret, chrono::Utc::now().timestamp() as u64),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let response = send(
+            reqwest::Method::DELETE,
+            "/api/me",
+            &stale,
+            serde_json::json!({"delete_boards": true, "code": "not-a-code"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status().as_u16(), 403);
+        let response = send(
+            reqwest::Method::DELETE,
+            "/api/me",
+            &stale,
+            serde_json::json!({"delete_boards": true, "code": recovery[0]}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status().as_u16(), 204);
+        assert!(db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
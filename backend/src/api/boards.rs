@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
@@ -12,10 +12,13 @@ use uuid::Uuid;
 use crate::auth;
 use crate::db;
 use crate::ws::handler::AppState;
+use crate::ws::room::RoomEvent;
 
 #[derive(Debug, Deserialize)]
 pub struct CreateBoardRequest {
     pub name: String,
+    /// Create the board inside this organisation
+    pub org_id: Option<Uuid>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,6 +26,33 @@ pub struct UpdateBoardRequest {
     pub name: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ListBoardsQuery {
+    /// Only boards filed directly in this folder
+    pub folder_id: Option<Uuid>,
+    /// Only boards outside any folder
+    #[serde(default)]
+    pub unfiled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetBoardFolderRequest {
+    /// `null` takes the board out of its folder
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetBoardOrgRequest {
+    /// `null` takes the board out of its organisation
+    pub org_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferBoardRequest {
+    /// The new owner
+    pub username: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AddCollaboratorRequest {
     pub username: String,
@@ -75,7 +105,28 @@ pub async fn create_board(
         }
     };
 
-    match db::boards::create_board(&state.pool, &body.name, claims.sub).await {
+    if let Some(org_id) = body.org_id {
+        match super::organizations::can_add_boards(&state, org_id, claims.sub).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(serde_json::json!({"error": "Not allowed to add boards to this organization"})),
+                )
+                    .into_response()
+            }
+            Err(e) => {
+                tracing::error!("Create board error: {}", e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({"error": "Failed to create board"})),
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    match db::boards::create_board(&state.pool, &body.name, claims.sub, body.org_id).await {
         Ok(board) => {
             let summary: db::boards::BoardSummary = board.into();
             (
@@ -97,6 +148,7 @@ pub async fn create_board(
 
 pub async fn list_boards(
     State(state): State<Arc<AppState>>,
+    Query(query): Query<ListBoardsQuery>,
     request: axum::extract::Request,
 ) -> impl IntoResponse {
     let claims = match get_claims(request.extensions()) {
@@ -112,8 +164,14 @@ pub async fn list_boards(
 
     match db::boards::list_boards_for_user(&state.pool, claims.sub).await {
         Ok(boards) => {
-            let summaries: Vec<db::boards::BoardSummary> =
-                boards.into_iter().map(|b| b.into()).collect();
+            let summaries: Vec<db::boards::BoardSummary> = boards
+                .into_iter()
+                .filter(|b| match query.folder_id {
+                    Some(folder_id) => b.folder_id == Some(folder_id),
+                    None => !query.unfiled || b.folder_id.is_none(),
+                })
+                .map(|b| b.into())
+                .collect();
             Json(serde_json::to_value(summaries).unwrap()).into_response()
         }
         Err(e) => {
@@ -246,6 +304,310 @@ pub async fn update_board(
     }
 }
 
+/// Hand the board to another user. Only the owner can; they stay on as an
+/// admin collaborator.
+pub async fn transfer_board(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match get_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(Some(role)) if role == "owner" => {}
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "Only the owner can transfer the board"})),
+            )
+                .into_response()
+        }
+    }
+
+    let body: TransferBoardRequest =
+        match axum::body::to_bytes(request.into_body(), 1024 * 16).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(b) => b,
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({"error": "Invalid request body"})),
+                    )
+                        .into_response()
+                }
+            },
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": "Failed to read body"})),
+                )
+                    .into_response()
+            }
+        };
+
+    let user = match db::users::find_by_username(&state.pool, &body.username).await {
+        Ok(Some(u)) => u,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "User not found"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Find user error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to find user"})),
+            )
+                .into_response();
+        }
+    };
+    if user.id == claims.sub {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "You already own this board"})),
+        )
+            .into_response();
+    }
+
+    match db::boards::transfer_ownership(&state.pool, board_id, user.id).await {
+        Ok(Some(board)) => {
+            super::organizations::refresh_access(&state, &[board_id], None).await;
+            let summary: db::boards::BoardSummary = board.into();
+            Json(serde_json::to_value(summary).unwrap()).into_response()
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Board not found"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Transfer board error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to transfer board"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// File a board in a folder, or take it out of one. Needs owner or admin on
+/// the board and the right to add to the target folder.
+pub async fn set_board_folder(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match get_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(Some(role)) if role == "owner" || role == "admin" => {}
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "Only owner/admin can move board"})),
+            )
+                .into_response()
+        }
+    }
+
+    let body: SetBoardFolderRequest =
+        match axum::body::to_bytes(request.into_body(), 1024 * 16).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(b) => b,
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({"error": "Invalid request body"})),
+                    )
+                        .into_response()
+                }
+            },
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": "Failed to read body"})),
+                )
+                    .into_response()
+            }
+        };
+
+    if let Some(folder_id) = body.folder_id {
+        match super::folders::can_file_boards(&state, folder_id, claims.sub).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(serde_json::json!({"error": "Not allowed to add to this folder"})),
+                )
+                    .into_response()
+            }
+            Err(e) => {
+                tracing::error!("Move board error: {}", e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({"error": "Failed to move board"})),
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    match db::boards::set_board_folder(&state.pool, board_id, body.folder_id).await {
+        Ok(Some(board)) => {
+            super::organizations::refresh_access(&state, &[board_id], None).await;
+            let summary: db::boards::BoardSummary = board.into();
+            Json(serde_json::to_value(summary).unwrap()).into_response()
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Board not found"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Move board error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to move board"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Move a board into an organisation, or take it out of one. Only the board
+/// owner can move it in, and only into an organisation where they are more
+/// than a guest; the owner or the organisation's owners and admins can take
+/// it out.
+pub async fn set_board_org(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match get_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    let board = match db::boards::get_board(&state.pool, board_id).await {
+        Ok(Some(board)) => board,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "Board not found"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Get board error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to move board"})),
+            )
+                .into_response();
+        }
+    };
+
+    let body: SetBoardOrgRequest =
+        match axum::body::to_bytes(request.into_body(), 1024 * 16).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(b) => b,
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({"error": "Invalid request body"})),
+                    )
+                        .into_response()
+                }
+            },
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": "Failed to read body"})),
+                )
+                    .into_response()
+            }
+        };
+
+    let is_owner = board.owner_id == claims.sub;
+    let allowed = match (body.org_id, board.org_id) {
+        (Some(org_id), _) if is_owner => {
+            super::organizations::can_add_boards(&state, org_id, claims.sub).await
+        }
+        (Some(_), _) => Ok(false),
+        (None, _) if is_owner => Ok(true),
+        (None, Some(current)) => db::organizations::get_role(&state.pool, current, claims.sub)
+            .await
+            .map(|role| role.is_some_and(|role| db::organizations::can_manage(&role))),
+        (None, None) => Ok(false),
+    };
+    match allowed {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "Not authorized to move this board"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Move board error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to move board"})),
+            )
+                .into_response();
+        }
+    }
+
+    match db::boards::set_board_org(&state.pool, board_id, body.org_id).await {
+        Ok(Some(board)) => {
+            super::organizations::refresh_access(&state, &[board_id], None).await;
+            let summary: db::boards::BoardSummary = board.into();
+            Json(serde_json::to_value(summary).unwrap()).into_response()
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Board not found"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Move board error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to move board"})),
+            )
+                .into_response()
+        }
+    }
+}
+
 pub async fn delete_board(
     State(state): State<Arc<AppState>>,
     Path(board_id): Path<Uuid>,
@@ -275,7 +637,13 @@ pub async fn delete_board(
     }
 
     match db::boards::delete_board(&state.pool, board_id).await {
-        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(true) => {
+            state
+                .room_manager
+                .notify(&board_id, RoomEvent::BoardDeleted)
+                .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Ok(false) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"error": "Board not found"})),
@@ -362,11 +730,22 @@ pub async fn add_collaborator(
 
     let role = body.role.unwrap_or_else(|| "editor".to_string());
     match db::boards::add_collaborator(&state.pool, board_id, user.id, &role).await {
-        Ok(collab) => (
-            StatusCode::CREATED,
-            Json(serde_json::to_value(collab).unwrap()),
-        )
-            .into_response(),
+        Ok(collab) => {
+            // Apply the effective role live; an owner listed as collaborator stays owner
+            if let Ok(Some(role)) =
+                db::boards::user_has_access(&state.pool, board_id, user.id).await
+            {
+                state
+                    .room_manager
+                    .notify(&board_id, RoomEvent::RoleChanged { user_id: user.id, role })
+                    .await;
+            }
+            (
+                StatusCode::CREATED,
+                Json(serde_json::to_value(collab).unwrap()),
+            )
+                .into_response()
+        }
         Err(e) => {
             tracing::error!("Add collaborator error: {}", e);
             (
@@ -406,7 +785,12 @@ pub async fn remove_collaborator(
     }
 
     match db::boards::remove_collaborator(&state.pool, board_id, user_id).await {
-        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(true) => {
+            // They may still inherit access through the board's organisation,
+            // so re-check before pushing a new role or closing their socket
+            super::organizations::refresh_access(&state, &[board_id], Some(user_id)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Ok(false) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"error": "Collaborator not found"})),
@@ -450,6 +834,29 @@ pub async fn create_share_link(
         }
     }
 
+    // Share links hand out access to anyone, so require a confirmed address first
+    match db::users::find_by_id(&state.pool, claims.sub).await {
+        Ok(Some(user)) if user.is_email_verified() => {}
+        Ok(_) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": "Verify your email address before creating share links",
+                    "code": "email_unverified",
+                })),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Create share link error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to create share link"})),
+            )
+                .into_response();
+        }
+    }
+
     let body: CreateShareLinkRequest =
         match axum::body::to_bytes(request.into_body(), 1024 * 16).await {
             Ok(bytes) => match serde_json::from_slice(&bytes) {
@@ -485,7 +892,16 @@ pub async fn create_share_link(
         .expires_in_hours
         .map(|h| chrono::Utc::now() + chrono::Duration::hours(h));
 
-    match db::boards::create_share_link(&state.pool, board_id, &token, &role, expires_at).await {
+    match db::boards::create_share_link(
+        &state.pool,
+        board_id,
+        &token,
+        &role,
+        expires_at,
+        claims.sub,
+    )
+    .await
+    {
         Ok(link) => (
             StatusCode::CREATED,
             Json(serde_json::to_value(link).unwrap()),
@@ -544,10 +960,10 @@ pub async fn get_share_links(
 
 pub async fn delete_share_link(
     State(state): State<Arc<AppState>>,
-    Path((_board_id, link_id)): Path<(Uuid, Uuid)>,
+    Path((board_id, link_id)): Path<(Uuid, Uuid)>,
     request: axum::extract::Request,
 ) -> impl IntoResponse {
-    let _claims = match get_claims(request.extensions()) {
+    let claims = match get_claims(request.extensions()) {
         Some(c) => c,
         None => {
             return (
@@ -558,8 +974,26 @@ pub async fn delete_share_link(
         }
     };
 
-    match db::boards::delete_share_link(&state.pool, link_id).await {
-        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(Some(role)) if role == "owner" || role == "admin" => {}
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "Not authorized"})),
+            )
+                .into_response()
+        }
+    }
+
+    match db::boards::delete_share_link(&state.pool, board_id, link_id).await {
+        Ok(true) => {
+            // Disconnect guests who joined through the link
+            state
+                .room_manager
+                .notify(&board_id, RoomEvent::ShareLinkRevoked { link_id })
+                .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Ok(false) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"error": "Share link not found"})),
@@ -604,3 +1038,141 @@ pub async fn get_board_by_share_token(
             .into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::ws::test_support::{
+        close_code, connect, create_user, delete_users, listener, next_json, serve, test_pool,
+        test_state, token_query,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_transfer_demotes_previous_owner() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "transfer_owner").await;
+        let heir = create_user(&pool, "transfer_heir").await;
+        let board = db::boards::create_board(&pool, "Handover", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, heir.id, "viewer")
+            .await
+            .unwrap();
+
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let owner_jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+        let heir_jwt = token_query(&pool, &heir).await["token=".len()..].to_string();
+        let transfer = |jwt: &str, username: &str| {
+            client
+                .post(format!("http://{}/api/boards/{}/transfer", addr, board.id))
+                .bearer_auth(jwt)
+                .json(&json!({ "username": username }))
+                .send()
+        };
+
+        assert_eq!(
+            transfer(&heir_jwt, &heir.username).await.unwrap().status(),
+            403
+        );
+        assert_eq!(
+            transfer(&owner_jwt, &owner.username).await.unwrap().status(),
+            400
+        );
+        let response = transfer(&owner_jwt, &heir.username).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let summary: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(summary["owner_id"], heir.id.to_string());
+
+        let role = |user_id| db::boards::user_has_access(&pool, board.id, user_id);
+        assert_eq!(role(heir.id).await.unwrap().as_deref(), Some("owner"));
+        assert_eq!(role(owner.id).await.unwrap().as_deref(), Some("admin"));
+        // The new owner's old collaborator entry is gone
+        let collaborators = db::boards::get_collaborators(&pool, board.id).await.unwrap();
+        assert_eq!(collaborators.len(), 1);
+        assert_eq!(collaborators[0].user_id, owner.id);
+
+        // The former owner is now only an admin and cannot transfer it back
+        assert_eq!(
+            transfer(&owner_jwt, &owner.username).await.unwrap().status(),
+            403
+        );
+
+        delete_users(&pool, &[&owner, &heir]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_removed_collaborator_keeps_inherited_access() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "uncollab_owner").await;
+        let member = create_user(&pool, "uncollab_member").await;
+        let outsider = create_user(&pool, "uncollab_outsider").await;
+        let org = db::organizations::create_organization(&pool, "Uncollab", owner.id)
+            .await
+            .unwrap();
+        db::organizations::set_member(&pool, org.id, member.id, db::organizations::MEMBER)
+            .await
+            .unwrap();
+        let board = db::boards::create_board(&pool, "Shared", owner.id, Some(org.id))
+            .await
+            .unwrap();
+        for user in [&member, &outsider] {
+            db::boards::add_collaborator(&pool, board.id, user.id, "admin")
+                .await
+                .unwrap();
+        }
+
+        let state = test_state(pool.clone());
+        let ws_addr = serve(state.clone()).await;
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let owner_jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+
+        let mut member_ws = connect(ws_addr, board.id, &token_query(&pool, &member).await)
+            .await
+            .unwrap();
+        next_json(&mut member_ws, "sync_state").await.unwrap();
+        let mut outsider_ws = connect(ws_addr, board.id, &token_query(&pool, &outsider).await)
+            .await
+            .unwrap();
+        next_json(&mut outsider_ws, "sync_state").await.unwrap();
+
+        for user in [&member, &outsider] {
+            let response = client
+                .delete(format!(
+                    "http://{}/api/boards/{}/collaborators/{}",
+                    addr, board.id, user.id
+                ))
+                .bearer_auth(&owner_jwt)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 204);
+        }
+
+        // The org member drops to the role they inherit and stays connected
+        let changed = next_json(&mut member_ws, "role_changed").await.unwrap();
+        assert_eq!(changed["role"], "editor");
+        // Someone with no other route to the board is disconnected
+        assert_eq!(
+            close_code(&mut outsider_ws).await,
+            Some(crate::ws::permissions::CLOSE_ACCESS_REVOKED)
+        );
+
+        db::organizations::delete_organization(&pool, org.id)
+            .await
+            .unwrap();
+        delete_users(&pool, &[&owner, &member, &outsider]).await;
+    }
+}
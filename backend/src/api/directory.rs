@@ -0,0 +1,371 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth;
+use crate::db;
+use crate::db::users::DirectoryUser;
+use crate::ws::handler::AppState;
+
+const MIN_QUERY_LEN: usize = 2;
+/// Fuzzy matching is quadratic in the query, so long ones are refused
+const MAX_QUERY_LEN: usize = 64;
+const MAX_RESULTS: usize = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+/// A search hit. The email address is only shown to members of the same
+/// organisation; people who merely share a board see the username and profile.
+#[derive(Debug, Serialize)]
+pub struct UserResult {
+    pub id: Uuid,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub avatar_color: Option<String>,
+}
+
+impl From<DirectoryUser> for UserResult {
+    fn from(u: DirectoryUser) -> Self {
+        UserResult {
+            id: u.id,
+            username: u.username,
+            email: u.shares_org.then_some(u.email),
+            display_name: u.display_name,
+            avatar_color: u.avatar_color,
+        }
+    }
+}
+
+/// Look up people to invite among the users the caller already shares a
+/// board with, so the directory does not expose every account
+pub async fn search_users(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    let q = query.q.trim().to_lowercase();
+    let len = q.chars().count();
+    if len < MIN_QUERY_LEN {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": format!("Search needs at least {} characters", MIN_QUERY_LEN)
+            })),
+        )
+            .into_response();
+    }
+    if len > MAX_QUERY_LEN {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": format!("Search can be at most {} characters", MAX_QUERY_LEN)
+            })),
+        )
+            .into_response();
+    }
+
+    let candidates = match db::users::list_connected_users(&state.pool, claims.sub).await {
+        Ok(users) => users,
+        Err(e) => {
+            tracing::error!("Search users error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to search users"})),
+            )
+                .into_response();
+        }
+    };
+
+    let results: Vec<UserResult> = rank(&q, candidates).into_iter().map(Into::into).collect();
+    Json(serde_json::to_value(results).unwrap()).into_response()
+}
+
+/// Best matches first, then alphabetically; non-matches are dropped. The
+/// email address only matches where it would be shown, so a search cannot
+/// confirm a guessed address.
+fn rank(q: &str, candidates: Vec<DirectoryUser>) -> Vec<DirectoryUser> {
+    let mut scored: Vec<(u32, DirectoryUser)> = candidates
+        .into_iter()
+        .filter_map(|user| {
+            let fields = [
+                Some(user.username.as_str()),
+                user.display_name.as_deref(),
+                user.shares_org.then_some(user.email.as_str()),
+            ];
+            let score = fields
+                .into_iter()
+                .flatten()
+                .filter_map(|field| score(q, &field.to_lowercase()))
+                .max()?;
+            Some((score, user))
+        })
+        .collect();
+    scored.sort_by(|(a, ua), (b, ub)| b.cmp(a).then_with(|| ua.username.cmp(&ub.username)));
+    scored
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, user)| user)
+        .collect()
+}
+
+/// How well a lowercase field matches the query: exact, prefix, substring,
+/// or a prefix with a typo or two
+fn score(q: &str, field: &str) -> Option<u32> {
+    if field == q {
+        return Some(100);
+    }
+    if field.starts_with(q) {
+        return Some(80);
+    }
+    if field.contains(q) {
+        return Some(60);
+    }
+    let q: Vec<char> = q.chars().collect();
+    let prefix: Vec<char> = field.chars().take(q.len()).collect();
+    let allowed = (q.len() / 4).max(1);
+    if q.len() >= 3 && edit_distance(&q, &prefix) <= allowed {
+        return Some(40);
+    }
+    None
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use super::*;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state, token_query,
+    };
+
+    fn user(username: &str, email: &str) -> DirectoryUser {
+        DirectoryUser {
+            id: Uuid::new_v4(),
+            username: username.to_string(),
+            email: email.to_string(),
+            display_name: None,
+            avatar_color: None,
+            shares_org: false,
+        }
+    }
+
+    fn colleague(username: &str, email: &str) -> DirectoryUser {
+        DirectoryUser {
+            shares_org: true,
+            ..user(username, email)
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("anna", "anna"), Some(100));
+        assert_eq!(score("ann", "annabel"), Some(80));
+        assert_eq!(score("bel", "annabel"), Some(60));
+        assert_eq!(score("anma", "annabel"), Some(40));
+        assert_eq!(score("xyz", "annabel"), None);
+        // Short queries must match exactly
+        assert_eq!(score("ax", "annabel"), None);
+    }
+
+    #[test]
+    fn test_rank_orders_and_filters() {
+        let ranked = rank(
+            "jen",
+            vec![
+                user("benjamin", "ben@example.com"),
+                user("jens", "jens@example.com"),
+                colleague("bob", "jenny.smith@example.com"),
+                user("carl", "carl@example.com"),
+                user("jen", "x@example.com"),
+            ],
+        );
+        let names: Vec<&str> = ranked.iter().map(|u| u.username.as_str()).collect();
+        assert_eq!(names, ["jen", "bob", "jens", "benjamin"]);
+    }
+
+    #[test]
+    fn test_rank_ignores_hidden_emails() {
+        let candidates = || {
+            vec![
+                user("partner", "john.doe@acme.com"),
+                colleague("colleague", "mary@acme.com"),
+            ]
+        };
+        assert!(rank("john.doe@acme.com", candidates()).is_empty());
+        let ranked = rank("mary@acme.com", candidates());
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].username, "colleague");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(edit_distance(&chars(""), &chars("abc")), 3);
+        assert_eq!(edit_distance(&chars("same"), &chars("same")), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_search_only_finds_connected_users() {
+        let pool = test_pool().await;
+        let me = create_user(&pool, "dir_me").await;
+        let partner = create_user(&pool, "dir_partner").await;
+        let stranger = create_user(&pool, "dir_stranger").await;
+        let board = db::boards::create_board(&pool, "Shared", partner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, me.id, "editor")
+            .await
+            .unwrap();
+
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let jwt = token_query(&pool, &me).await["token=".len()..].to_string();
+
+        let results: serde_json::Value = reqwest::Client::new()
+            .get(format!("http://{}/api/users/search", addr))
+            .query(&[("q", "dir_")])
+            .bearer_auth(&jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let results = results.as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["id"], partner.id.to_string());
+        assert!(results[0].get("email").is_none());
+
+        let too_long = reqwest::Client::new()
+            .get(format!("http://{}/api/users/search", addr))
+            .query(&[("q", "a".repeat(MAX_QUERY_LEN + 1))])
+            .bearer_auth(&jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(too_long.status(), 400);
+
+        delete_users(&pool, &[&me, &partner, &stranger]).await;
+    }
+
+    /// The single result a search is expected to return
+    async fn search_one(
+        client: &reqwest::Client,
+        url: &str,
+        jwt: &str,
+        q: &str,
+    ) -> serde_json::Value {
+        let results: serde_json::Value = client
+            .get(url)
+            .query(&[("q", q)])
+            .bearer_auth(jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(results.as_array().unwrap().len(), 1, "{}", q);
+        results[0].clone()
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_email_is_only_shown_to_org_co_members() {
+        let pool = test_pool().await;
+        let me = create_user(&pool, "dirorg_me").await;
+        let colleague = create_user(&pool, "dirorg_colleague").await;
+        let partner = create_user(&pool, "dirorg_partner").await;
+        let org = db::organizations::create_organization(&pool, "Directory Org", me.id)
+            .await
+            .unwrap();
+        db::organizations::set_member(&pool, org.id, colleague.id, db::organizations::MEMBER)
+            .await
+            .unwrap();
+        // Sharing a board as well does not hide the colleague's address
+        let board = db::boards::create_board(&pool, "Shared", me.id, None)
+            .await
+            .unwrap();
+        for user in [&colleague, &partner] {
+            db::boards::add_collaborator(&pool, board.id, user.id, "editor")
+                .await
+                .unwrap();
+        }
+
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let jwt = token_query(&pool, &me).await["token=".len()..].to_string();
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/api/users/search", addr);
+        let search = |q: &'static str| search_one(&client, &url, &jwt, q);
+        let found = search("dirorg_colleague").await;
+        assert_eq!(found["id"], colleague.id.to_string());
+        assert_eq!(found["email"], colleague.email);
+        let found = search("dirorg_partner").await;
+        assert_eq!(found["id"], partner.id.to_string());
+        assert!(found.get("email").is_none());
+
+        // Only addresses that are shown can be searched for
+        let found = search_one(&client, &url, &jwt, &colleague.email).await;
+        assert_eq!(found["id"], colleague.id.to_string());
+        let results: serde_json::Value = client
+            .get(&url)
+            .query(&[("q", &partner.email)])
+            .bearer_auth(&jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(results, serde_json::json!([]));
+
+        db::organizations::delete_organization(&pool, org.id)
+            .await
+            .unwrap();
+        delete_users(&pool, &[&me, &colleague, &partner]).await;
+    }
+}
@@ -0,0 +1,491 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::Value;
+use uuid::Uuid;
+use yrs::Doc;
+
+use crate::auth;
+use crate::db;
+use crate::model::Element;
+use crate::ws::elements;
+use crate::ws::handler::AppState;
+use crate::ws::room::Room;
+use crate::ws::{storage, sync};
+
+/// Elements may carry large drawings, so allow bigger bodies than the other endpoints
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// The document element reads and writes go to: the live room document if the
+/// board is open, otherwise a document decoded from the persisted state.
+pub(super) enum BoardDoc {
+    Live(Room),
+    Stored(Doc),
+}
+
+impl BoardDoc {
+    /// Returns None if the board does not exist
+    pub(super) async fn load(state: &AppState, board_id: Uuid) -> anyhow::Result<Option<Self>> {
+        if let Some(room) = state.room_manager.get_room(&board_id).await {
+            return Ok(Some(BoardDoc::Live(room)));
+        }
+        let doc = Doc::new();
+        if !storage::load_board(&state.pool, board_id, &doc).await? {
+            return Ok(None);
+        }
+        Ok(Some(BoardDoc::Stored(doc)))
+    }
+
+    pub(super) async fn read<T>(&self, f: impl FnOnce(&Doc) -> T) -> T {
+        match self {
+            BoardDoc::Live(room) => f(&*room.doc.read().await),
+            BoardDoc::Stored(doc) => f(doc),
+        }
+    }
+
+    /// Publish a change: append it to the board's update log and broadcast it
+    /// to connected clients if the room is open.
+    pub(super) async fn commit(
+        &self,
+        state: &AppState,
+        board_id: Uuid,
+        update: &[u8],
+        messages: &[Value],
+    ) -> anyhow::Result<()> {
+        db::updates::append_update(&state.pool, board_id, update).await?;
+        if let BoardDoc::Live(room) = self {
+            let _ = room.tx.send(sync::create_update_message(update));
+            for message in messages {
+                let _ = room.tx.send(serde_json::to_vec(message)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn generate_element_id() -> String {
+    use rand::Rng;
+    let suffix: String = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(6)
+        .map(|c| (c as char).to_ascii_lowercase())
+        .collect();
+    format!("el_{}_0_{}", chrono::Utc::now().timestamp_millis(), suffix)
+}
+
+fn find_element(doc: &Doc, element_id: &str) -> Option<Value> {
+    elements::get_elements(doc)
+        .into_iter()
+        .find(|e| e.get("id").and_then(|v| v.as_str()) == Some(element_id))
+}
+
+/// Check the board exists and the caller's role on it. Editing requires a
+/// role other than viewer.
+async fn check_access(
+    state: &AppState,
+    board_id: Uuid,
+    user_id: Uuid,
+    write: bool,
+) -> Result<(), axum::response::Response> {
+    match db::boards::get_board(&state.pool, board_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "Board not found"})),
+            )
+                .into_response())
+        }
+        Err(e) => {
+            tracing::error!("Load board error: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to load board"})),
+            )
+                .into_response());
+        }
+    }
+    match db::boards::user_has_access(&state.pool, board_id, user_id).await {
+        Ok(Some(role)) if !write || role != "viewer" => Ok(()),
+        Ok(Some(_)) => Err((
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({"error": "Viewers cannot edit this board"})),
+        )
+            .into_response()),
+        Ok(None) => Err((
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({"error": "No access to this board"})),
+        )
+            .into_response()),
+        Err(e) => {
+            tracing::error!("Check board access error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to check board access"})),
+            )
+                .into_response())
+        }
+    }
+}
+
+pub(super) async fn load_doc(
+    state: &AppState,
+    board_id: Uuid,
+) -> Result<BoardDoc, axum::response::Response> {
+    match BoardDoc::load(state, board_id).await {
+        Ok(Some(doc)) => Ok(doc),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Board not found"})),
+        )
+            .into_response()),
+        Err(e) => {
+            tracing::error!("Load board state error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to load board"})),
+            )
+                .into_response())
+        }
+    }
+}
+
+async fn read_json_body(
+    request: axum::extract::Request,
+) -> Result<Value, axum::response::Response> {
+    match axum::body::to_bytes(request.into_body(), MAX_BODY_SIZE).await {
+        Ok(bytes) => match serde_json::from_slice::<Value>(&bytes) {
+            Ok(v) if v.is_object() => Ok(v),
+            _ => Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "Invalid request body"})),
+            )
+                .into_response()),
+        },
+        Err(_) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Failed to read body"})),
+        )
+            .into_response()),
+    }
+}
+
+pub async fn list_elements(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    if let Err(response) = check_access(&state, board_id, claims.sub, false).await {
+        return response;
+    }
+
+    let doc = match load_doc(&state, board_id).await {
+        Ok(doc) => doc,
+        Err(response) => return response,
+    };
+
+    let list = doc.read(elements::get_elements).await;
+    Json(Value::Array(list)).into_response()
+}
+
+pub async fn create_element(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    if let Err(response) = check_access(&state, board_id, claims.sub, true).await {
+        return response;
+    }
+
+    let mut body = match read_json_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    if body.get("id").is_none() {
+        body["id"] = Value::String(generate_element_id());
+    }
+
+    let element = match Element::from_json(body) {
+        Ok(e) => e,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+                .into_response()
+        }
+    };
+
+    let doc = match load_doc(&state, board_id).await {
+        Ok(doc) => doc,
+        Err(response) => return response,
+    };
+
+    let result = doc
+        .read(|d| {
+            if find_element(d, element.id()).is_some() {
+                return Ok(None);
+            }
+            elements::upsert_element(d, &element).map(Some)
+        })
+        .await;
+    let update = match result {
+        Ok(Some(update)) => update,
+        Ok(None) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({"error": "Element already exists"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Create element error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to create element"})),
+            )
+                .into_response();
+        }
+    };
+
+    let message = serde_json::json!({"type": "element_add", "element": element.to_json()});
+    if let Err(e) = doc.commit(&state, board_id, &update, &[message]).await {
+        tracing::error!("Create element error: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": "Failed to create element"})),
+        )
+            .into_response();
+    }
+
+    (StatusCode::CREATED, Json(element.to_json())).into_response()
+}
+
+pub async fn update_element(
+    State(state): State<Arc<AppState>>,
+    Path((board_id, element_id)): Path<(Uuid, String)>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    if let Err(response) = check_access(&state, board_id, claims.sub, true).await {
+        return response;
+    }
+
+    let patch = match read_json_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+
+    let doc = match load_doc(&state, board_id).await {
+        Ok(doc) => doc,
+        Err(response) => return response,
+    };
+
+    let mut merged = match doc.read(|d| find_element(d, &element_id)).await {
+        Some(existing) => existing,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "Element not found"})),
+            )
+                .into_response()
+        }
+    };
+
+    // Shallow merge; the id and type of an element are fixed
+    if let (Some(target), Some(fields)) = (merged.as_object_mut(), patch.as_object()) {
+        for (key, value) in fields {
+            if key != "id" && key != "type" {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    let element = match Element::from_json(merged) {
+        Ok(e) => e,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+                .into_response()
+        }
+    };
+
+    let update = match doc.read(|d| elements::upsert_element(d, &element)).await {
+        Ok(update) => update,
+        Err(e) => {
+            tracing::error!("Update element error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to update element"})),
+            )
+                .into_response();
+        }
+    };
+
+    let message = serde_json::json!({"type": "element_update", "element": element.to_json()});
+    if let Err(e) = doc.commit(&state, board_id, &update, &[message]).await {
+        tracing::error!("Update element error: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": "Failed to update element"})),
+        )
+            .into_response();
+    }
+
+    Json(element.to_json()).into_response()
+}
+
+pub async fn delete_element(
+    State(state): State<Arc<AppState>>,
+    Path((board_id, element_id)): Path<(Uuid, String)>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    if let Err(response) = check_access(&state, board_id, claims.sub, true).await {
+        return response;
+    }
+
+    let doc = match load_doc(&state, board_id).await {
+        Ok(doc) => doc,
+        Err(response) => return response,
+    };
+
+    let update = match doc.read(|d| elements::remove_element(d, &element_id)).await {
+        Some(update) => update,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "Element not found"})),
+            )
+                .into_response()
+        }
+    };
+
+    let message = serde_json::json!({"type": "element_remove", "elementId": element_id});
+    if let Err(e) = doc.commit(&state, board_id, &update, &[message]).await {
+        tracing::error!("Delete element error: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": "Failed to delete element"})),
+        )
+            .into_response();
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use super::*;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state, token_query,
+    };
+
+    #[test]
+    fn test_generated_ids_are_valid_and_unique() {
+        let a = generate_element_id();
+        let b = generate_element_id();
+        assert!(a.starts_with("el_"));
+        assert_ne!(a, b);
+        let element = Element::from_json(serde_json::json!({
+            "id": a, "type": "text", "x": 0, "y": 0, "color": "#000"
+        }));
+        assert!(element.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stored_doc_read_and_write() {
+        let doc = BoardDoc::Stored(Doc::new());
+        let element = Element::from_json(serde_json::json!({
+            "id": "el_1", "type": "sticky", "x": 0, "y": 0, "width": 200, "height": 200,
+            "color": "#FFF176"
+        }))
+        .unwrap();
+
+        doc.read(|d| elements::upsert_element(d, &element))
+            .await
+            .unwrap();
+        let found = doc.read(|d| find_element(d, "el_1")).await;
+        assert!(found.is_some());
+        assert!(doc.read(|d| find_element(d, "el_2")).await.is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_missing_board_is_not_found() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "elements_owner").await;
+        let stranger = create_user(&pool, "elements_stranger").await;
+        let board = db::boards::create_board(&pool, "Elements", owner.id, None)
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let jwt = token_query(&pool, &stranger).await["token=".len()..].to_string();
+        let status = |board_id: Uuid| {
+            client
+                .get(format!("http://{}/api/boards/{}/elements", addr, board_id))
+                .bearer_auth(&jwt)
+                .send()
+        };
+
+        assert_eq!(status(Uuid::new_v4()).await.unwrap().status(), 404);
+        assert_eq!(status(board.id).await.unwrap().status(), 403);
+
+        delete_users(&pool, &[&owner, &stranger]).await;
+    }
+}
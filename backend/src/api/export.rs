@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+
+use crate::auth;
+use crate::db;
+use crate::export;
+use crate::ws::handler::AppState;
+
+/// Start a personal data export, or report on the one in progress. Answers
+/// 202 while the archive is being built and 200 with a download link once
+/// it is ready. Each ready answer carries a fresh link that replaces the last.
+pub async fn export_data(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match export::request_export(&state, claims.sub).await {
+        Ok(export) if export.status == db::exports::READY => {
+            let token = match export::issue_download_token(&state, export.id).await {
+                Ok(token) => token,
+                Err(e) => {
+                    tracing::error!("Data export error: {}", e);
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(serde_json::json!({"error": "Failed to create download link"})),
+                    )
+                        .into_response();
+                }
+            };
+            Json(serde_json::json!({
+                "id": export.id,
+                "status": export.status,
+                "download_url": format!("{}/api/exports/{}", state.config.public_url, token),
+                "completed_at": export.completed_at,
+                "expires_at": export.expires_at,
+            }))
+            .into_response()
+        }
+        Ok(export) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({
+                "id": export.id,
+                "status": export.status,
+                "created_at": export.created_at,
+            })),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Data export error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to start data export"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Download a finished export. The link itself is the credential.
+pub async fn download_export(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    match db::exports::get_archive(&state.pool, &auth::hash_secret_token(&token)).await {
+        Ok(Some((archive, completed_at))) => {
+            let filename = format!(
+                "attachment; filename=\"whiteboard-export-{}.zip\"",
+                completed_at.format("%Y%m%d")
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, "application/zip".to_string()),
+                    (header::CONTENT_DISPOSITION, filename),
+                    (header::CACHE_CONTROL, "no-store".to_string()),
+                ],
+                archive,
+            )
+                .into_response()
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Export not found or expired"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Download export error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to download export"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+    use std::io::{Cursor, Read};
+
+    use axum::routing::get;
+
+    use super::*;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state, token_query,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_export_builds_and_downloads() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "export").await;
+        let other = create_user(&pool, "export_other").await;
+        let board = db::boards::create_board(&pool, "Mine", user.id, None)
+            .await
+            .unwrap();
+        let theirs = db::boards::create_board(&pool, "Theirs", other.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, theirs.id, user.id, "viewer")
+            .await
+            .unwrap();
+        // Share links need a verified address
+        db::users::mark_email_verified(&pool, user.id)
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone())
+            .route("/api/exports/:token", get(download_export))
+            .with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+        let jwt = token_query(&pool, &user).await["token=".len()..].to_string();
+
+        let sticky = serde_json::json!({
+            "type": "sticky", "x": 1, "y": 2, "width": 200, "height": 200,
+            "color": "#ffeb3b", "content": "hello",
+        });
+        client
+            .post(url(&format!("/api/boards/{}/elements", board.id)))
+            .bearer_auth(&jwt)
+            .json(&sticky)
+            .send()
+            .await
+            .unwrap();
+        client
+            .post(url(&format!("/api/boards/{}/share-links", board.id)))
+            .bearer_auth(&jwt)
+            .json(&serde_json::json!({}))
+            .send()
+            .await
+            .unwrap();
+
+        let started = client
+            .get(url("/api/me/export"))
+            .bearer_auth(&jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(started.status().as_u16(), 202);
+
+        let mut ready = serde_json::Value::Null;
+        for _ in 0..50 {
+            let response = client
+                .get(url("/api/me/export"))
+                .bearer_auth(&jwt)
+                .send()
+                .await
+                .unwrap();
+            if response.status().as_u16() == 200 {
+                ready = response.json().await.unwrap();
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert_eq!(ready["status"], "ready");
+        let download_url = ready["download_url"].as_str().unwrap();
+        let path = &download_url[download_url.find("/api/exports/").unwrap()..];
+
+        // Only the hash of the link's token is stored
+        let token = &path["/api/exports/".len()..];
+        let (stored,): (Option<String>,) =
+            sqlx::query_as("SELECT token_hash FROM data_exports WHERE id = $1")
+                .bind(uuid::Uuid::parse_str(ready["id"].as_str().unwrap()).unwrap())
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            stored.as_deref(),
+            Some(auth::hash_secret_token(token).as_str())
+        );
+
+        // The link works without a session
+        let download = client.get(url(path)).send().await.unwrap();
+        assert_eq!(download.status().as_u16(), 200);
+        assert_eq!(download.headers()[header::CONTENT_TYPE], "application/zip");
+        let bytes = download.bytes().await.unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes.to_vec())).unwrap();
+        let mut read = |name: &str| {
+            let mut contents = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&contents).unwrap()
+        };
+        assert_eq!(read("profile.json")["user"]["id"], user.id.to_string());
+        let exported = read(&format!("boards/{}/board.json", board.id));
+        assert_eq!(exported["elements"][0]["content"], "hello");
+        assert_eq!(
+            read("memberships.json")[0]["board_id"],
+            theirs.id.to_string()
+        );
+        assert_eq!(
+            read("share_links.json")[0]["board_id"],
+            board.id.to_string()
+        );
+        assert!(!read("activity.json")["sessions"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+        assert!(zip
+            .by_name(&format!("boards/{}/state.yrs", board.id))
+            .is_ok());
+        assert!(zip
+            .by_name(&format!("boards/{}/board.json", theirs.id))
+            .is_err());
+
+        let missing = client
+            .get(url("/api/exports/not-a-token"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status().as_u16(), 404);
+
+        delete_users(&pool, &[&user, &other]).await;
+    }
+}
@@ -0,0 +1,663 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::organizations::{can_add_boards, refresh_access};
+use crate::auth;
+use crate::auth::Claims;
+use crate::db;
+use crate::db::folders::Folder;
+use crate::ws::handler::AppState;
+use crate::ws::permissions::can_edit;
+
+const MAX_NAME_LEN: usize = 255;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateFolderRequest {
+    pub name: String,
+    /// Create a subfolder; it lives in the parent's space
+    pub parent_id: Option<Uuid>,
+    /// Create a top-level folder in this organisation instead of the caller's own space
+    pub org_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameFolderRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveFolderRequest {
+    /// `null` moves the folder to the top level
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddFolderCollaboratorRequest {
+    pub username: String,
+    pub role: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> Response {
+    tracing::error!("{} error: {}", context, e);
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &format!("{} failed", context),
+    )
+}
+
+fn get_claims(request: &axum::extract::Request) -> Option<Claims> {
+    auth::middleware::extract_claims(request.extensions())
+}
+
+async fn read_body<T: DeserializeOwned>(request: axum::extract::Request) -> Result<T, Response> {
+    let bytes = axum::body::to_bytes(request.into_body(), 1024 * 16)
+        .await
+        .map_err(|_| error(StatusCode::BAD_REQUEST, "Failed to read body"))?;
+    serde_json::from_slice(&bytes)
+        .map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid request body"))
+}
+
+/// The trimmed name, if it is acceptable
+fn valid_name(name: &str) -> Option<&str> {
+    let name = name.trim();
+    (!name.is_empty() && name.chars().count() <= MAX_NAME_LEN).then_some(name)
+}
+
+fn invalid_name() -> Response {
+    error(
+        StatusCode::BAD_REQUEST,
+        &format!("Name must be 1-{} characters", MAX_NAME_LEN),
+    )
+}
+
+/// The folder and the caller's role on it. Folders the caller cannot see
+/// are reported as missing.
+async fn folder_access(
+    state: &AppState,
+    folder_id: Uuid,
+    user_id: Uuid,
+) -> Result<(Folder, String), Response> {
+    let role = match db::folders::folder_role(&state.pool, folder_id, user_id).await {
+        Ok(Some(role)) => role,
+        Ok(None) => return Err(error(StatusCode::NOT_FOUND, "Folder not found")),
+        Err(e) => return Err(internal_error("Folder", e)),
+    };
+    match db::folders::get_folder(&state.pool, folder_id).await {
+        Ok(Some(folder)) => Ok((folder, role)),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Folder not found")),
+        Err(e) => Err(internal_error("Folder", e)),
+    }
+}
+
+/// Like `folder_access`, but only for the folder's owners and admins
+async fn folder_manager(
+    state: &AppState,
+    folder_id: Uuid,
+    user_id: Uuid,
+) -> Result<Folder, Response> {
+    let (folder, role) = folder_access(state, folder_id, user_id).await?;
+    if role != "owner" && role != "admin" {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "Only owners and admins can manage this folder",
+        ));
+    }
+    Ok(folder)
+}
+
+/// Like `folder_access`, but only for those who may add to the folder
+async fn folder_editor(
+    state: &AppState,
+    folder_id: Uuid,
+    user_id: Uuid,
+) -> Result<Folder, Response> {
+    let (folder, role) = folder_access(state, folder_id, user_id).await?;
+    if !can_edit(&role) {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "Not allowed to add to this folder",
+        ));
+    }
+    Ok(folder)
+}
+
+/// Re-check the roles of connected users on every board below the folder
+async fn refresh_tree(state: &AppState, folder_id: Uuid, only: Option<Uuid>) {
+    match db::folders::list_board_ids_in_tree(&state.pool, folder_id).await {
+        Ok(board_ids) => refresh_access(state, &board_ids, only).await,
+        Err(e) => tracing::error!("List folder boards error: {}", e),
+    }
+}
+
+/// Every folder the caller can see, flat; `parent_id` gives the tree
+pub async fn list_folders(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    match db::folders::list_folders_for_user(&state.pool, claims.sub).await {
+        Ok(folders) => Json(serde_json::to_value(folders).unwrap()).into_response(),
+        Err(e) => internal_error("List folders", e),
+    }
+}
+
+/// Create a folder in the caller's space, an organisation's, or under a parent
+pub async fn create_folder(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let body: CreateFolderRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let name = match valid_name(&body.name) {
+        Some(name) => name,
+        None => return invalid_name(),
+    };
+
+    let (owner_id, org_id) = match (body.parent_id, body.org_id) {
+        (Some(parent_id), org_id) => {
+            let parent = match folder_editor(&state, parent_id, claims.sub).await {
+                Ok(parent) => parent,
+                Err(response) => return response,
+            };
+            if org_id.is_some() && org_id != parent.org_id {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    "A subfolder belongs to its parent's space",
+                );
+            }
+            (parent.owner_id, parent.org_id)
+        }
+        (None, Some(org_id)) => match can_add_boards(&state, org_id, claims.sub).await {
+            Ok(true) => (None, Some(org_id)),
+            Ok(false) => {
+                return error(
+                    StatusCode::FORBIDDEN,
+                    "Not allowed to add folders to this organization",
+                )
+            }
+            Err(e) => return internal_error("Create folder", e),
+        },
+        (None, None) => (Some(claims.sub), None),
+    };
+
+    match db::folders::create_folder(&state.pool, name, body.parent_id, owner_id, org_id).await {
+        Ok(folder) => (
+            StatusCode::CREATED,
+            Json(serde_json::to_value(folder).unwrap()),
+        )
+            .into_response(),
+        Err(e) => internal_error("Create folder", e),
+    }
+}
+
+/// A folder with the caller's role, its subfolders and its boards
+pub async fn get_folder(
+    State(state): State<Arc<AppState>>,
+    Path(folder_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let (folder, role) = match folder_access(&state, folder_id, claims.sub).await {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    // Access on a folder reaches everything inside it
+    let folders = match db::folders::list_children(&state.pool, folder_id).await {
+        Ok(folders) => folders,
+        Err(e) => return internal_error("Get folder", e),
+    };
+    let boards: Vec<db::boards::BoardSummary> =
+        match db::folders::list_boards(&state.pool, folder_id).await {
+            Ok(boards) => boards.into_iter().map(Into::into).collect(),
+            Err(e) => return internal_error("Get folder", e),
+        };
+    Json(serde_json::json!({
+        "folder": folder,
+        "role": role,
+        "folders": folders,
+        "boards": boards,
+    }))
+    .into_response()
+}
+
+pub async fn rename_folder(
+    State(state): State<Arc<AppState>>,
+    Path(folder_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Err(response) = folder_manager(&state, folder_id, claims.sub).await {
+        return response;
+    }
+    let body: RenameFolderRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let name = match valid_name(&body.name) {
+        Some(name) => name,
+        None => return invalid_name(),
+    };
+
+    match db::folders::rename_folder(&state.pool, folder_id, name).await {
+        Ok(Some(folder)) => Json(serde_json::to_value(folder).unwrap()).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Folder not found"),
+        Err(e) => internal_error("Rename folder", e),
+    }
+}
+
+/// Move a folder under another in the same space, or to the top level
+pub async fn move_folder(
+    State(state): State<Arc<AppState>>,
+    Path(folder_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let folder = match folder_manager(&state, folder_id, claims.sub).await {
+        Ok(folder) => folder,
+        Err(response) => return response,
+    };
+    let body: MoveFolderRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+
+    if let Some(parent_id) = body.parent_id {
+        let parent = match folder_editor(&state, parent_id, claims.sub).await {
+            Ok(parent) => parent,
+            Err(response) => return response,
+        };
+        if parent.owner_id != folder.owner_id || parent.org_id != folder.org_id {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "Folders can only be moved within the same space",
+            );
+        }
+        match db::folders::is_within(&state.pool, parent_id, folder_id).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    "A folder cannot be moved into itself",
+                )
+            }
+            Err(e) => return internal_error("Move folder", e),
+        }
+    }
+
+    match db::folders::move_folder(&state.pool, folder_id, body.parent_id).await {
+        Ok(Some(folder)) => {
+            refresh_tree(&state, folder_id, None).await;
+            Json(serde_json::to_value(folder).unwrap()).into_response()
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, "Folder not found"),
+        Err(e) => internal_error("Move folder", e),
+    }
+}
+
+/// Delete a folder and its subfolders; the boards in them are kept
+pub async fn delete_folder(
+    State(state): State<Arc<AppState>>,
+    Path(folder_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Err(response) = folder_manager(&state, folder_id, claims.sub).await {
+        return response;
+    }
+
+    let board_ids = match db::folders::list_board_ids_in_tree(&state.pool, folder_id).await {
+        Ok(ids) => ids,
+        Err(e) => return internal_error("Delete folder", e),
+    };
+    match db::folders::delete_folder(&state.pool, folder_id).await {
+        Ok(true) => {
+            refresh_access(&state, &board_ids, None).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => error(StatusCode::NOT_FOUND, "Folder not found"),
+        Err(e) => internal_error("Delete folder", e),
+    }
+}
+
+pub async fn list_collaborators(
+    State(state): State<Arc<AppState>>,
+    Path(folder_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Err(response) = folder_access(&state, folder_id, claims.sub).await {
+        return response;
+    }
+    match db::folders::list_collaborators(&state.pool, folder_id).await {
+        Ok(collabs) => Json(serde_json::to_value(collabs).unwrap()).into_response(),
+        Err(e) => internal_error("List folder collaborators", e),
+    }
+}
+
+/// Grant a user a role on the folder and every board and folder inside it
+pub async fn add_collaborator(
+    State(state): State<Arc<AppState>>,
+    Path(folder_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Err(response) = folder_manager(&state, folder_id, claims.sub).await {
+        return response;
+    }
+    let body: AddFolderCollaboratorRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let role = body.role.unwrap_or_else(|| "editor".to_string());
+    if !db::boards::COLLABORATOR_ROLES.contains(&role.as_str()) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "Role must be admin, editor or viewer",
+        );
+    }
+
+    let user = match db::users::find_by_username(&state.pool, &body.username).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "User not found"),
+        Err(e) => return internal_error("Find user", e),
+    };
+    match db::folders::add_collaborator(&state.pool, folder_id, user.id, &role).await {
+        Ok(collab) => {
+            refresh_tree(&state, folder_id, Some(user.id)).await;
+            (
+                StatusCode::CREATED,
+                Json(serde_json::to_value(collab).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => internal_error("Add folder collaborator", e),
+    }
+}
+
+pub async fn remove_collaborator(
+    State(state): State<Arc<AppState>>,
+    Path((folder_id, user_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Err(response) = folder_manager(&state, folder_id, claims.sub).await {
+        return response;
+    }
+    match db::folders::remove_collaborator(&state.pool, folder_id, user_id).await {
+        Ok(true) => {
+            refresh_tree(&state, folder_id, Some(user_id)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => error(StatusCode::NOT_FOUND, "Collaborator not found"),
+        Err(e) => internal_error("Remove folder collaborator", e),
+    }
+}
+
+/// Whether the user may file boards in the folder
+pub(crate) async fn can_file_boards(
+    state: &AppState,
+    folder_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<bool> {
+    let role = db::folders::folder_role(&state.pool, folder_id, user_id).await?;
+    Ok(role.is_some_and(|role| can_edit(&role)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state, token_query,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_folder_access_cascades_to_boards() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "folder_owner").await;
+        let friend = create_user(&pool, "folder_friend").await;
+
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let owner_jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+        let friend_jwt = token_query(&pool, &friend).await["token=".len()..].to_string();
+        let url = |path: String| format!("http://{}{}", addr, path);
+
+        let create = |body: Value| {
+            client
+                .post(url("/api/folders".into()))
+                .bearer_auth(&owner_jwt)
+                .json(&body)
+                .send()
+        };
+        let top: Value = create(json!({"name": "2025"}))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(top["owner_id"], owner.id.to_string());
+        let top_id = top["id"].as_str().unwrap().to_string();
+        let sub: Value = create(json!({"name": "Copenhagen", "parent_id": top_id}))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let sub_id = sub["id"].as_str().unwrap().to_string();
+
+        let board = db::boards::create_board(&pool, "Hall B", owner.id, None)
+            .await
+            .unwrap();
+        let response = client
+            .put(url(format!("/api/boards/{}/folder", board.id)))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({"folder_id": sub_id}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        // Nothing is visible to the friend until the top folder is shared
+        let role = || db::boards::user_has_access(&pool, board.id, friend.id);
+        assert_eq!(role().await.unwrap(), None);
+        let response = client
+            .get(url(format!("/api/folders/{}", sub_id)))
+            .bearer_auth(&friend_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        let response = client
+            .post(url(format!("/api/folders/{}/collaborators", top_id)))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({"username": friend.username, "role": "viewer"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(role().await.unwrap().as_deref(), Some("viewer"));
+
+        let folders: Value = client
+            .get(url("/api/folders".into()))
+            .bearer_auth(&friend_jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(folders.as_array().unwrap().len(), 2);
+        let boards: Value = client
+            .get(url("/api/boards".into()))
+            .query(&[("folder_id", &sub_id)])
+            .bearer_auth(&friend_jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(boards.as_array().unwrap().len(), 1);
+        assert_eq!(boards[0]["id"], board.id.to_string());
+        let folder: Value = client
+            .get(url(format!("/api/folders/{}", top_id)))
+            .bearer_auth(&friend_jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(folder["role"], "viewer");
+        assert_eq!(folder["folders"][0]["id"], sub_id.as_str());
+
+        // Viewers cannot rename, and a folder cannot move into its own subfolder
+        let response = client
+            .put(url(format!("/api/folders/{}", top_id)))
+            .bearer_auth(&friend_jwt)
+            .json(&json!({"name": "Mine now"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let response = client
+            .put(url(format!("/api/folders/{}/parent", top_id)))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({"parent_id": sub_id}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+
+        let response = client
+            .delete(url(format!(
+                "/api/folders/{}/collaborators/{}",
+                top_id, friend.id
+            )))
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+        assert_eq!(role().await.unwrap(), None);
+
+        // Deleting the folders keeps the board
+        let response = client
+            .delete(url(format!("/api/folders/{}", top_id)))
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+        let board = db::boards::get_board(&pool, board.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(board.folder_id, None);
+
+        delete_users(&pool, &[&owner, &friend]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_folder_access_keeps_boards_of_deleted_account() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "folder_leaver").await;
+        let friend = create_user(&pool, "folder_heir").await;
+        let host = create_user(&pool, "folder_host").await;
+        let top = db::folders::create_folder(&pool, "Archive", None, Some(owner.id), None)
+            .await
+            .unwrap();
+        let sub = db::folders::create_folder(&pool, "2024", Some(top.id), Some(owner.id), None)
+            .await
+            .unwrap();
+        db::folders::add_collaborator(&pool, top.id, friend.id, "editor")
+            .await
+            .unwrap();
+        let hosted = db::folders::create_folder(&pool, "Guests", None, Some(host.id), None)
+            .await
+            .unwrap();
+
+        let mut boards = Vec::new();
+        for (name, folder_id) in [("Shared", Some(sub.id)), ("Hosted", Some(hosted.id))] {
+            let board = db::boards::create_board(&pool, name, owner.id, None)
+                .await
+                .unwrap();
+            db::boards::set_board_folder(&pool, board.id, folder_id)
+                .await
+                .unwrap();
+            boards.push(board.id);
+        }
+        let private = db::boards::create_board(&pool, "Private", owner.id, None)
+            .await
+            .unwrap();
+
+        // Only the board nobody else can reach counts as unshared
+        let unshared = db::boards::list_unshared_owned(&pool, owner.id)
+            .await
+            .unwrap();
+        let unshared: Vec<Uuid> = unshared.iter().map(|b| b.id).collect();
+        assert_eq!(unshared, [private.id]);
+
+        let deleted = db::users::delete_user(&pool, owner.id).await.unwrap();
+        assert_eq!(deleted.deleted_boards, [private.id]);
+        let mut transferred = deleted.transferred_boards;
+        transferred.sort_by_key(|(board_id, _)| boards.iter().position(|id| id == board_id));
+        assert_eq!(transferred, [(boards[0], friend.id), (boards[1], host.id)]);
+
+        delete_users(&pool, &[&friend, &host]).await;
+    }
+}
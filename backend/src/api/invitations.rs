@@ -0,0 +1,411 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::users::is_valid_email;
+use crate::auth;
+use crate::auth::Claims;
+use crate::db;
+use crate::ws::handler::AppState;
+
+/// How long an invitation stays open unless the request says otherwise
+const DEFAULT_EXPIRY_HOURS: i64 = 14 * 24;
+/// The longest an invitation can be kept open
+const MAX_EXPIRY_HOURS: i64 = 90 * 24;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    pub role: Option<String>,
+    pub expires_in_hours: Option<i64>,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> Response {
+    tracing::error!("{} error: {}", context, e);
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &format!("{} failed", context),
+    )
+}
+
+fn get_claims(request: &axum::extract::Request) -> Option<Claims> {
+    auth::middleware::extract_claims(request.extensions())
+}
+
+/// Only the board's owners and admins manage its invitations
+async fn require_manager(state: &AppState, board_id: Uuid, user_id: Uuid) -> Option<Response> {
+    match db::boards::user_has_access(&state.pool, board_id, user_id).await {
+        Ok(Some(role)) if role == "owner" || role == "admin" => None,
+        Ok(_) => Some(error(
+            StatusCode::FORBIDDEN,
+            "Not authorized to manage invitations",
+        )),
+        Err(e) => Some(internal_error("Invitation", e)),
+    }
+}
+
+/// Invite an email address to the board and send them a link. The
+/// invitation is accepted when a verified account with that address signs
+/// in, straight away if one already exists.
+pub async fn create_invitation(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Some(response) = require_manager(&state, board_id, claims.sub).await {
+        return response;
+    }
+
+    // Invitations send email to any address, so require a confirmed sender
+    let inviter = match db::users::find_by_id(&state.pool, claims.sub).await {
+        Ok(Some(user)) if user.is_email_verified() => user,
+        Ok(_) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": "Verify your email address before inviting people",
+                    "code": "email_unverified",
+                })),
+            )
+                .into_response()
+        }
+        Err(e) => return internal_error("Create invitation", e),
+    };
+
+    let body: CreateInvitationRequest =
+        match axum::body::to_bytes(request.into_body(), 1024 * 16).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(b) => b,
+                Err(_) => return error(StatusCode::BAD_REQUEST, "Invalid request body"),
+            },
+            Err(_) => return error(StatusCode::BAD_REQUEST, "Failed to read body"),
+        };
+    let email = body.email.trim().to_lowercase();
+    if !is_valid_email(&email) {
+        return error(StatusCode::BAD_REQUEST, "Invalid email address");
+    }
+    let role = body.role.unwrap_or_else(|| "editor".to_string());
+    if !db::boards::COLLABORATOR_ROLES.contains(&role.as_str()) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "Role must be admin, editor or viewer",
+        );
+    }
+    let hours = body.expires_in_hours.unwrap_or(DEFAULT_EXPIRY_HOURS);
+    if !(1..=MAX_EXPIRY_HOURS).contains(&hours) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!("Expiry must be 1-{} hours away", MAX_EXPIRY_HOURS),
+        );
+    }
+    let expires_at = chrono::Utc::now() + chrono::Duration::hours(hours);
+
+    let board = match db::boards::get_board(&state.pool, board_id).await {
+        Ok(Some(board)) => board,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Board not found"),
+        Err(e) => return internal_error("Create invitation", e),
+    };
+    let invitation = match db::invitations::create_invitation(
+        &state.pool,
+        board_id,
+        &email,
+        &role,
+        inviter.id,
+        expires_at,
+    )
+    .await
+    {
+        Ok(invitation) => invitation,
+        Err(e) => return internal_error("Create invitation", e),
+    };
+    if let Err(e) = auth::email::send_board_invitation(&state, &inviter, &board, &invitation).await
+    {
+        tracing::error!("Send invitation error: {}", e);
+    }
+
+    // An existing verified account joins now. Only this invitation is
+    // taken, and the answer is the same either way so it does not reveal
+    // whether the address has an account.
+    match db::users::find_by_email(&state.pool, &email).await {
+        Ok(Some(user)) if user.is_email_verified() => {
+            match db::invitations::accept_for_user(
+                &state.pool,
+                user.id,
+                &user.email,
+                Some(invitation.id),
+            )
+            .await
+            {
+                Ok(joined) if !joined.is_empty() => {
+                    super::organizations::refresh_access(&state, &[board_id], Some(user.id)).await;
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Accept invitation error: {}", e),
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::error!("Find user error: {}", e),
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(serde_json::to_value(invitation).unwrap()),
+    )
+        .into_response()
+}
+
+/// Open invitations to the board
+pub async fn list_invitations(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Some(response) = require_manager(&state, board_id, claims.sub).await {
+        return response;
+    }
+    match db::invitations::list_for_board(&state.pool, board_id).await {
+        Ok(invitations) => Json(serde_json::to_value(invitations).unwrap()).into_response(),
+        Err(e) => internal_error("List invitations", e),
+    }
+}
+
+pub async fn revoke_invitation(
+    State(state): State<Arc<AppState>>,
+    Path((board_id, invitation_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Some(response) = require_manager(&state, board_id, claims.sub).await {
+        return response;
+    }
+    match db::invitations::delete_invitation(&state.pool, board_id, invitation_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error(StatusCode::NOT_FOUND, "Invitation not found"),
+        Err(e) => internal_error("Revoke invitation", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::mail::outbox::Outbox;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state_with_mailer, token_query,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_invitation_is_accepted_once_the_address_is_verified() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "invite_owner").await;
+        let board = db::boards::create_board(&pool, "Invites", owner.id, None)
+            .await
+            .unwrap();
+        let outbox = Arc::new(Outbox::in_memory());
+        let state = test_state_with_mailer(pool.clone(), outbox.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state.clone());
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let owner_jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+        let url = format!("http://{}/api/boards/{}/invitations", addr, board.id);
+        let invite = |email: String| {
+            client
+                .post(&url)
+                .bearer_auth(&owner_jwt)
+                .json(&json!({ "email": email, "role": "viewer" }))
+                .send()
+        };
+
+        let suffix = Uuid::new_v4().simple().to_string();
+        let email = format!("Newcomer_{}@Example.com", suffix);
+        let response = invite(email.clone()).await.unwrap();
+        assert_eq!(response.status(), 403);
+
+        db::users::mark_email_verified(&pool, owner.id)
+            .await
+            .unwrap();
+        for hours in [0, MAX_EXPIRY_HOURS + 1, i64::MAX] {
+            let response = client
+                .post(&url)
+                .bearer_auth(&owner_jwt)
+                .json(&json!({ "email": email, "expires_in_hours": hours }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400, "{} hours", hours);
+        }
+        let response = invite(email.clone()).await.unwrap();
+        assert_eq!(response.status(), 201);
+        let invitation: Value = response.json().await.unwrap();
+        assert_eq!(invitation["email"], email.to_lowercase());
+        assert!(invitation.get("accepted").is_none());
+        let sent = outbox.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, email.to_lowercase());
+        assert!(sent[0].body.contains(&board.id.to_string()));
+
+        let other: Value = invite(format!("other_{}@example.com", suffix))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let response = client
+            .delete(format!("{}/{}", url, other["id"].as_str().unwrap()))
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+        let listed: Value = client
+            .get(&url)
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+
+        // Registering the address is not enough until it is verified
+        let newcomer =
+            db::users::create_user(&pool, &format!("newcomer_{}", &suffix[..12]), &email, "x")
+                .await
+                .unwrap();
+        auth::sessions::start_session(&state, &newcomer, None)
+            .await
+            .unwrap();
+        let role = || db::boards::user_has_access(&pool, board.id, newcomer.id);
+        assert_eq!(role().await.unwrap(), None);
+
+        db::users::mark_email_verified(&pool, newcomer.id)
+            .await
+            .unwrap();
+        let newcomer = db::users::find_by_id(&pool, newcomer.id)
+            .await
+            .unwrap()
+            .unwrap();
+        auth::sessions::start_session(&state, &newcomer, None)
+            .await
+            .unwrap();
+        assert_eq!(role().await.unwrap().as_deref(), Some("viewer"));
+        assert!(db::invitations::list_for_board(&pool, board.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        delete_users(&pool, &[&owner, &newcomer]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_inviting_an_existing_account_accepts_only_that_invitation() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "invite_host").await;
+        let member = create_user(&pool, "invite_member").await;
+        for user in [&owner, &member] {
+            db::users::mark_email_verified(&pool, user.id)
+                .await
+                .unwrap();
+        }
+        let board = db::boards::create_board(&pool, "Current", owner.id, None)
+            .await
+            .unwrap();
+        let elsewhere = db::boards::create_board(&pool, "Elsewhere", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, member.id, "viewer")
+            .await
+            .unwrap();
+        // Left open on another board, to be taken up later
+        db::invitations::create_invitation(
+            &pool,
+            elsewhere.id,
+            &member.email,
+            "editor",
+            owner.id,
+            chrono::Utc::now() + chrono::Duration::hours(1),
+        )
+        .await
+        .unwrap();
+
+        let state = test_state_with_mailer(pool.clone(), Arc::new(Outbox::in_memory()));
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let owner_jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+        let response = reqwest::Client::new()
+            .post(format!(
+                "http://{}/api/boards/{}/invitations",
+                addr, board.id
+            ))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({ "email": member.email, "role": "editor" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+        let invitation: Value = response.json().await.unwrap();
+        assert!(invitation.get("accepted").is_none());
+
+        // The existing viewer is upgraded rather than the invitation lost
+        let role = |board_id| db::boards::user_has_access(&pool, board_id, member.id);
+        assert_eq!(role(board.id).await.unwrap().as_deref(), Some("editor"));
+        assert_eq!(role(elsewhere.id).await.unwrap(), None);
+        let open = db::invitations::list_for_board(&pool, elsewhere.id)
+            .await
+            .unwrap();
+        assert_eq!(open.len(), 1);
+
+        // A weaker invitation never lowers a role
+        db::invitations::create_invitation(
+            &pool,
+            board.id,
+            &member.email,
+            "viewer",
+            owner.id,
+            chrono::Utc::now() + chrono::Duration::hours(1),
+        )
+        .await
+        .unwrap();
+        let joined = db::invitations::accept_for_user(&pool, member.id, &member.email, None)
+            .await
+            .unwrap();
+        assert_eq!(joined, [(elsewhere.id, "editor".to_string())]);
+        assert_eq!(role(board.id).await.unwrap().as_deref(), Some("editor"));
+        assert!(db::invitations::list_for_board(&pool, board.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        delete_users(&pool, &[&owner, &member]).await;
+    }
+}
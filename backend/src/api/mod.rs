@@ -1,2 +1,16 @@
+pub mod access_requests;
+pub mod account;
 pub mod boards;
+pub mod directory;
+pub mod elements;
+pub mod export;
+pub mod folders;
+pub mod invitations;
+pub mod notifications;
+pub mod oidc;
+pub mod organizations;
+pub mod sessions;
+pub mod snapshots;
+pub mod tokens;
+pub mod two_factor;
 pub mod users;
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::auth;
+use crate::db;
+use crate::ws::handler::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct ListNotificationsQuery {
+    /// Only notifications not yet marked read
+    #[serde(default)]
+    pub unread: bool,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> Response {
+    tracing::error!("{} error: {}", context, e);
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &format!("{} failed", context),
+    )
+}
+
+/// The current user's in-app notifications, newest first
+pub async fn list_notifications(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListNotificationsQuery>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    match db::notifications::list_for_user(&state.pool, claims.sub, query.unread).await {
+        Ok(notifications) => Json(serde_json::to_value(notifications).unwrap()).into_response(),
+        Err(e) => internal_error("List notifications", e),
+    }
+}
+
+pub async fn mark_read(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    match db::notifications::mark_read(&state.pool, claims.sub, id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error(StatusCode::NOT_FOUND, "Notification not found"),
+        Err(e) => internal_error("Mark notification read", e),
+    }
+}
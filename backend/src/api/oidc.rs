@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::auth;
+use crate::auth::oidc::{OidcError, Provider};
+use crate::ws::handler::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Send the browser back to the app with an error to show on the login page
+fn error_redirect(state: &AppState, message: &str) -> Response {
+    let base = format!("{}/", state.config.public_url.trim_end_matches('/'));
+    match Url::parse_with_params(&base, &[("sso_error", message)]) {
+        Ok(url) => Redirect::to(url.as_str()).into_response(),
+        Err(_) => (StatusCode::BAD_REQUEST, message.to_string()).into_response(),
+    }
+}
+
+fn oidc_error_redirect(state: &AppState, error: OidcError) -> Response {
+    match error {
+        OidcError::Provider(_) | OidcError::Internal(_) => {
+            tracing::error!("Single sign-on error: {}", error);
+            error_redirect(state, "Single sign-on failed")
+        }
+        _ => error_redirect(state, &error.to_string()),
+    }
+}
+
+/// Whether single sign-on is enabled, and what to call it on the login page
+pub async fn provider(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match Provider::from_config(&state.config) {
+        Some(provider) => Json(serde_json::json!({
+            "enabled": true,
+            "name": provider.name,
+        })),
+        None => Json(serde_json::json!({ "enabled": false })),
+    }
+}
+
+/// Redirect the browser to the identity provider
+pub async fn login(State(state): State<Arc<AppState>>) -> Response {
+    match auth::oidc::begin_login(&state).await {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(OidcError::Disabled) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Single sign-on is not configured"})),
+        )
+            .into_response(),
+        Err(e) => oidc_error_redirect(&state, e),
+    }
+}
+
+/// Send the browser back to the app with values in the URL fragment, which
+/// never reaches a server log
+fn fragment_redirect(state: &AppState, fragment: &str) -> Response {
+    Redirect::to(&format!(
+        "{}/#{}",
+        state.config.public_url.trim_end_matches('/'),
+        fragment
+    ))
+    .into_response()
+}
+
+/// The provider's redirect back. Starts a session and hands its tokens to the
+/// app in the URL fragment. Users who owe a second factor get a challenge
+/// there instead, which the app completes through `/api/auth/2fa/*` as after
+/// a password login.
+pub async fn callback(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<CallbackQuery>,
+) -> Response {
+    if let Some(error) = query.error {
+        tracing::warn!(
+            "Identity provider returned {}: {}",
+            error,
+            query.error_description.as_deref().unwrap_or("")
+        );
+        return error_redirect(&state, "Sign-in was cancelled or refused");
+    }
+    let (code, login_state) = match (query.code, query.state) {
+        (Some(code), Some(login_state)) => (code, login_state),
+        _ => return error_redirect(&state, "Sign-in response is incomplete"),
+    };
+
+    let user = match auth::oidc::complete_login(&state, &code, &login_state).await {
+        Ok(user) => user,
+        Err(e) => return oidc_error_redirect(&state, e),
+    };
+
+    // A lock from failed password or code attempts holds for SSO too
+    if auth::lockout::remaining(&user).is_some() {
+        return error_redirect(
+            &state,
+            "Account temporarily locked after too many failed attempts",
+        );
+    }
+
+    if let Some(purpose) = super::two_factor::second_factor(&state, &user) {
+        return match super::two_factor::challenge(&state, &user, purpose) {
+            Ok(challenge) => fragment_redirect(
+                &state,
+                &format!(
+                    "two_factor={}&challenge_token={}&expires_in={}",
+                    challenge["two_factor"].as_str().unwrap_or_default(),
+                    challenge["challenge_token"].as_str().unwrap_or_default(),
+                    challenge["expires_in"]
+                ),
+            ),
+            Err(e) => {
+                tracing::error!("Create challenge error: {}", e);
+                error_redirect(&state, "Failed to start session")
+            }
+        };
+    }
+
+    match auth::sessions::start_session(&state, &user, super::users::user_agent(&headers)).await {
+        Ok(tokens) => fragment_redirect(
+            &state,
+            &format!(
+                "sso_token={}&refresh_token={}&expires_in={}",
+                tokens.token, tokens.refresh_token, tokens.expires_in
+            ),
+        ),
+        Err(e) => {
+            tracing::error!("Create session error: {}", e);
+            error_redirect(&state, "Failed to start session")
+        }
+    }
+}
@@ -0,0 +1,799 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::auth;
+use crate::auth::Claims;
+use crate::db;
+use crate::db::organizations::{UserOrganization, GUEST, MEMBER, OWNER, ROLES};
+use crate::ws::handler::AppState;
+use crate::ws::room::RoomEvent;
+
+const MAX_NAME_LEN: usize = 255;
+
+#[derive(Debug, Deserialize)]
+pub struct OrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddMemberRequest {
+    pub username: String,
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferBoardsRequest {
+    /// The new owner; a member of the organisation
+    pub username: String,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> Response {
+    tracing::error!("{} error: {}", context, e);
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &format!("{} failed", context),
+    )
+}
+
+fn get_claims(request: &axum::extract::Request) -> Option<Claims> {
+    auth::middleware::extract_claims(request.extensions())
+}
+
+async fn read_body<T: DeserializeOwned>(request: axum::extract::Request) -> Result<T, Response> {
+    let bytes = axum::body::to_bytes(request.into_body(), 1024 * 16)
+        .await
+        .map_err(|_| error(StatusCode::BAD_REQUEST, "Failed to read body"))?;
+    serde_json::from_slice(&bytes)
+        .map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid request body"))
+}
+
+/// The trimmed name, if it is acceptable
+fn valid_name(name: &str) -> Option<&str> {
+    let name = name.trim();
+    (!name.is_empty() && name.chars().count() <= MAX_NAME_LEN).then_some(name)
+}
+
+fn invalid_name() -> Response {
+    error(
+        StatusCode::BAD_REQUEST,
+        &format!("Name must be 1-{} characters", MAX_NAME_LEN),
+    )
+}
+
+/// The organisation as the caller sees it. Outsiders get a 404 so they
+/// cannot probe which organisations exist.
+async fn membership(
+    state: &AppState,
+    org_id: Uuid,
+    user_id: Uuid,
+) -> Result<UserOrganization, Response> {
+    match db::organizations::get_for_user(&state.pool, org_id, user_id).await {
+        Ok(Some(org)) => Ok(org),
+        Ok(None) => Err(error(StatusCode::NOT_FOUND, "Organization not found")),
+        Err(e) => Err(internal_error("Organization", e)),
+    }
+}
+
+/// Like `membership`, but only for owners and admins
+async fn manager(
+    state: &AppState,
+    org_id: Uuid,
+    user_id: Uuid,
+) -> Result<UserOrganization, Response> {
+    let org = membership(state, org_id, user_id).await?;
+    if !db::organizations::can_manage(&org.role) {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "Only owners and admins can manage the organization",
+        ));
+    }
+    Ok(org)
+}
+
+fn invalid_role() -> Response {
+    error(
+        StatusCode::BAD_REQUEST,
+        "Role must be owner, admin, member or guest",
+    )
+}
+
+/// Push the current role of everyone connected to these boards, after a
+/// change to organisation membership may have altered what they inherit.
+/// With `only`, just that user is re-checked.
+pub(crate) async fn refresh_access(state: &AppState, board_ids: &[Uuid], only: Option<Uuid>) {
+    for board_id in board_ids {
+        let room = match state.room_manager.get_room(board_id).await {
+            Some(room) => room,
+            None => continue,
+        };
+        for user in room.get_users().await {
+            if only.is_some_and(|id| id != user.user_id) {
+                continue;
+            }
+            let event =
+                match db::boards::user_has_access(&state.pool, *board_id, user.user_id).await {
+                    Ok(Some(role)) => RoomEvent::RoleChanged {
+                        user_id: user.user_id,
+                        role,
+                    },
+                    Ok(None) => RoomEvent::AccessRevoked {
+                        user_id: user.user_id,
+                    },
+                    Err(e) => {
+                        tracing::error!("Refresh access error: {}", e);
+                        continue;
+                    }
+                };
+            state.room_manager.notify(board_id, event).await;
+        }
+    }
+}
+
+async fn refresh_member(state: &AppState, org_id: Uuid, user_id: Uuid) {
+    match db::organizations::list_board_ids(&state.pool, org_id).await {
+        Ok(board_ids) => refresh_access(state, &board_ids, Some(user_id)).await,
+        Err(e) => tracing::error!("List organization boards error: {}", e),
+    }
+}
+
+/// Whether taking the owner role away from `user_id` would leave the
+/// organisation without one
+async fn is_last_owner(state: &AppState, org_id: Uuid, role: &str) -> Result<bool, Response> {
+    if role != OWNER {
+        return Ok(false);
+    }
+    match db::organizations::count_owners(&state.pool, org_id).await {
+        Ok(count) => Ok(count <= 1),
+        Err(e) => Err(internal_error("Organization", e)),
+    }
+}
+
+pub async fn list_organizations(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    match db::organizations::list_for_user(&state.pool, claims.sub).await {
+        Ok(orgs) => Json(serde_json::to_value(orgs).unwrap()).into_response(),
+        Err(e) => internal_error("List organizations", e),
+    }
+}
+
+/// Create an organisation owned by the caller
+pub async fn create_organization(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let body: OrganizationRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let name = match valid_name(&body.name) {
+        Some(name) => name,
+        None => return invalid_name(),
+    };
+
+    match db::organizations::create_organization(&state.pool, name, claims.sub).await {
+        Ok(org) => {
+            let org = UserOrganization {
+                id: org.id,
+                name: org.name,
+                role: OWNER.to_string(),
+                created_at: org.created_at,
+                updated_at: org.updated_at,
+            };
+            (
+                StatusCode::CREATED,
+                Json(serde_json::to_value(org).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => internal_error("Create organization", e),
+    }
+}
+
+pub async fn get_organization(
+    State(state): State<Arc<AppState>>,
+    Path(org_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    match membership(&state, org_id, claims.sub).await {
+        Ok(org) => Json(serde_json::to_value(org).unwrap()).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Rename an organisation
+pub async fn update_organization(
+    State(state): State<Arc<AppState>>,
+    Path(org_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let current = match manager(&state, org_id, claims.sub).await {
+        Ok(org) => org,
+        Err(response) => return response,
+    };
+    let body: OrganizationRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let name = match valid_name(&body.name) {
+        Some(name) => name,
+        None => return invalid_name(),
+    };
+
+    match db::organizations::update_name(&state.pool, org_id, name).await {
+        Ok(Some(org)) => {
+            let org = UserOrganization {
+                id: org.id,
+                name: org.name,
+                role: current.role,
+                created_at: org.created_at,
+                updated_at: org.updated_at,
+            };
+            Json(serde_json::to_value(org).unwrap()).into_response()
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, "Organization not found"),
+        Err(e) => internal_error("Update organization", e),
+    }
+}
+
+/// Delete an organisation. Only owners may; its boards go back to being
+/// shared only with their collaborators.
+pub async fn delete_organization(
+    State(state): State<Arc<AppState>>,
+    Path(org_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    match membership(&state, org_id, claims.sub).await {
+        Ok(org) if org.role == OWNER => {}
+        Ok(_) => {
+            return error(
+                StatusCode::FORBIDDEN,
+                "Only owners can delete the organization",
+            )
+        }
+        Err(response) => return response,
+    }
+
+    let board_ids = match db::organizations::list_board_ids(&state.pool, org_id).await {
+        Ok(ids) => ids,
+        Err(e) => return internal_error("Delete organization", e),
+    };
+    match db::organizations::delete_organization(&state.pool, org_id).await {
+        Ok(true) => {
+            refresh_access(&state, &board_ids, None).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => error(StatusCode::NOT_FOUND, "Organization not found"),
+        Err(e) => internal_error("Delete organization", e),
+    }
+}
+
+pub async fn list_members(
+    State(state): State<Arc<AppState>>,
+    Path(org_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Err(response) = membership(&state, org_id, claims.sub).await {
+        return response;
+    }
+    match db::organizations::list_members(&state.pool, org_id).await {
+        Ok(members) => Json(serde_json::to_value(members).unwrap()).into_response(),
+        Err(e) => internal_error("List members", e),
+    }
+}
+
+/// Add a member by username. Only owners can make someone an owner.
+pub async fn add_member(
+    State(state): State<Arc<AppState>>,
+    Path(org_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let org = match manager(&state, org_id, claims.sub).await {
+        Ok(org) => org,
+        Err(response) => return response,
+    };
+    let body: AddMemberRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let role = body.role.unwrap_or_else(|| MEMBER.to_string());
+    if !ROLES.contains(&role.as_str()) {
+        return invalid_role();
+    }
+    if role == OWNER && org.role != OWNER {
+        return error(StatusCode::FORBIDDEN, "Only owners can add owners");
+    }
+
+    let user = match db::users::find_by_username(&state.pool, &body.username).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "User not found"),
+        Err(e) => return internal_error("Find user", e),
+    };
+    match db::organizations::get_role(&state.pool, org_id, user.id).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return error(StatusCode::CONFLICT, "User is already a member"),
+        Err(e) => return internal_error("Add member", e),
+    }
+
+    if let Err(e) = db::organizations::set_member(&state.pool, org_id, user.id, &role).await {
+        return internal_error("Add member", e);
+    }
+    refresh_member(&state, org_id, user.id).await;
+    (
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "user_id": user.id,
+            "username": user.username,
+            "role": role,
+        })),
+    )
+        .into_response()
+}
+
+/// Change a member's role. Owners are managed by owners only, and the last
+/// owner cannot step down.
+pub async fn update_member(
+    State(state): State<Arc<AppState>>,
+    Path((org_id, user_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let org = match manager(&state, org_id, claims.sub).await {
+        Ok(org) => org,
+        Err(response) => return response,
+    };
+    let body: UpdateMemberRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    if !ROLES.contains(&body.role.as_str()) {
+        return invalid_role();
+    }
+
+    let current = match db::organizations::get_role(&state.pool, org_id, user_id).await {
+        Ok(Some(role)) => role,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Member not found"),
+        Err(e) => return internal_error("Update member", e),
+    };
+    if (current == OWNER || body.role == OWNER) && org.role != OWNER {
+        return error(StatusCode::FORBIDDEN, "Only owners can manage owners");
+    }
+    if body.role != OWNER {
+        match is_last_owner(&state, org_id, &current).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return error(
+                    StatusCode::CONFLICT,
+                    "The organization needs at least one owner",
+                )
+            }
+            Err(response) => return response,
+        }
+    }
+
+    if let Err(e) = db::organizations::set_member(&state.pool, org_id, user_id, &body.role).await {
+        return internal_error("Update member", e);
+    }
+    refresh_member(&state, org_id, user_id).await;
+    Json(serde_json::json!({ "user_id": user_id, "role": body.role })).into_response()
+}
+
+/// Remove a member. Anyone may leave; owners and admins may remove others,
+/// but only owners remove owners, and the last owner cannot go.
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    Path((org_id, user_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    let org = match membership(&state, org_id, claims.sub).await {
+        Ok(org) => org,
+        Err(response) => return response,
+    };
+    let leaving = user_id == claims.sub;
+    if !leaving && !db::organizations::can_manage(&org.role) {
+        return error(
+            StatusCode::FORBIDDEN,
+            "Only owners and admins can manage the organization",
+        );
+    }
+
+    let current = match db::organizations::get_role(&state.pool, org_id, user_id).await {
+        Ok(Some(role)) => role,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Member not found"),
+        Err(e) => return internal_error("Remove member", e),
+    };
+    if !leaving && current == OWNER && org.role != OWNER {
+        return error(StatusCode::FORBIDDEN, "Only owners can manage owners");
+    }
+    match is_last_owner(&state, org_id, &current).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return error(
+                StatusCode::CONFLICT,
+                "The organization needs at least one owner",
+            )
+        }
+        Err(response) => return response,
+    }
+
+    match db::organizations::remove_member(&state.pool, org_id, user_id).await {
+        Ok(true) => {
+            refresh_member(&state, org_id, user_id).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => error(StatusCode::NOT_FOUND, "Member not found"),
+        Err(e) => internal_error("Remove member", e),
+    }
+}
+
+/// Hand all of a user's boards in the organisation to another member, for
+/// when someone leaves. They stay on each board as an admin until removed.
+/// The user need not still be a member.
+pub async fn transfer_boards(
+    State(state): State<Arc<AppState>>,
+    Path((org_id, user_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match get_claims(&request) {
+        Some(c) => c,
+        None => return error(StatusCode::UNAUTHORIZED, "Not authenticated"),
+    };
+    if let Err(response) = manager(&state, org_id, claims.sub).await {
+        return response;
+    }
+    let body: TransferBoardsRequest = match read_body(request).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+
+    let new_owner = match db::users::find_by_username(&state.pool, &body.username).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "User not found"),
+        Err(e) => return internal_error("Find user", e),
+    };
+    if new_owner.id == user_id {
+        return error(StatusCode::BAD_REQUEST, "Boards must go to someone else");
+    }
+    match can_add_boards(&state, org_id, new_owner.id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "The new owner must be a member of the organization",
+            )
+        }
+        Err(e) => return internal_error("Transfer boards", e),
+    }
+
+    match db::boards::transfer_org_boards(&state.pool, org_id, user_id, new_owner.id).await {
+        Ok(board_ids) => {
+            refresh_access(&state, &board_ids, None).await;
+            Json(serde_json::json!({
+                "owner_id": new_owner.id,
+                "transferred_boards": board_ids,
+            }))
+            .into_response()
+        }
+        Err(e) => internal_error("Transfer boards", e),
+    }
+}
+
+/// Whether the user may put boards in the organisation. Guests may not.
+pub(crate) async fn can_add_boards(
+    state: &AppState,
+    org_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<bool> {
+    let role = db::organizations::get_role(&state.pool, org_id, user_id).await?;
+    Ok(role.is_some_and(|role| role != GUEST))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state, token_query,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_organization_members_inherit_board_access() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "org_owner").await;
+        let member = create_user(&pool, "org_member").await;
+        let guest = create_user(&pool, "org_guest").await;
+
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let jwt = |query: String| query["token=".len()..].to_string();
+        let owner_jwt = jwt(token_query(&pool, &owner).await);
+        let member_jwt = jwt(token_query(&pool, &member).await);
+        let guest_jwt = jwt(token_query(&pool, &guest).await);
+        let url = |path: String| format!("http://{}{}", addr, path);
+
+        let org: Value = client
+            .post(url("/api/orgs".into()))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({"name": "Exhibitions"}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(org["role"], "owner");
+        let org_id = org["id"].as_str().unwrap().to_string();
+
+        for (user, role) in [(&member, "member"), (&guest, "guest")] {
+            let response = client
+                .post(url(format!("/api/orgs/{}/members", org_id)))
+                .bearer_auth(&owner_jwt)
+                .json(&json!({"username": user.username, "role": role}))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 201);
+        }
+
+        // Members cannot manage the organisation, and guests cannot add boards to it
+        let response = client
+            .post(url(format!("/api/orgs/{}/members", org_id)))
+            .bearer_auth(&member_jwt)
+            .json(&json!({"username": guest.username, "role": "admin"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let response = client
+            .post(url("/api/boards".into()))
+            .bearer_auth(&guest_jwt)
+            .json(&json!({"name": "Sneaky", "org_id": org_id}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+
+        let board: Value = client
+            .post(url("/api/boards".into()))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({"name": "Stand plan", "org_id": org_id}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(board["org_id"], org_id.as_str());
+        let board_id: Uuid = board["id"].as_str().unwrap().parse().unwrap();
+
+        let role = |user_id| db::boards::user_has_access(&pool, board_id, user_id);
+        assert_eq!(role(member.id).await.unwrap().as_deref(), Some("editor"));
+        assert_eq!(role(guest.id).await.unwrap(), None);
+        let boards: Value = client
+            .get(url("/api/boards".into()))
+            .bearer_auth(&member_jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(boards
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|b| b["id"] == board_id.to_string()));
+
+        // A direct invitation still counts when it is the stronger role
+        db::boards::add_collaborator(&pool, board_id, member.id, "admin")
+            .await
+            .unwrap();
+        assert_eq!(role(member.id).await.unwrap().as_deref(), Some("admin"));
+        db::boards::remove_collaborator(&pool, board_id, member.id)
+            .await
+            .unwrap();
+
+        // The last owner can neither step down nor leave
+        let response = client
+            .patch(url(format!("/api/orgs/{}/members/{}", org_id, owner.id)))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({"role": "admin"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 409);
+        let response = client
+            .delete(url(format!("/api/orgs/{}/members/{}", org_id, owner.id)))
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 409);
+
+        let response = client
+            .patch(url(format!("/api/orgs/{}/members/{}", org_id, member.id)))
+            .bearer_auth(&owner_jwt)
+            .json(&json!({"role": "guest"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(role(member.id).await.unwrap(), None);
+
+        let members: Value = client
+            .get(url(format!("/api/orgs/{}/members", org_id)))
+            .bearer_auth(&guest_jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(members.as_array().unwrap().len(), 3);
+
+        let response = client
+            .delete(url(format!("/api/orgs/{}", org_id)))
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+        let board = db::boards::get_board(&pool, board_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(board.org_id, None);
+        let response = client
+            .get(url(format!("/api/orgs/{}", org_id)))
+            .bearer_auth(&owner_jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        delete_users(&pool, &[&owner, &member, &guest]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_transfer_boards_of_departing_member() {
+        let pool = test_pool().await;
+        let admin = create_user(&pool, "leave_admin").await;
+        let leaver = create_user(&pool, "leave_leaver").await;
+        let heir = create_user(&pool, "leave_heir").await;
+        let org = db::organizations::create_organization(&pool, "Fairs", admin.id)
+            .await
+            .unwrap();
+        db::organizations::set_member(&pool, org.id, heir.id, MEMBER)
+            .await
+            .unwrap();
+        let in_org = db::boards::create_board(&pool, "Org board", leaver.id, Some(org.id))
+            .await
+            .unwrap();
+        let folder = db::folders::create_folder(&pool, "Stands", None, None, Some(org.id))
+            .await
+            .unwrap();
+        let filed = db::boards::create_board(&pool, "Filed", leaver.id, None)
+            .await
+            .unwrap();
+        db::boards::set_board_folder(&pool, filed.id, Some(folder.id))
+            .await
+            .unwrap();
+        let private = db::boards::create_board(&pool, "Private", leaver.id, None)
+            .await
+            .unwrap();
+
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+        let admin_jwt = token_query(&pool, &admin).await["token=".len()..].to_string();
+        let transfer = |username: &str| {
+            client
+                .post(format!(
+                    "http://{}/api/orgs/{}/members/{}/transfer",
+                    addr, org.id, leaver.id
+                ))
+                .bearer_auth(&admin_jwt)
+                .json(&json!({ "username": username }))
+                .send()
+        };
+
+        // The leaver already left the organisation; only members can inherit
+        assert_eq!(transfer(&leaver.username).await.unwrap().status(), 400);
+        let result: Value = transfer(&heir.username)
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(result["transferred_boards"].as_array().unwrap().len(), 2);
+
+        for board_id in [in_org.id, filed.id] {
+            let board = db::boards::get_board(&pool, board_id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(board.owner_id, heir.id);
+            assert_eq!(
+                db::boards::user_has_access(&pool, board_id, leaver.id)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some("admin")
+            );
+        }
+        let private = db::boards::get_board(&pool, private.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(private.owner_id, leaver.id);
+
+        db::organizations::delete_organization(&pool, org.id)
+            .await
+            .unwrap();
+        delete_users(&pool, &[&admin, &leaver, &heir]).await;
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::auth;
+use crate::db;
+use crate::ws::handler::AppState;
+
+/// List the signed-in user's active sessions, marking the current one
+pub async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::sessions::list_sessions(&state.pool, claims.sub).await {
+        Ok(sessions) => {
+            let sessions: Vec<serde_json::Value> = sessions
+                .into_iter()
+                .map(|s| {
+                    let current = s.id == claims.sid;
+                    let mut value =
+                        serde_json::to_value(db::sessions::SessionSummary::from(s)).unwrap();
+                    value["current"] = current.into();
+                    value
+                })
+                .collect();
+            Json(serde_json::json!(sessions)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("List sessions error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to list sessions"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Revoke one of the signed-in user's sessions, signing that device out
+pub async fn delete_session(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::sessions::revoke_session(&state.pool, claims.sub, session_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Session not found"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Revoke session error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to revoke session"})),
+            )
+                .into_response()
+        }
+    }
+}
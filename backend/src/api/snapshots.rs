@@ -0,0 +1,311 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::elements::load_doc;
+use crate::auth;
+use crate::db;
+use crate::model::Element;
+use crate::ws::handler::AppState;
+use crate::ws::{snapshots, sync};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnapshotRequest {
+    pub name: String,
+}
+
+pub async fn create_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(Some(role)) if role != "viewer" => {}
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "Not authorized to create snapshots"})),
+            )
+                .into_response()
+        }
+    }
+
+    let body: CreateSnapshotRequest =
+        match axum::body::to_bytes(request.into_body(), 1024 * 16).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(b) => b,
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({"error": "Invalid request body"})),
+                    )
+                        .into_response()
+                }
+            },
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": "Failed to read body"})),
+                )
+                    .into_response()
+            }
+        };
+
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > 255 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Snapshot name must be 1-255 characters"})),
+        )
+            .into_response();
+    }
+
+    let doc = match load_doc(&state, board_id).await {
+        Ok(doc) => doc,
+        Err(response) => return response,
+    };
+    let state_bytes = doc.read(sync::encode_doc_state).await;
+
+    match db::snapshots::create_snapshot(
+        &state.pool,
+        board_id,
+        Some(name),
+        db::snapshots::KIND_MANUAL,
+        &state_bytes,
+        Some(claims.sub),
+    )
+    .await
+    {
+        Ok(snapshot) => {
+            let summary: db::snapshots::SnapshotSummary = snapshot.into();
+            (
+                StatusCode::CREATED,
+                Json(serde_json::to_value(summary).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Create snapshot error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to create snapshot"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn list_snapshots(
+    State(state): State<Arc<AppState>>,
+    Path(board_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(Some(_)) => {}
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "No access to this board"})),
+            )
+                .into_response()
+        }
+    }
+
+    match db::snapshots::list_snapshots(&state.pool, board_id).await {
+        Ok(snapshots) => Json(serde_json::to_value(snapshots).unwrap()).into_response(),
+        Err(e) => {
+            tracing::error!("List snapshots error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to list snapshots"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Preview a snapshot: its metadata plus the elements it contains
+pub async fn get_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path((board_id, snapshot_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(Some(_)) => {}
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "No access to this board"})),
+            )
+                .into_response()
+        }
+    }
+
+    let snapshot = match db::snapshots::get_snapshot(&state.pool, board_id, snapshot_id).await {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "Snapshot not found"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Get snapshot error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to get snapshot"})),
+            )
+                .into_response();
+        }
+    };
+
+    let elements = match snapshots::snapshot_elements(&snapshot.yrs_state) {
+        Ok(elements) => elements,
+        Err(e) => {
+            tracing::error!("Decode snapshot error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to decode snapshot"})),
+            )
+                .into_response();
+        }
+    };
+
+    let summary: db::snapshots::SnapshotSummary = snapshot.into();
+    let mut preview = serde_json::to_value(summary).unwrap();
+    preview["elements"] = elements.iter().map(Element::to_json).collect();
+    Json(preview).into_response()
+}
+
+/// Restore a snapshot. The current state is snapshotted first so the restore can be undone.
+pub async fn restore_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path((board_id, snapshot_id)): Path<(Uuid, Uuid)>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+        Ok(Some(role)) if role == "owner" || role == "admin" => {}
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "Only owner/admin can restore snapshots"})),
+            )
+                .into_response()
+        }
+    }
+
+    let snapshot = match db::snapshots::get_snapshot(&state.pool, board_id, snapshot_id).await {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "Snapshot not found"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Get snapshot error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to get snapshot"})),
+            )
+                .into_response();
+        }
+    };
+
+    let doc = match load_doc(&state, board_id).await {
+        Ok(doc) => doc,
+        Err(response) => return response,
+    };
+
+    let current_state = doc.read(sync::encode_doc_state).await;
+    if let Err(e) = db::snapshots::create_snapshot(
+        &state.pool,
+        board_id,
+        Some("Before restore"),
+        db::snapshots::KIND_AUTO,
+        &current_state,
+        Some(claims.sub),
+    )
+    .await
+    {
+        tracing::error!("Pre-restore snapshot error: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": "Failed to restore snapshot"})),
+        )
+            .into_response();
+    }
+
+    let result = doc
+        .read(|d| snapshots::restore_snapshot(d, &snapshot.yrs_state))
+        .await;
+    let committed = match result {
+        Ok(Some((update, messages))) => doc.commit(&state, board_id, &update, &messages).await,
+        Ok(None) => Ok(()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = committed {
+        tracing::error!("Restore snapshot error: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": "Failed to restore snapshot"})),
+        )
+            .into_response();
+    }
+
+    let elements = doc.read(crate::ws::elements::get_elements).await;
+    Json(serde_json::json!({"restored": snapshot_id, "elements": elements})).into_response()
+}
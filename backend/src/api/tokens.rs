@@ -0,0 +1,361 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::auth;
+use crate::auth::api_tokens::Scope;
+use crate::db;
+use crate::ws::handler::AppState;
+
+/// The longest lifetime a token can be created with
+const MAX_EXPIRES_IN_DAYS: i64 = 365;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub board_id: Option<Uuid>,
+    pub expires_in_days: Option<i64>,
+}
+
+/// List the signed-in user's personal access tokens
+pub async fn list_tokens(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::api_tokens::list_tokens(&state.pool, claims.sub).await {
+        Ok(tokens) => {
+            let tokens: Vec<db::api_tokens::ApiTokenSummary> =
+                tokens.into_iter().map(Into::into).collect();
+            Json(serde_json::to_value(tokens).unwrap()).into_response()
+        }
+        Err(e) => {
+            tracing::error!("List tokens error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to list tokens"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Create a personal access token. The token itself is only shown in this response.
+pub async fn create_token(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    let body: CreateTokenRequest = match axum::body::to_bytes(request.into_body(), 1024 * 16).await
+    {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(b) => b,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": "Invalid request body"})),
+                )
+                    .into_response()
+            }
+        },
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "Failed to read body"})),
+            )
+                .into_response()
+        }
+    };
+
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Token name must be 1-100 characters"})),
+        )
+            .into_response();
+    }
+
+    let mut scopes = Vec::new();
+    for scope in &body.scopes {
+        match Scope::parse(scope) {
+            Some(scope) if !scopes.contains(&scope) => scopes.push(scope),
+            Some(_) => {}
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": format!("Unknown scope: {}", scope)})),
+                )
+                    .into_response()
+            }
+        }
+    }
+    if scopes.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "A token needs at least one scope"})),
+        )
+            .into_response();
+    }
+
+    let expires_at = match body.expires_in_days {
+        Some(days) if !(1..=MAX_EXPIRES_IN_DAYS).contains(&days) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": format!("expires_in_days must be 1-{}", MAX_EXPIRES_IN_DAYS)
+                })),
+            )
+                .into_response()
+        }
+        Some(days) => Some(Utc::now() + Duration::days(days)),
+        None => None,
+    };
+
+    if let Some(board_id) = body.board_id {
+        match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+            Ok(Some(_)) => {}
+            _ => {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(serde_json::json!({"error": "No access to this board"})),
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    let token = auth::api_tokens::generate_api_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_string()).collect();
+    match db::api_tokens::create_token(
+        &state.pool,
+        claims.sub,
+        name,
+        &auth::hash_secret_token(&token),
+        &scopes,
+        body.board_id,
+        expires_at,
+    )
+    .await
+    {
+        Ok(created) => {
+            let summary: db::api_tokens::ApiTokenSummary = created.into();
+            let mut value = serde_json::to_value(summary).unwrap();
+            value["token"] = token.into();
+            (StatusCode::CREATED, Json(value)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Create token error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to create token"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Revoke one of the signed-in user's personal access tokens
+pub async fn delete_token(
+    State(state): State<Arc<AppState>>,
+    Path(token_id): Path<Uuid>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::api_tokens::revoke_token(&state.pool, claims.sub, token_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Token not found"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Revoke token error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to revoke token"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use reqwest::Method;
+
+    use super::*;
+    use crate::ws::test_support::{
+        create_user, delete_users, listener, test_pool, test_state, token_query,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_token_scopes_and_board_restriction() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "pat").await;
+        let board = db::boards::create_board(&pool, "Synced", owner.id, None)
+            .await
+            .unwrap();
+        let other = db::boards::create_board(&pool, "Other", owner.id, None)
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let client = reqwest::Client::new();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+        let jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+
+        let created: serde_json::Value = client
+            .post(url("/api/me/tokens"))
+            .bearer_auth(&jwt)
+            .json(&serde_json::json!({
+                "name": "sync script",
+                "scopes": ["boards:read"],
+                "board_id": board.id,
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let token = created["token"].as_str().unwrap().to_string();
+        assert_eq!(created["scopes"], serde_json::json!(["boards:read"]));
+
+        let status = |path: String, method: reqwest::Method| {
+            let request = client.request(method, url(&path)).bearer_auth(&token);
+            async move { request.send().await.unwrap().status().as_u16() }
+        };
+        assert_eq!(
+            status(format!("/api/boards/{}", board.id), Method::GET).await,
+            200
+        );
+        assert_eq!(
+            status(format!("/api/boards/{}/elements", board.id), Method::GET).await,
+            200
+        );
+        // Missing scope, another board, and routes not about one board
+        assert_eq!(
+            status(format!("/api/boards/{}", board.id), Method::DELETE).await,
+            403
+        );
+        assert_eq!(
+            status(format!("/api/boards/{}", other.id), Method::GET).await,
+            403
+        );
+        assert_eq!(status("/api/boards".to_string(), Method::GET).await, 403);
+        assert_eq!(status("/api/me/tokens".to_string(), Method::GET).await, 403);
+
+        let listed: serde_json::Value = client
+            .get(url("/api/me/tokens"))
+            .bearer_auth(&jwt)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert!(listed[0].get("token").is_none());
+        assert!(listed[0].get("token_hash").is_none());
+
+        let revoked = client
+            .delete(url(&format!(
+                "/api/me/tokens/{}",
+                created["id"].as_str().unwrap()
+            )))
+            .bearer_auth(&jwt)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(revoked.status().as_u16(), 204);
+        assert_eq!(
+            status(format!("/api/boards/{}", board.id), Method::GET).await,
+            401
+        );
+
+        delete_users(&pool, &[&owner]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_create_token_rejects_unknown_scope() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "pat_scope").await;
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = crate::protected_routes(state.clone()).with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let jwt = token_query(&pool, &owner).await["token=".len()..].to_string();
+
+        for scopes in [serde_json::json!(["boards:delete"]), serde_json::json!([])] {
+            let response = reqwest::Client::new()
+                .post(format!("http://{}/api/me/tokens", addr))
+                .bearer_auth(&jwt)
+                .json(&serde_json::json!({ "name": "bad", "scopes": scopes }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status().as_u16(), 400);
+        }
+        for days in [0, MAX_EXPIRES_IN_DAYS + 1, i64::MAX] {
+            let response = reqwest::Client::new()
+                .post(format!("http://{}/api/me/tokens", addr))
+                .bearer_auth(&jwt)
+                .json(&serde_json::json!({
+                    "name": "long", "scopes": ["boards:read"], "expires_in_days": days,
+                }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status().as_u16(), 400, "{} days", days);
+        }
+
+        delete_users(&pool, &[&owner]).await;
+    }
+}
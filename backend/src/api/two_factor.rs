@@ -0,0 +1,425 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+
+use super::users::{failed_login, locked_response, session_response, user_agent, AuthResponse};
+use crate::auth;
+use crate::auth::two_factor::{CHALLENGE_SETUP, CHALLENGE_VERIFY};
+use crate::db;
+use crate::ws::handler::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct ChallengeRequest {
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChallengeCodeRequest {
+    pub challenge_token: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CodeRequest {
+    pub code: String,
+}
+
+/// The second login step the user owes after a first factor, if any
+pub(crate) fn second_factor(state: &AppState, user: &db::users::User) -> Option<&'static str> {
+    if user.is_two_factor_enabled() {
+        Some(CHALLENGE_VERIFY)
+    } else if state.config.require_two_factor {
+        Some(CHALLENGE_SETUP)
+    } else {
+        None
+    }
+}
+
+/// A challenge for the second login step, with the fields the app expects
+pub(crate) fn challenge(
+    state: &AppState,
+    user: &db::users::User,
+    purpose: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let challenge_token = auth::two_factor::create_challenge(user.id, purpose, &state.jwt_secret)?;
+    Ok(serde_json::json!({
+        "two_factor": if purpose == CHALLENGE_SETUP { "setup" } else { "verify" },
+        "challenge_token": challenge_token,
+        "expires_in": auth::two_factor::challenge_seconds(),
+    }))
+}
+
+/// Answer a correct password with a challenge instead of a session
+pub(crate) fn challenge_response(
+    state: &AppState,
+    user: &db::users::User,
+    purpose: &str,
+    status: StatusCode,
+) -> Response {
+    match challenge(state, user, purpose) {
+        Ok(body) => (status, Json(body)).into_response(),
+        Err(e) => {
+            tracing::error!("Create challenge error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Login failed"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn invalid_challenge() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({"error": "Login has expired, please sign in again"})),
+    )
+        .into_response()
+}
+
+fn invalid_code() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({"error": "Invalid code"})),
+    )
+        .into_response()
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> Response {
+    tracing::error!("{} error: {}", context, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": format!("{} failed", context)})),
+    )
+        .into_response()
+}
+
+async fn find_user(state: &AppState, user_id: uuid::Uuid) -> Result<db::users::User, Response> {
+    match db::users::find_by_id(&state.pool, user_id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(invalid_challenge()),
+        Err(e) => Err(internal_error("Two-factor", e)),
+    }
+}
+
+/// Second login step: a code from the authenticator app or a recovery code
+pub async fn verify(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<ChallengeCodeRequest>,
+) -> Response {
+    let user_id = match auth::two_factor::verify_challenge(
+        &req.challenge_token,
+        CHALLENGE_VERIFY,
+        &state.jwt_secret,
+    ) {
+        Some(id) => id,
+        None => return invalid_challenge(),
+    };
+
+    let user = match find_user(&state, user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if let Some(remaining) = auth::lockout::remaining(&user) {
+        return locked_response(remaining);
+    }
+
+    match auth::two_factor::verify_code(&state, user_id, &req.code).await {
+        Ok(true) => {
+            if let Err(e) = auth::lockout::clear(&state, user_id).await {
+                tracing::error!("Clear failed logins error: {}", e);
+            }
+            session_response(&state, user, &headers, StatusCode::OK).await
+        }
+        Ok(false) => failed_login(&state, user_id, "Invalid code").await,
+        Err(e) => internal_error("Two-factor verification", e),
+    }
+}
+
+/// Enrolment during login, when 2FA is required and the user has none yet
+pub async fn login_setup(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ChallengeRequest>,
+) -> Response {
+    let user_id = match auth::two_factor::verify_challenge(
+        &req.challenge_token,
+        CHALLENGE_SETUP,
+        &state.jwt_secret,
+    ) {
+        Some(id) => id,
+        None => return invalid_challenge(),
+    };
+    let user = match find_user(&state, user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    setup_response(&state, &user).await
+}
+
+/// Finish enrolment during login and start the session
+pub async fn login_setup_confirm(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<ChallengeCodeRequest>,
+) -> Response {
+    let user_id = match auth::two_factor::verify_challenge(
+        &req.challenge_token,
+        CHALLENGE_SETUP,
+        &state.jwt_secret,
+    ) {
+        Some(id) => id,
+        None => return invalid_challenge(),
+    };
+
+    let recovery_codes = match auth::two_factor::confirm_setup(&state, user_id, &req.code).await {
+        Ok(Some(codes)) => codes,
+        Ok(None) => return invalid_code(),
+        Err(e) => return internal_error("Two-factor setup", e),
+    };
+    let user = match find_user(&state, user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    match auth::sessions::start_session(&state, &user, user_agent(&headers)).await {
+        Ok(tokens) => {
+            let response = AuthResponse {
+                tokens,
+                user: user.into(),
+            };
+            let mut value = serde_json::to_value(response).unwrap();
+            value["recovery_codes"] = recovery_codes.into();
+            Json(value).into_response()
+        }
+        Err(e) => internal_error("Start session", e),
+    }
+}
+
+async fn setup_response(state: &AppState, user: &db::users::User) -> Response {
+    match auth::two_factor::begin_setup(state, user).await {
+        Ok(Some(setup)) => Json(serde_json::to_value(setup).unwrap()).into_response(),
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": "Two-factor authentication is already enabled"})),
+        )
+            .into_response(),
+        Err(e) => internal_error("Two-factor setup", e),
+    }
+}
+
+/// Read `{ "code": ... }` from a request whose extensions were already used
+async fn read_code(request: axum::extract::Request) -> Result<String, Response> {
+    let bytes = axum::body::to_bytes(request.into_body(), 1024 * 16)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "Failed to read body"})),
+            )
+                .into_response()
+        })?;
+    serde_json::from_slice::<CodeRequest>(&bytes)
+        .map(|req| req.code)
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "Invalid request body"})),
+            )
+                .into_response()
+        })
+}
+
+fn not_authenticated() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({"error": "Not authenticated"})),
+    )
+        .into_response()
+}
+
+/// Whether the signed-in user has 2FA, and how many recovery codes are left
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return not_authenticated(),
+    };
+    let user = match db::users::find_by_id(&state.pool, claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return not_authenticated(),
+        Err(e) => return internal_error("Two-factor status", e),
+    };
+    match db::two_factor::count_recovery_codes(&state.pool, user.id).await {
+        Ok(remaining) => Json(serde_json::json!({
+            "enabled": user.is_two_factor_enabled(),
+            "required": state.config.require_two_factor,
+            "recovery_codes_remaining": remaining,
+        }))
+        .into_response(),
+        Err(e) => internal_error("Two-factor status", e),
+    }
+}
+
+/// Start enrolment for the signed-in user
+pub async fn setup(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return not_authenticated(),
+    };
+    match db::users::find_by_id(&state.pool, claims.sub).await {
+        Ok(Some(user)) => setup_response(&state, &user).await,
+        Ok(None) => not_authenticated(),
+        Err(e) => internal_error("Two-factor setup", e),
+    }
+}
+
+/// Confirm enrolment with a first code; answers with the recovery codes
+pub async fn confirm(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return not_authenticated(),
+    };
+    let code = match read_code(request).await {
+        Ok(code) => code,
+        Err(response) => return response,
+    };
+    match auth::two_factor::confirm_setup(&state, claims.sub, &code).await {
+        Ok(Some(codes)) => Json(serde_json::json!({ "recovery_codes": codes })).into_response(),
+        Ok(None) => invalid_code(),
+        Err(e) => internal_error("Two-factor setup", e),
+    }
+}
+
+/// Turn 2FA off, unless it is required for everyone
+pub async fn disable(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return not_authenticated(),
+    };
+    if state.config.require_two_factor {
+        return (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": "Two-factor authentication is required"})),
+        )
+            .into_response();
+    }
+    let code = match read_code(request).await {
+        Ok(code) => code,
+        Err(response) => return response,
+    };
+    match auth::two_factor::disable(&state, claims.sub, &code).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => invalid_code(),
+        Err(e) => internal_error("Disable two-factor", e),
+    }
+}
+
+/// Replace the recovery codes, invalidating the old ones
+pub async fn regenerate_recovery_codes(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> Response {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => return not_authenticated(),
+    };
+    let code = match read_code(request).await {
+        Ok(code) => code,
+        Err(response) => return response,
+    };
+    match auth::two_factor::regenerate_recovery_codes(&state, claims.sub, &code).await {
+        Ok(Some(codes)) => Json(serde_json::json!({ "recovery_codes": codes })).into_response(),
+        Ok(None) => invalid_code(),
+        Err(e) => internal_error("Recovery codes", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use axum::{routing::post, Router};
+
+    use super::*;
+    use crate::ws::test_support::{create_user, delete_users, listener, test_pool, test_state};
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_login_needs_second_step_when_enabled() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "login2fa").await;
+        db::users::update_password(&pool, user.id, &bcrypt::hash("hunter22", 4).unwrap())
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let setup = auth::two_factor::begin_setup(&state, &user)
+            .await
+            .unwrap()
+            .unwrap();
+        let now = chrono::Utc::now().timestamp() as u64;
+        let recovery = auth::two_factor::confirm_setup(
+            &state,
+            user.id,
+            &auth::totp::code_at(&setup.secret, now),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let (listener, addr) = listener().await;
+        let app = Router::new()
+            .route("/api/auth/login", post(super::super::users::login))
+            .route("/api/auth/2fa/verify", post(verify))
+            .with_state(state);
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::new();
+
+        let challenge: serde_json::Value = client
+            .post(format!("http://{}/api/auth/login", addr))
+            .json(&serde_json::json!({"username": user.username, "password": "hunter22"}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(challenge["two_factor"], "verify");
+        assert!(challenge.get("token").is_none());
+        let challenge_token = challenge["challenge_token"].as_str().unwrap();
+
+        let verify_with = |code: &str| {
+            client
+                .post(format!("http://{}/api/auth/2fa/verify", addr))
+                .json(&serde_json::json!({"challenge_token": challenge_token, "code": code}))
+                .send()
+        };
+        assert_eq!(verify_with("not-a-code").await.unwrap().status(), 401);
+        let session: serde_json::Value = verify_with(&recovery[0])
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(session["token"].is_string());
+        assert_eq!(session["user"]["two_factor_enabled"], true);
+
+        delete_users(&pool, &[&user]).await;
+    }
+}
@@ -1,10 +1,21 @@
 use std::sync::Arc;
 
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::auth;
+use crate::auth::password::Verification;
+use crate::auth::sessions::{IssuedTokens, Refresh};
+use crate::auth::two_factor::{CHALLENGE_SETUP, CHALLENGE_VERIFY};
 use crate::db;
+use crate::ratelimit;
 use crate::ws::handler::AppState;
 
 #[derive(Debug, Deserialize)]
@@ -20,14 +31,80 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
-    pub token: String,
+    #[serde(flatten)]
+    pub tokens: IssuedTokens,
     pub user: db::users::UserPublic,
 }
 
+/// A plausible address: one '@', a local part, and a dotted domain
+pub(crate) fn is_valid_email(email: &str) -> bool {
+    let (local, domain) = match email.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    !local.is_empty()
+        && !email.chars().any(char::is_whitespace)
+        && !domain.contains('@')
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty())
+}
+
+pub(crate) fn user_agent(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok())
+}
+
+/// Start a session for an authenticated user and build the login response
+pub(crate) async fn session_response(
+    state: &AppState,
+    user: db::users::User,
+    headers: &HeaderMap,
+    status: StatusCode,
+) -> axum::response::Response {
+    match auth::sessions::start_session(state, &user, user_agent(headers)).await {
+        Ok(tokens) => {
+            let response = AuthResponse {
+                tokens,
+                user: user.into(),
+            };
+            (status, Json(serde_json::to_value(response).unwrap())).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Create session error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to start session"})),
+            )
+                .into_response()
+        }
+    }
+}
+
 pub async fn register(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<RegisterRequest>,
 ) -> impl IntoResponse {
     if req.username.len() < 3 || req.username.len() > 100 {
@@ -38,7 +115,7 @@ pub async fn register(
             .into_response();
     }
 
-    if !req.email.contains('@') {
+    if !is_valid_email(&req.email) {
         return (
             StatusCode::BAD_REQUEST,
             Json(serde_json::json!({"error": "Invalid email"})),
@@ -54,9 +131,10 @@ pub async fn register(
             .into_response();
     }
 
-    let password_hash = match bcrypt::hash(&req.password, bcrypt::DEFAULT_COST) {
+    let password_hash = match auth::password::hash(&state.config, &req.password) {
         Ok(h) => h,
-        Err(_) => {
+        Err(e) => {
+            tracing::error!("Hash password error: {}", e);
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(serde_json::json!({"error": "Failed to hash password"})),
@@ -67,13 +145,19 @@ pub async fn register(
 
     match db::users::create_user(&state.pool, &req.username, &req.email, &password_hash).await {
         Ok(user) => {
-            let token = auth::create_token(user.id, &user.username, &state.jwt_secret)
-                .unwrap_or_default();
-            let response = AuthResponse {
-                token,
-                user: user.into(),
-            };
-            (StatusCode::CREATED, Json(serde_json::to_value(response).unwrap())).into_response()
+            // Registration still succeeds if mail is down; the user can ask for a new link
+            if let Err(e) = auth::email::send_verification(&state, &user).await {
+                tracing::error!("Send verification email error: {}", e);
+            }
+            if state.config.require_two_factor {
+                return super::two_factor::challenge_response(
+                    &state,
+                    &user,
+                    CHALLENGE_SETUP,
+                    StatusCode::CREATED,
+                );
+            }
+            session_response(&state, user, &headers, StatusCode::CREATED).await
         }
         Err(e) => {
             let msg = e.to_string();
@@ -97,6 +181,7 @@ pub async fn register(
 
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<LoginRequest>,
 ) -> impl IntoResponse {
     let user = match db::users::find_by_username(&state.pool, &req.username).await {
@@ -118,21 +203,281 @@ pub async fn login(
         }
     };
 
-    match bcrypt::verify(&req.password, &user.password_hash) {
-        Ok(true) => {
-            let token = auth::create_token(user.id, &user.username, &state.jwt_secret)
-                .unwrap_or_default();
+    if let Some(remaining) = auth::lockout::remaining(&user) {
+        return locked_response(remaining);
+    }
+
+    let verification = auth::password::verify(&state.config, &req.password, &user.password_hash);
+    if verification == Verification::Invalid {
+        return failed_login(&state, user.id, "Invalid credentials").await;
+    }
+    if verification == Verification::NeedsRehash {
+        rehash_password(&state, &user, &req.password).await;
+    }
+
+    // With 2FA the password only earns a challenge for the second step,
+    // and failures keep counting until the code is right
+    let second_factor = super::two_factor::second_factor(&state, &user);
+    if second_factor != Some(CHALLENGE_VERIFY) {
+        if let Err(e) = auth::lockout::clear(&state, user.id).await {
+            tracing::error!("Clear failed logins error: {}", e);
+        }
+    }
+    if let Some(purpose) = second_factor {
+        return super::two_factor::challenge_response(&state, &user, purpose, StatusCode::OK);
+    }
+    session_response(&state, user, &headers, StatusCode::OK).await
+}
+
+/// Store the password again under the current algorithm and parameters.
+/// Login goes ahead if this fails; it is retried next time.
+async fn rehash_password(state: &AppState, user: &db::users::User, password: &str) {
+    let result = match auth::password::hash(&state.config, password) {
+        Ok(hash) => db::users::update_password(&state.pool, user.id, &hash).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::error!("Rehash password error: {}", e);
+    }
+}
+
+/// 429 for a locked account, with the time left in `Retry-After`
+pub(crate) fn locked_response(remaining: std::time::Duration) -> Response {
+    ratelimit::layer::too_many_requests(
+        remaining,
+        "Account temporarily locked after too many failed attempts",
+    )
+}
+
+/// Count a failed password or code against the account, answering 401, or
+/// 429 if this failure locked it
+pub(crate) async fn failed_login(state: &AppState, user_id: Uuid, error: &str) -> Response {
+    match auth::lockout::record_failure(state, user_id).await {
+        Ok(Some(until)) => {
+            return locked_response((until - Utc::now()).to_std().unwrap_or_default())
+        }
+        Ok(None) => {}
+        Err(e) => tracing::error!("Record failed login error: {}", e),
+    }
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({ "error": error })),
+    )
+        .into_response()
+}
+
+/// Exchange a refresh token for a new access token and refresh token
+pub async fn refresh(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RefreshRequest>,
+) -> impl IntoResponse {
+    match auth::sessions::refresh_session(&state, &req.refresh_token).await {
+        Ok(Refresh::Issued { tokens, user }) => {
             let response = AuthResponse {
-                token,
-                user: user.into(),
+                tokens,
+                user: (*user).into(),
             };
             Json(serde_json::to_value(response).unwrap()).into_response()
         }
-        _ => (
+        Ok(Refresh::Reused) => {
+            tracing::warn!("Refresh token reuse detected, session revoked");
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Session has been revoked"})),
+            )
+                .into_response()
+        }
+        Ok(Refresh::Invalid) => (
             StatusCode::UNAUTHORIZED,
-            Json(serde_json::json!({"error": "Invalid credentials"})),
+            Json(serde_json::json!({"error": "Invalid or expired refresh token"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Refresh error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to refresh session"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// End the session the access token belongs to
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    match db::sessions::revoke_session(&state.pool, claims.sub, claims.sid).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Logout error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to log out"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Confirm an email address with the token from a verification email
+pub async fn verify_email(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<TokenRequest>,
+) -> impl IntoResponse {
+    match auth::email::verify_email(&state, &req.token).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Invalid or expired verification link"})),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Verify email error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to verify email"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Send a fresh verification email to the signed-in user
+pub async fn resend_verification(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+) -> impl IntoResponse {
+    let claims = match auth::middleware::extract_claims(request.extensions()) {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({"error": "Not authenticated"})),
+            )
+                .into_response()
+        }
+    };
+
+    let user = match db::users::find_by_id(&state.pool, claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "User not found"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Resend verification error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to send verification email"})),
+            )
+                .into_response();
+        }
+    };
+
+    if user.is_email_verified() {
+        return (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": "Email is already verified"})),
+        )
+            .into_response();
+    }
+
+    match auth::email::send_verification(&state, &user).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Resend verification error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to send verification email"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Email a password reset link. Always answers 202 so the endpoint does not
+/// reveal which addresses have accounts.
+pub async fn forgot_password(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ForgotPasswordRequest>,
+) -> impl IntoResponse {
+    // Look up and send in the background so response time does not depend on the address
+    tokio::spawn(async move {
+        let result = match db::users::find_by_email(&state.pool, req.email.trim()).await {
+            Ok(Some(user)) => auth::email::send_password_reset(&state, &user).await,
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("Send password reset error: {}", e);
+        }
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({
+            "message": "If an account uses that address, a reset link is on its way"
+        })),
+    )
+        .into_response()
+}
+
+/// Set a new password with the token from a reset email
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ResetPasswordRequest>,
+) -> impl IntoResponse {
+    if req.password.len() < 6 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Password must be at least 6 characters"})),
+        )
+            .into_response();
+    }
+
+    let password_hash = match auth::password::hash(&state.config, &req.password) {
+        Ok(h) => h,
+        Err(e) => {
+            tracing::error!("Hash password error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to hash password"})),
+            )
+                .into_response()
+        }
+    };
+
+    match auth::email::reset_password(&state, &req.token, &password_hash).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "Invalid or expired reset link"})),
         )
             .into_response(),
+        Err(e) => {
+            tracing::error!("Reset password error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "Failed to reset password"})),
+            )
+                .into_response()
+        }
     }
 }
 
@@ -163,3 +508,79 @@ pub async fn me(
             .into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_email() {
+        assert!(is_valid_email("alice@example.com"));
+        assert!(is_valid_email("a.b+tag@mail.example.co.uk"));
+        for invalid in [
+            "alice",
+            "@example.com",
+            "alice@",
+            "alice@localhost",
+            "alice@@example.com",
+            "alice@example.com.",
+            "alice@.example.com",
+            "al ice@example.com",
+        ] {
+            assert!(!is_valid_email(invalid), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_login_upgrades_legacy_hash() {
+        use std::future::IntoFuture;
+
+        use axum::{routing::post, Router};
+
+        use crate::ws::test_support::{create_user, delete_users, listener, test_pool, test_state};
+
+        let pool = test_pool().await;
+        let user = create_user(&pool, "rehash").await;
+        db::users::update_password(&pool, user.id, &bcrypt::hash("hunter22", 4).unwrap())
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let app = Router::new()
+            .route("/api/auth/login", post(login))
+            .with_state(state.clone());
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let login_status = |password: &str| {
+            let request = reqwest::Client::new()
+                .post(format!("http://{}/api/auth/login", addr))
+                .json(&serde_json::json!({"username": user.username, "password": password}));
+            async move { request.send().await.unwrap().status().as_u16() }
+        };
+        let stored = || async {
+            db::users::find_by_id(&pool, user.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .password_hash
+        };
+
+        assert_eq!(login_status("wrong").await, 401);
+        assert!(stored().await.starts_with("$2"));
+
+        assert_eq!(login_status("hunter22").await, 200);
+        let upgraded = stored().await;
+        assert!(upgraded.starts_with("$argon2id$"));
+        assert_eq!(
+            auth::password::verify(&state.config, "hunter22", &upgraded),
+            Verification::Valid
+        );
+
+        // Already current, so a second login leaves the hash alone
+        assert_eq!(login_status("hunter22").await, 200);
+        assert_eq!(stored().await, upgraded);
+
+        delete_users(&pool, &[&user]).await;
+    }
+}
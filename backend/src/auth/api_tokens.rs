@@ -0,0 +1,158 @@
+//! Personal access tokens: long-lived bearer tokens for scripts, limited to
+//! a set of scopes and optionally to a single board.
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use super::{generate_secret_token, hash_secret_token, Claims};
+use crate::db;
+use crate::ws::handler::AppState;
+
+/// Marks a bearer token as a personal access token rather than a JWT
+pub const TOKEN_PREFIX: &str = "wbp_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    BoardsRead,
+    BoardsWrite,
+    /// Everything, including board sharing and the account itself
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::BoardsRead => "boards:read",
+            Scope::BoardsWrite => "boards:write",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Scope> {
+        match s {
+            "boards:read" => Some(Scope::BoardsRead),
+            "boards:write" => Some(Scope::BoardsWrite),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// What a request authenticated with a personal access token may do.
+/// Requests with a session JWT carry no `TokenAccess` and may do anything.
+#[derive(Debug, Clone)]
+pub struct TokenAccess {
+    pub scopes: Vec<Scope>,
+    pub board_id: Option<Uuid>,
+}
+
+impl TokenAccess {
+    pub fn has_scope(&self, required: Scope) -> bool {
+        self.scopes
+            .iter()
+            .any(|&scope| scope == required || scope == Scope::Admin)
+    }
+
+    /// Whether the token may touch the given board; None means a route
+    /// that is not about one board
+    pub fn allows_board(&self, board_id: Option<Uuid>) -> bool {
+        match self.board_id {
+            Some(restricted) => board_id == Some(restricted),
+            None => true,
+        }
+    }
+}
+
+/// A new random token; only its hash is stored
+pub fn generate_api_token() -> String {
+    format!("{}{}", TOKEN_PREFIX, generate_secret_token())
+}
+
+/// Resolve a personal access token to claims for its owner and its limits.
+/// Returns None if the token is unknown, revoked or expired.
+pub async fn authenticate(state: &AppState, token: &str) -> Result<Option<(Claims, TokenAccess)>> {
+    let token = match db::api_tokens::use_token(&state.pool, &hash_secret_token(token)).await? {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    let user = match db::users::find_by_id(&state.pool, token.user_id).await? {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let claims = Claims {
+        sub: user.id,
+        username: user.username,
+        sid: token.id,
+        exp: token
+            .expires_at
+            .map(|t| t.timestamp() as usize)
+            .unwrap_or(usize::MAX),
+        iat: token
+            .created_at
+            .map(|t| t.timestamp() as usize)
+            .unwrap_or_default(),
+    };
+    let access = TokenAccess {
+        // Scopes are validated when the token is created
+        scopes: token
+            .scopes
+            .iter()
+            .filter_map(|s| Scope::parse(s))
+            .collect(),
+        board_id: token.board_id,
+    };
+    Ok(Some((claims, access)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(scopes: &[Scope], board_id: Option<Uuid>) -> TokenAccess {
+        TokenAccess {
+            scopes: scopes.to_vec(),
+            board_id,
+        }
+    }
+
+    #[test]
+    fn test_scope_round_trip() {
+        for scope in [Scope::BoardsRead, Scope::BoardsWrite, Scope::Admin] {
+            assert_eq!(Scope::parse(scope.as_str()), Some(scope));
+        }
+        assert_eq!(Scope::parse("boards:delete"), None);
+    }
+
+    #[test]
+    fn test_scopes_are_independent_except_admin() {
+        let read = access(&[Scope::BoardsRead], None);
+        assert!(read.has_scope(Scope::BoardsRead));
+        assert!(!read.has_scope(Scope::BoardsWrite));
+        assert!(!read.has_scope(Scope::Admin));
+
+        let admin = access(&[Scope::Admin], None);
+        assert!(admin.has_scope(Scope::BoardsRead));
+        assert!(admin.has_scope(Scope::BoardsWrite));
+    }
+
+    #[test]
+    fn test_board_restriction() {
+        let board = Uuid::new_v4();
+        let restricted = access(&[Scope::BoardsRead], Some(board));
+        assert!(restricted.allows_board(Some(board)));
+        assert!(!restricted.allows_board(Some(Uuid::new_v4())));
+        assert!(!restricted.allows_board(None));
+
+        let unrestricted = access(&[Scope::BoardsRead], None);
+        assert!(unrestricted.allows_board(None));
+        assert!(unrestricted.allows_board(Some(board)));
+    }
+
+    #[test]
+    fn test_generated_tokens_carry_prefix() {
+        let token = generate_api_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_ne!(token, generate_api_token());
+    }
+}
@@ -0,0 +1,323 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+
+use super::{generate_secret_token, hash_secret_token};
+use crate::db;
+use crate::db::email_tokens::{CHANGE_EMAIL, RESET_PASSWORD, VERIFY_EMAIL};
+use crate::mail::Email;
+use crate::ws::handler::AppState;
+
+const VERIFY_EMAIL_HOURS: i64 = 48;
+const RESET_PASSWORD_MINUTES: i64 = 60;
+
+/// Email someone, who may not have an account yet, that they were invited
+/// to a board
+pub async fn send_board_invitation(
+    state: &AppState,
+    inviter: &db::users::User,
+    board: &db::boards::Board,
+    invitation: &db::invitations::Invitation,
+) -> Result<()> {
+    let link = format!("{}/board.html?id={}", state.config.public_url, board.id);
+    let email = Email {
+        to: invitation.email.clone(),
+        subject: format!("{} invited you to \"{}\"", inviter.username, board.name),
+        body: format!(
+            "Hi,\n\n{} invited you to the board \"{}\" as {}. Sign up or sign in with this \
+             email address to open it:\n\n{}\n\nThe invitation expires on {}.",
+            inviter.username,
+            board.name,
+            invitation.role,
+            link,
+            invitation.expires_at.format("%Y-%m-%d")
+        ),
+    };
+    state.mailer.send(&email).await
+}
+
+/// Join the boards the user's address was invited to. Only a verified
+/// address counts, so nobody can claim invitations by registering someone
+/// else's email.
+pub async fn accept_invitations(state: &AppState, user: &db::users::User) -> Result<()> {
+    if !user.is_email_verified() {
+        return Ok(());
+    }
+    let joined = db::invitations::accept_for_user(&state.pool, user.id, &user.email, None).await?;
+    if !joined.is_empty() {
+        tracing::info!(
+            "User {} accepted {} board invitations",
+            user.id,
+            joined.len()
+        );
+    }
+    Ok(())
+}
+
+/// Accept invitations after an address was verified, without failing the
+/// verification itself
+async fn accept_after_verify(state: &AppState, user_id: uuid::Uuid) {
+    let result = match db::users::find_by_id(&state.pool, user_id).await {
+        Ok(Some(user)) => accept_invitations(state, &user).await,
+        Ok(None) => Ok(()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::error!("Accept invitations error: {}", e);
+    }
+}
+
+/// Email the user a link that confirms they own their address
+pub async fn send_verification(state: &AppState, user: &db::users::User) -> Result<()> {
+    let token = generate_secret_token();
+    let expires_at = Utc::now() + Duration::hours(VERIFY_EMAIL_HOURS);
+    db::email_tokens::create_token(
+        &state.pool,
+        user.id,
+        VERIFY_EMAIL,
+        &hash_secret_token(&token),
+        expires_at,
+    )
+    .await?;
+
+    let link = format!("{}/?verify={}", state.config.public_url, token);
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Confirm your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nConfirm your email address by opening this link:\n\n{}\n\n\
+             The link expires in {} hours.",
+            user.username, link, VERIFY_EMAIL_HOURS
+        ),
+    };
+    state.mailer.send(&email).await
+}
+
+/// Email the user a link to choose a new password
+pub async fn send_password_reset(state: &AppState, user: &db::users::User) -> Result<()> {
+    let token = generate_secret_token();
+    let expires_at = Utc::now() + Duration::minutes(RESET_PASSWORD_MINUTES);
+    db::email_tokens::create_token(
+        &state.pool,
+        user.id,
+        RESET_PASSWORD,
+        &hash_secret_token(&token),
+        expires_at,
+    )
+    .await?;
+
+    let link = format!("{}/?reset={}", state.config.public_url, token);
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nSomeone asked to reset the password for your account. \
+             Choose a new password here:\n\n{}\n\n\
+             The link expires in {} minutes. If you did not ask for this, ignore this email.",
+            user.username, link, RESET_PASSWORD_MINUTES
+        ),
+    };
+    state.mailer.send(&email).await
+}
+
+/// Hold a new address until the user opens the link sent to it, and tell
+/// the current address about the change
+pub async fn send_email_change(
+    state: &AppState,
+    user: &db::users::User,
+    new_email: &str,
+) -> Result<()> {
+    db::users::set_pending_email(&state.pool, user.id, new_email).await?;
+    let token = generate_secret_token();
+    let expires_at = Utc::now() + Duration::hours(VERIFY_EMAIL_HOURS);
+    db::email_tokens::create_token(
+        &state.pool,
+        user.id,
+        CHANGE_EMAIL,
+        &hash_secret_token(&token),
+        expires_at,
+    )
+    .await?;
+
+    let link = format!("{}/?verify={}", state.config.public_url, token);
+    let confirm = Email {
+        to: new_email.to_string(),
+        subject: "Confirm your new email address".to_string(),
+        body: format!(
+            "Hi {},\n\nConfirm that you want to use this address for your account by opening \
+             this link:\n\n{}\n\nThe link expires in {} hours.",
+            user.username, link, VERIFY_EMAIL_HOURS
+        ),
+    };
+    state.mailer.send(&confirm).await?;
+
+    let notice = Email {
+        to: user.email.clone(),
+        subject: "Your email address is being changed".to_string(),
+        body: format!(
+            "Hi {},\n\nSomeone asked to change the email address of your account to {}. \
+             It changes once the new address is confirmed. If this was not you, change your \
+             password.",
+            user.username, new_email
+        ),
+    };
+    state.mailer.send(&notice).await
+}
+
+/// Mark the address behind a verification token as verified, or switch to
+/// the new address an email change link was sent to.
+/// Returns false if the token is unknown, used or expired.
+pub async fn verify_email(state: &AppState, token: &str) -> Result<bool> {
+    let hash = hash_secret_token(token);
+    let verified = db::email_tokens::consume_token(&state.pool, VERIFY_EMAIL, &hash).await?;
+    if let Some(user_id) = verified {
+        db::users::mark_email_verified(&state.pool, user_id).await?;
+        accept_after_verify(state, user_id).await;
+        return Ok(true);
+    }
+    match db::email_tokens::consume_token(&state.pool, CHANGE_EMAIL, &hash).await? {
+        Some(user_id) => {
+            let applied = db::users::apply_pending_email(&state.pool, user_id).await?;
+            if applied {
+                accept_after_verify(state, user_id).await;
+            }
+            Ok(applied)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Set a new password with a reset token and sign the user out everywhere.
+/// Returns false if the token is unknown, used or expired.
+pub async fn reset_password(state: &AppState, token: &str, password_hash: &str) -> Result<bool> {
+    let hash = hash_secret_token(token);
+    match db::email_tokens::consume_token(&state.pool, RESET_PASSWORD, &hash).await? {
+        Some(user_id) => {
+            db::users::update_password(&state.pool, user_id, password_hash).await?;
+            // The link proved control of the address, so it is verified too
+            db::users::mark_email_verified(&state.pool, user_id).await?;
+            db::sessions::revoke_all_sessions(&state.pool, user_id).await?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mail::outbox::Outbox;
+    use crate::ws::test_support::{create_user, delete_users, test_pool, test_state_with_mailer};
+
+    /// The token carried by the link in an email body
+    fn link_token(body: &str, param: &str) -> String {
+        let start = body.find(&format!("?{}=", param)).unwrap() + param.len() + 2;
+        body[start..start + 64].to_string()
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_verify_email_link_works_once() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "verify").await;
+        let outbox = Arc::new(Outbox::in_memory());
+        let state = test_state_with_mailer(pool.clone(), outbox.clone());
+        assert!(!user.is_email_verified());
+
+        // Asking twice invalidates the first link
+        send_verification(&state, &user).await.unwrap();
+        send_verification(&state, &user).await.unwrap();
+        let sent = outbox.sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].to, user.email);
+        let stale = link_token(&sent[0].body, "verify");
+        let token = link_token(&sent[1].body, "verify");
+
+        assert!(!verify_email(&state, &stale).await.unwrap());
+        assert!(verify_email(&state, &token).await.unwrap());
+        assert!(!verify_email(&state, &token).await.unwrap());
+        let user = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(user.is_email_verified());
+
+        delete_users(&pool, &[&user]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_password_reset_revokes_sessions() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "reset").await;
+        let outbox = Arc::new(Outbox::in_memory());
+        let state = test_state_with_mailer(pool.clone(), outbox.clone());
+        let tokens = super::super::sessions::start_session(&state, &user, None)
+            .await
+            .unwrap();
+        let claims = super::super::verify_token(&tokens.token, &state.jwt_secret).unwrap();
+
+        send_password_reset(&state, &user).await.unwrap();
+        let token = link_token(&outbox.sent()[0].body, "reset");
+
+        // A reset token does not verify an email, and vice versa
+        assert!(!verify_email(&state, &token).await.unwrap());
+        assert!(reset_password(&state, &token, "new-hash").await.unwrap());
+        assert!(!reset_password(&state, &token, "other-hash").await.unwrap());
+
+        let user = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.password_hash, "new-hash");
+        assert!(user.is_email_verified());
+        assert!(!db::sessions::is_session_active(&pool, claims.sid)
+            .await
+            .unwrap());
+
+        delete_users(&pool, &[&user]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_email_change_applies_once_confirmed() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "change").await;
+        let taken = create_user(&pool, "change_taken").await;
+        let outbox = Arc::new(Outbox::in_memory());
+        let state = test_state_with_mailer(pool.clone(), outbox.clone());
+        let new_email = format!("new_{}", user.email);
+
+        send_email_change(&state, &user, &new_email).await.unwrap();
+        let sent = outbox.sent();
+        assert_eq!(sent[0].to, new_email);
+        assert_eq!(sent[1].to, user.email);
+        let pending = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.email, user.email);
+        assert_eq!(pending.pending_email.as_deref(), Some(new_email.as_str()));
+
+        let token = link_token(&sent[0].body, "verify");
+        assert!(verify_email(&state, &token).await.unwrap());
+        assert!(!verify_email(&state, &token).await.unwrap());
+        let changed = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(changed.email, new_email);
+        assert_eq!(changed.pending_email, None);
+        assert!(changed.is_email_verified());
+
+        // An address taken after the link was sent is not applied
+        send_email_change(&state, &changed, &taken.email)
+            .await
+            .unwrap();
+        let token = link_token(&outbox.sent()[2].body, "verify");
+        assert!(!verify_email(&state, &token).await.unwrap());
+
+        delete_users(&pool, &[&changed, &taken]).await;
+    }
+}
@@ -0,0 +1,108 @@
+//! Progressive account lockout: after `LOCKOUT_THRESHOLD` failed logins in a
+//! row the account locks, and each further failure doubles the lock.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db;
+use crate::ws::handler::AppState;
+
+/// How long to lock after the given number of consecutive failures, if at all
+pub fn lockout_duration(config: &Config, failures: i32) -> Option<Duration> {
+    if config.lockout_threshold <= 0 || failures < config.lockout_threshold {
+        return None;
+    }
+    let doublings = (failures - config.lockout_threshold).min(30) as u32;
+    let seconds = config
+        .lockout_base_seconds
+        .saturating_mul(1 << doublings)
+        .min(config.lockout_max_seconds);
+    Some(Duration::seconds(seconds))
+}
+
+/// Time left on the user's lock, if it is locked
+pub fn remaining(user: &db::users::User) -> Option<std::time::Duration> {
+    let until = user.locked_until?;
+    (until - Utc::now()).to_std().ok()
+}
+
+/// Count a failed password or code. Returns when the account is now locked
+/// until, if this failure locked it.
+pub async fn record_failure(state: &AppState, user_id: Uuid) -> Result<Option<DateTime<Utc>>> {
+    let failures = db::users::record_failed_login(&state.pool, user_id).await?;
+    let duration = match lockout_duration(&state.config, failures) {
+        Some(duration) => duration,
+        None => return Ok(None),
+    };
+    let until = Utc::now() + duration;
+    db::users::lock_until(&state.pool, user_id, until).await?;
+    tracing::warn!(
+        "Locked user {} for {}s after {} failed logins",
+        user_id,
+        duration.num_seconds(),
+        failures
+    );
+    Ok(Some(until))
+}
+
+/// Forget earlier failures once the user has signed in
+pub async fn clear(state: &AppState, user_id: Uuid) -> Result<()> {
+    db::users::clear_failed_logins(&state.pool, user_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::test_support::test_config;
+
+    #[test]
+    fn test_lockout_doubles_up_to_the_maximum() {
+        let mut config = test_config();
+        config.lockout_threshold = 3;
+        config.lockout_base_seconds = 30;
+        config.lockout_max_seconds = 200;
+
+        assert_eq!(lockout_duration(&config, 2), None);
+        assert_eq!(lockout_duration(&config, 3), Some(Duration::seconds(30)));
+        assert_eq!(lockout_duration(&config, 4), Some(Duration::seconds(60)));
+        assert_eq!(lockout_duration(&config, 5), Some(Duration::seconds(120)));
+        assert_eq!(lockout_duration(&config, 6), Some(Duration::seconds(200)));
+        assert_eq!(lockout_duration(&config, 500), Some(Duration::seconds(200)));
+
+        config.lockout_threshold = 0;
+        assert_eq!(lockout_duration(&config, 500), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_failures_lock_and_success_clears() {
+        use crate::ws::test_support::{create_user, delete_users, test_pool, test_state_with};
+
+        let pool = test_pool().await;
+        let user = create_user(&pool, "lockout").await;
+        let mut config = test_config();
+        config.lockout_threshold = 2;
+        let state = test_state_with(pool.clone(), config);
+
+        assert_eq!(record_failure(&state, user.id).await.unwrap(), None);
+        assert!(record_failure(&state, user.id).await.unwrap().is_some());
+        let locked = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let left = remaining(&locked).expect("account should be locked");
+        assert!(left.as_secs() <= 30);
+
+        clear(&state, user.id).await.unwrap();
+        let cleared = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(remaining(&cleared).is_none());
+        assert_eq!(record_failure(&state, user.id).await.unwrap(), None);
+
+        delete_users(&pool, &[&locked]).await;
+    }
+}
@@ -1,19 +1,22 @@
+use std::sync::Arc;
+
 use axum::{
-    extract::Request,
+    extract::{RawPathParams, Request, State},
     http::StatusCode,
     middleware::Next,
     response::{IntoResponse, Response},
 };
 
+use super::api_tokens::{Scope, TokenAccess, TOKEN_PREFIX};
 use super::Claims;
+use crate::db;
+use crate::ws::handler::AppState;
 
-pub async fn auth_middleware(mut request: Request, next: Next) -> Response {
-    let jwt_secret = request
-        .extensions()
-        .get::<String>()
-        .cloned()
-        .unwrap_or_default();
-
+pub async fn auth_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
     let auth_header = request
         .headers()
         .get("Authorization")
@@ -28,13 +31,66 @@ pub async fn auth_middleware(mut request: Request, next: Next) -> Response {
         }
     };
 
-    match super::verify_token(token, &jwt_secret) {
-        Ok(claims) => {
+    if token.starts_with(TOKEN_PREFIX) {
+        return match super::api_tokens::authenticate(&state, token).await {
+            Ok(Some((claims, access))) => {
+                request.extensions_mut().insert(claims);
+                request.extensions_mut().insert(access);
+                next.run(request).await
+            }
+            Ok(None) => (StatusCode::UNAUTHORIZED, "Invalid or revoked token").into_response(),
+            Err(e) => {
+                tracing::error!("API token check error: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+
+    let claims = match super::verify_token(token, &state.jwt_secret) {
+        Ok(claims) => claims,
+        Err(_) => return (StatusCode::UNAUTHORIZED, "Invalid or expired token").into_response(),
+    };
+
+    match db::sessions::is_session_active(&state.pool, claims.sid).await {
+        Ok(true) => {
             request.extensions_mut().insert(claims);
             next.run(request).await
         }
-        Err(_) => (StatusCode::UNAUTHORIZED, "Invalid or expired token").into_response(),
+        Ok(false) => (StatusCode::UNAUTHORIZED, "Session has been revoked").into_response(),
+        Err(e) => {
+            tracing::error!("Session check error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Limit a route to personal access tokens with the given scope. Tokens
+/// restricted to a board may only use routes for that board, which take it
+/// as the `board_id` path parameter. Session tokens pass through.
+pub async fn require_scope(
+    State(scope): State<Scope>,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(access) = request.extensions().get::<TokenAccess>() {
+        if !access.has_scope(scope) {
+            return (
+                StatusCode::FORBIDDEN,
+                format!("Token lacks the {} scope", scope.as_str()),
+            )
+                .into_response();
+        }
+        let board_id = params
+            .iter()
+            .find(|(name, _)| *name == "board_id")
+            .and_then(|(_, value)| value.parse().ok());
+        if !access.allows_board(board_id) {
+            return (StatusCode::FORBIDDEN, "Token is restricted to another board")
+                .into_response();
+        }
     }
+    next.run(request).await
 }
 
 pub fn extract_claims(extensions: &axum::http::Extensions) -> Option<Claims> {
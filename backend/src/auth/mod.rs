@@ -1,24 +1,45 @@
+pub mod api_tokens;
+pub mod email;
+pub mod lockout;
 pub mod middleware;
+pub mod oidc;
+pub mod password;
+pub mod sessions;
+pub mod totp;
+pub mod two_factor;
 
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: Uuid,
     pub username: String,
+    /// The session the token was issued for; revoking it invalidates the token.
+    /// For personal access tokens, the token's own id.
+    pub sid: Uuid,
     pub exp: usize,
     pub iat: usize,
 }
 
-pub fn create_token(user_id: Uuid, username: &str, secret: &str) -> anyhow::Result<String> {
+/// Create a short-lived access token for a session
+pub fn create_token(
+    user_id: Uuid,
+    username: &str,
+    session_id: Uuid,
+    secret: &str,
+    ttl_minutes: i64,
+) -> anyhow::Result<String> {
     let now = Utc::now();
-    let exp = now + Duration::hours(24);
+    let exp = now + Duration::minutes(ttl_minutes);
     let claims = Claims {
         sub: user_id,
         username: username.to_string(),
+        sid: session_id,
         exp: exp.timestamp() as usize,
         iat: now.timestamp() as usize,
     };
@@ -39,6 +60,18 @@ pub fn verify_token(token: &str, secret: &str) -> anyhow::Result<Claims> {
     Ok(data.claims)
 }
 
+/// A random single-use secret, such as a refresh or email token. Only its
+/// hash is stored.
+pub fn generate_secret_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hash_secret_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,19 +82,23 @@ mod tests {
         let username = "testuser";
         let secret = "test-secret-key";
 
-        let token = create_token(user_id, username, secret).expect("should create token");
+        let session_id = Uuid::new_v4();
+
+        let token = create_token(user_id, username, session_id, secret, 15)
+            .expect("should create token");
         assert!(!token.is_empty());
 
         let claims = verify_token(&token, secret).expect("should verify token");
         assert_eq!(claims.sub, user_id);
         assert_eq!(claims.username, username);
+        assert_eq!(claims.sid, session_id);
         assert!(claims.exp > claims.iat);
     }
 
     #[test]
     fn test_verify_with_wrong_secret() {
         let user_id = Uuid::new_v4();
-        let token = create_token(user_id, "user", "secret1").unwrap();
+        let token = create_token(user_id, "user", Uuid::new_v4(), "secret1", 15).unwrap();
         let result = verify_token(&token, "secret2");
         assert!(result.is_err());
     }
@@ -75,17 +112,60 @@ mod tests {
     #[test]
     fn test_token_contains_correct_username() {
         let user_id = Uuid::new_v4();
-        let token = create_token(user_id, "alice", "secret").unwrap();
+        let token = create_token(user_id, "alice", Uuid::new_v4(), "secret", 15).unwrap();
         let claims = verify_token(&token, "secret").unwrap();
         assert_eq!(claims.username, "alice");
     }
 
     #[test]
-    fn test_token_expiry_is_24h() {
+    fn test_token_expiry_follows_ttl() {
         let user_id = Uuid::new_v4();
-        let token = create_token(user_id, "user", "secret").unwrap();
+        let token = create_token(user_id, "user", Uuid::new_v4(), "secret", 15).unwrap();
         let claims = verify_token(&token, "secret").unwrap();
         let duration = claims.exp - claims.iat;
-        assert_eq!(duration, 86400); // 24 hours in seconds
+        assert_eq!(duration, 900); // 15 minutes in seconds
+    }
+
+    #[test]
+    fn test_token_without_session_is_rejected() {
+        // Tokens issued before sessions existed carry no sid
+        #[derive(Serialize)]
+        struct LegacyClaims {
+            sub: Uuid,
+            username: String,
+            exp: usize,
+            iat: usize,
+        }
+        let now = Utc::now();
+        let legacy = LegacyClaims {
+            sub: Uuid::new_v4(),
+            username: "user".to_string(),
+            exp: (now + Duration::hours(24)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+        };
+        let token = encode(
+            &Header::default(),
+            &legacy,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(verify_token(&token, "secret").is_err());
+    }
+
+    #[test]
+    fn test_secret_tokens_are_random() {
+        let a = generate_secret_token();
+        let b = generate_secret_token();
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_hash_secret_token() {
+        let hash = hash_secret_token("token");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, hash_secret_token("token"));
+        assert_ne!(hash, hash_secret_token("other"));
+        assert_ne!(hash, "token");
     }
 }
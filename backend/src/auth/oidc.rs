@@ -0,0 +1,708 @@
+//! OpenID Connect login: the authorization-code flow with PKCE against the
+//! issuer configured in `Config`. Provider accounts are linked to local users
+//! through `user_identities`; a first login provisions the account.
+
+use anyhow::anyhow;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{generate_secret_token, hash_secret_token};
+use crate::config::Config;
+use crate::db;
+use crate::ws::handler::AppState;
+
+/// How long a login may take at the provider before its state expires
+const STATE_MINUTES: i64 = 10;
+const HTTP_TIMEOUT_SECONDS: u64 = 10;
+const MAX_USERNAME_LEN: usize = 50;
+
+/// Stored as the password hash of provisioned accounts; no password matches it
+pub const NO_PASSWORD: &str = "!";
+
+#[derive(Debug, thiserror::Error)]
+pub enum OidcError {
+    #[error("single sign-on is not configured")]
+    Disabled,
+    #[error("the login has expired, please try again")]
+    InvalidState,
+    #[error("identity provider error: {0}")]
+    Provider(String),
+    #[error("an account with this email already exists, sign in with your password")]
+    EmailTaken,
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// The OIDC client settings, present when single sign-on is enabled
+pub struct Provider<'a> {
+    pub issuer: &'a str,
+    pub client_id: &'a str,
+    pub client_secret: Option<&'a str>,
+    pub scopes: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> Provider<'a> {
+    pub fn from_config(config: &'a Config) -> Option<Self> {
+        Some(Provider {
+            issuer: config.oidc_issuer_url.as_deref()?,
+            client_id: config.oidc_client_id.as_deref()?,
+            client_secret: config.oidc_client_secret.as_deref(),
+            scopes: &config.oidc_scopes,
+            name: &config.oidc_provider_name,
+        })
+    }
+}
+
+/// The parts of the provider's discovery document the flow needs
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    /// Some providers send this as a string
+    email_verified: Option<serde_json::Value>,
+    preferred_username: Option<String>,
+}
+
+impl IdClaims {
+    fn email(&self) -> Option<&str> {
+        self.email
+            .as_deref()
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+    }
+
+    fn is_email_verified(&self) -> bool {
+        match &self.email_verified {
+            Some(serde_json::Value::Bool(verified)) => *verified,
+            Some(serde_json::Value::String(verified)) => verified == "true",
+            _ => false,
+        }
+    }
+}
+
+/// Where the provider sends the browser back to
+pub fn redirect_uri(config: &Config) -> String {
+    format!(
+        "{}/api/auth/oidc/callback",
+        config.public_url.trim_end_matches('/')
+    )
+}
+
+/// The S256 PKCE challenge for a code verifier
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn http_client() -> anyhow::Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(HTTP_TIMEOUT_SECONDS))
+        .build()?)
+}
+
+fn provider_error(context: &str, e: impl std::fmt::Display) -> OidcError {
+    OidcError::Provider(format!("{}: {}", context, e))
+}
+
+async fn discover(client: &reqwest::Client, issuer: &str) -> Result<Discovery, OidcError> {
+    let issuer = issuer.trim_end_matches('/');
+    let url = format!("{}/.well-known/openid-configuration", issuer);
+    let discovery: Discovery = client
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| provider_error("discovery failed", e))?
+        .json()
+        .await
+        .map_err(|e| provider_error("invalid discovery document", e))?;
+    if discovery.issuer.trim_end_matches('/') != issuer {
+        return Err(OidcError::Provider(format!(
+            "discovery document is for issuer {}",
+            discovery.issuer
+        )));
+    }
+    Ok(discovery)
+}
+
+/// Start a login and return the provider URL to send the browser to
+pub async fn begin_login(state: &AppState) -> Result<String, OidcError> {
+    let provider = Provider::from_config(&state.config).ok_or(OidcError::Disabled)?;
+    let discovery = discover(&http_client()?, provider.issuer).await?;
+
+    let login_state = generate_secret_token();
+    let code_verifier = generate_secret_token();
+    let nonce = generate_secret_token();
+    db::oidc::create_state(
+        &state.pool,
+        &hash_secret_token(&login_state),
+        &code_verifier,
+        &nonce,
+        Utc::now() + Duration::minutes(STATE_MINUTES),
+    )
+    .await?;
+
+    let url = Url::parse_with_params(
+        &discovery.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", provider.client_id),
+            ("redirect_uri", &redirect_uri(&state.config)),
+            ("scope", provider.scopes),
+            ("state", &login_state),
+            ("nonce", &nonce),
+            ("code_challenge", &pkce_challenge(&code_verifier)),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|e| provider_error("invalid authorization endpoint", e))?;
+    Ok(url.into())
+}
+
+/// Finish a login the provider redirected back with, returning the local user
+pub async fn complete_login(
+    state: &AppState,
+    code: &str,
+    login_state: &str,
+) -> Result<db::users::User, OidcError> {
+    let provider = Provider::from_config(&state.config).ok_or(OidcError::Disabled)?;
+    let pending = db::oidc::consume_state(&state.pool, &hash_secret_token(login_state))
+        .await?
+        .ok_or(OidcError::InvalidState)?;
+
+    let client = http_client()?;
+    let discovery = discover(&client, provider.issuer).await?;
+
+    let redirect_uri = redirect_uri(&state.config);
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &redirect_uri),
+        ("client_id", provider.client_id),
+        ("code_verifier", &pending.code_verifier),
+    ];
+    if let Some(secret) = provider.client_secret {
+        form.push(("client_secret", secret));
+    }
+    let tokens: TokenResponse = client
+        .post(&discovery.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| provider_error("code exchange failed", e))?
+        .json()
+        .await
+        .map_err(|e| provider_error("invalid token response", e))?;
+
+    let claims = verify_id_token(&client, &provider, &discovery, &tokens.id_token).await?;
+    if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+        return Err(OidcError::Provider(
+            "ID token nonce does not match".to_string(),
+        ));
+    }
+    find_or_provision(state, &discovery.issuer, &claims).await
+}
+
+/// Check the ID token's signature, issuer, audience and expiry
+async fn verify_id_token(
+    client: &reqwest::Client,
+    provider: &Provider<'_>,
+    discovery: &Discovery,
+    id_token: &str,
+) -> Result<IdClaims, OidcError> {
+    let header = decode_header(id_token).map_err(|e| provider_error("invalid ID token", e))?;
+    let key = match header.alg {
+        // HMAC-signed tokens use the client secret as the key
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            let secret = provider.client_secret.ok_or_else(|| {
+                OidcError::Provider("HMAC-signed ID token without a client secret".to_string())
+            })?;
+            DecodingKey::from_secret(secret.as_bytes())
+        }
+        _ => {
+            let jwks_uri = discovery.jwks_uri.as_deref().ok_or_else(|| {
+                OidcError::Provider("discovery document has no jwks_uri".to_string())
+            })?;
+            let keys: JwkSet = client
+                .get(jwks_uri)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| provider_error("fetching signing keys failed", e))?
+                .json()
+                .await
+                .map_err(|e| provider_error("invalid signing keys", e))?;
+            let jwk = match &header.kid {
+                Some(kid) => keys.find(kid),
+                None => keys.keys.first(),
+            }
+            .ok_or_else(|| OidcError::Provider("unknown ID token signing key".to_string()))?;
+            DecodingKey::from_jwk(jwk).map_err(|e| provider_error("invalid signing key", e))?
+        }
+    };
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[provider.client_id]);
+    validation.set_issuer(&[&discovery.issuer]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    let data = decode::<IdClaims>(id_token, &key, &validation)
+        .map_err(|e| provider_error("invalid ID token", e))?;
+    Ok(data.claims)
+}
+
+/// The user linked to a provider account. On first login an existing account
+/// with the same address is linked if the provider vouches for the address;
+/// otherwise a new account is created.
+async fn find_or_provision(
+    state: &AppState,
+    issuer: &str,
+    claims: &IdClaims,
+) -> Result<db::users::User, OidcError> {
+    let pool = &state.pool;
+    if let Some(identity) =
+        db::oidc::touch_identity(pool, issuer, &claims.sub, claims.email()).await?
+    {
+        return db::users::find_by_id(pool, identity.user_id)
+            .await?
+            .ok_or_else(|| anyhow!("identity {} has no user", identity.id).into());
+    }
+
+    let email = claims.email().ok_or_else(|| {
+        OidcError::Provider("the identity provider did not share an email address".to_string())
+    })?;
+    let verified = claims.is_email_verified();
+    let user = match db::users::find_by_email(pool, email).await? {
+        Some(existing) if verified => existing,
+        Some(_) => return Err(OidcError::EmailTaken),
+        None => {
+            let username = available_username(state, &username_candidate(claims)).await?;
+            db::users::create_user(pool, &username, email, NO_PASSWORD).await?
+        }
+    };
+    if verified {
+        db::users::mark_email_verified(pool, user.id).await?;
+    }
+    db::oidc::link_identity(pool, user.id, issuer, &claims.sub, Some(email)).await?;
+    tracing::info!(
+        "Linked {} account {} to user {}",
+        issuer,
+        claims.sub,
+        user.id
+    );
+
+    db::users::find_by_id(pool, user.id)
+        .await?
+        .ok_or_else(|| anyhow!("user {} disappeared", user.id).into())
+}
+
+/// A username from the provider's profile, reduced to safe characters
+fn username_candidate(claims: &IdClaims) -> String {
+    let source = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| claims.email().and_then(|e| e.split('@').next()))
+        .unwrap_or_default();
+    let name: String = source
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .take(MAX_USERNAME_LEN)
+        .collect();
+    if name.len() < 3 {
+        "user".to_string()
+    } else {
+        name
+    }
+}
+
+/// The candidate, or the candidate with a random suffix if it is taken
+async fn available_username(state: &AppState, candidate: &str) -> anyhow::Result<String> {
+    if db::users::find_by_username(&state.pool, candidate)
+        .await?
+        .is_none()
+    {
+        return Ok(candidate.to_string());
+    }
+    for _ in 0..5 {
+        let name = format!("{}_{}", candidate, &generate_secret_token()[..6]);
+        if db::users::find_by_username(&state.pool, &name)
+            .await?
+            .is_none()
+        {
+            return Ok(name);
+        }
+    }
+    Err(anyhow!("no free username for {}", candidate))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::future::IntoFuture;
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::{Form, State},
+        http::StatusCode,
+        routing::{get, post},
+        Json, Router,
+    };
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::ws::test_support::{
+        delete_users, listener, test_config, test_pool, test_state_with,
+    };
+
+    const CLIENT_ID: &str = "whiteboard";
+    const CLIENT_SECRET: &str = "client-secret";
+
+    /// A minimal issuer: discovery, and a token endpoint that hands out an
+    /// HS256 ID token for the next login once the PKCE verifier checks out
+    #[derive(Default)]
+    struct MockIssuer {
+        issuer: String,
+        /// The code challenge and nonce sent with the authorization request
+        challenge: Option<(String, String)>,
+        claims: serde_json::Value,
+    }
+
+    type Mock = Arc<Mutex<MockIssuer>>;
+
+    async fn discovery(State(mock): State<Mock>) -> Json<serde_json::Value> {
+        let issuer = mock.lock().unwrap().issuer.clone();
+        Json(serde_json::json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+        }))
+    }
+
+    async fn token(
+        State(mock): State<Mock>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<serde_json::Value>, StatusCode> {
+        let mock = mock.lock().unwrap();
+        let (challenge, nonce) = mock.challenge.clone().ok_or(StatusCode::BAD_REQUEST)?;
+        if form.get("code").map(String::as_str) != Some("the-code")
+            || form.get("client_secret").map(String::as_str) != Some(CLIENT_SECRET)
+            || pkce_challenge(&form["code_verifier"]) != challenge
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let mut claims = mock.claims.clone();
+        claims["iss"] = mock.issuer.clone().into();
+        claims["aud"] = CLIENT_ID.into();
+        claims["exp"] = (Utc::now().timestamp() + 300).into();
+        claims["nonce"] = nonce.into();
+        let id_token = encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(CLIENT_SECRET.as_bytes()),
+        )
+        .unwrap();
+        Ok(Json(
+            serde_json::json!({ "id_token": id_token, "token_type": "Bearer" }),
+        ))
+    }
+
+    async fn mock_issuer() -> Mock {
+        let (listener, addr) = listener().await;
+        let mock = Arc::new(Mutex::new(MockIssuer {
+            issuer: format!("http://{}", addr),
+            ..Default::default()
+        }));
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/token", post(token))
+            .with_state(mock.clone());
+        tokio::spawn(axum::serve(listener, app).into_future());
+        mock
+    }
+
+    fn oidc_state(pool: sqlx::PgPool, mock: &Mock) -> Arc<AppState> {
+        let mut config = test_config();
+        config.oidc_issuer_url = Some(mock.lock().unwrap().issuer.clone());
+        config.oidc_client_id = Some(CLIENT_ID.to_string());
+        config.oidc_client_secret = Some(CLIENT_SECRET.to_string());
+        test_state_with(pool, config)
+    }
+
+    /// Start a login and act as the browser at the provider: remember what
+    /// the authorization request carried and return the state to come back with
+    async fn authorize(state: &AppState, mock: &Mock) -> String {
+        let url = Url::parse(&begin_login(state).await.unwrap()).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["redirect_uri"], redirect_uri(&state.config));
+        mock.lock().unwrap().challenge =
+            Some((params["code_challenge"].clone(), params["nonce"].clone()));
+        params["state"].clone()
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // RFC 7636, appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_username_candidate() {
+        let claims = |preferred: Option<&str>, email: Option<&str>| IdClaims {
+            sub: "sub".to_string(),
+            nonce: None,
+            email: email.map(str::to_string),
+            email_verified: None,
+            preferred_username: preferred.map(str::to_string),
+        };
+        assert_eq!(
+            username_candidate(&claims(Some("jane.doe"), Some("x@example.com"))),
+            "jane.doe"
+        );
+        assert_eq!(
+            username_candidate(&claims(None, Some("Jane Doe@example.com"))),
+            "JaneDoe"
+        );
+        assert_eq!(username_candidate(&claims(Some("æø"), None)), "user");
+        assert_eq!(
+            username_candidate(&claims(Some(&"a".repeat(80)), None)).len(),
+            MAX_USERNAME_LEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled_without_issuer() {
+        let state = crate::ws::test_support::offline_state();
+        assert!(matches!(
+            begin_login(&state).await,
+            Err(OidcError::Disabled)
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_first_login_provisions_and_second_reuses_account() {
+        let pool = test_pool().await;
+        let mock = mock_issuer().await;
+        let state = oidc_state(pool.clone(), &mock);
+        let subject = Uuid::new_v4().to_string();
+        let email = format!("sso_{}@example.com", subject);
+        mock.lock().unwrap().claims = serde_json::json!({
+            "sub": subject,
+            "email": email,
+            "email_verified": true,
+            "preferred_username": "sso-user",
+        });
+
+        let login_state = authorize(&state, &mock).await;
+        let user = complete_login(&state, "the-code", &login_state)
+            .await
+            .unwrap();
+        assert_eq!(user.email, email);
+        assert!(user.username.starts_with("sso-user"));
+        assert!(user.is_email_verified());
+        assert!(!bcrypt::verify("", &user.password_hash).unwrap_or(false));
+
+        // The state works once
+        assert!(matches!(
+            complete_login(&state, "the-code", &login_state).await,
+            Err(OidcError::InvalidState)
+        ));
+
+        let again = complete_login(&state, "the-code", &authorize(&state, &mock).await)
+            .await
+            .unwrap();
+        assert_eq!(again.id, user.id);
+
+        let tokens = super::super::sessions::start_session(&state, &user, None)
+            .await
+            .unwrap();
+        let claims = super::super::verify_token(&tokens.token, &state.jwt_secret).unwrap();
+        assert_eq!(claims.sub, user.id);
+
+        delete_users(&pool, &[&user]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_unverified_email_does_not_take_over_account() {
+        let pool = test_pool().await;
+        let mock = mock_issuer().await;
+        let state = oidc_state(pool.clone(), &mock);
+        let existing = crate::ws::test_support::create_user(&pool, "local").await;
+        mock.lock().unwrap().claims = serde_json::json!({
+            "sub": Uuid::new_v4().to_string(),
+            "email": existing.email,
+            "email_verified": false,
+        });
+
+        let login_state = authorize(&state, &mock).await;
+        assert!(matches!(
+            complete_login(&state, "the-code", &login_state).await,
+            Err(OidcError::EmailTaken)
+        ));
+
+        // A verified address links the provider account to the existing user
+        mock.lock().unwrap().claims["email_verified"] = "true".into();
+        let login_state = authorize(&state, &mock).await;
+        let user = complete_login(&state, "the-code", &login_state)
+            .await
+            .unwrap();
+        assert_eq!(user.id, existing.id);
+        assert_eq!(user.password_hash, existing.password_hash);
+
+        delete_users(&pool, &[&existing]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_callback_asks_two_factor_users_for_their_code() {
+        let pool = test_pool().await;
+        let mock = mock_issuer().await;
+        let state = oidc_state(pool.clone(), &mock);
+        let existing = crate::ws::test_support::create_user(&pool, "sso2fa").await;
+        let setup = super::super::two_factor::begin_setup(&state, &existing)
+            .await
+            .unwrap()
+            .unwrap();
+        let now = Utc::now().timestamp() as u64;
+        let recovery = super::super::two_factor::confirm_setup(
+            &state,
+            existing.id,
+            &super::super::totp::code_at(&setup.secret, now),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        mock.lock().unwrap().claims = serde_json::json!({
+            "sub": Uuid::new_v4().to_string(),
+            "email": existing.email,
+            "email_verified": true,
+        });
+
+        let (listener, addr) = listener().await;
+        let app = Router::new()
+            .route("/api/auth/oidc/callback", get(crate::api::oidc::callback))
+            .route("/api/auth/2fa/verify", post(crate::api::two_factor::verify))
+            .with_state(state.clone());
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+
+        let login_state = authorize(&state, &mock).await;
+        let response = client
+            .get(format!("http://{}/api/auth/oidc/callback", addr))
+            .query(&[("code", "the-code"), ("state", login_state.as_str())])
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_redirection());
+        let location = Url::parse(response.headers()["location"].to_str().unwrap()).unwrap();
+        let fragment = format!("http://app/?{}", location.fragment().unwrap());
+        let fragment: HashMap<_, _> = Url::parse(&fragment)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        // No session yet, only a challenge for the second step
+        assert!(!fragment.contains_key("sso_token"));
+        assert_eq!(fragment["two_factor"], "verify");
+
+        let response = client
+            .post(format!("http://{}/api/auth/2fa/verify", addr))
+            .json(&serde_json::json!({
+                "challenge_token": fragment["challenge_token"],
+                "code": recovery[0],
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let session: serde_json::Value = response.json().await.unwrap();
+        assert!(session["token"].is_string());
+
+        delete_users(&pool, &[&existing]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_callback_refuses_locked_accounts() {
+        let pool = test_pool().await;
+        let mock = mock_issuer().await;
+        let state = oidc_state(pool.clone(), &mock);
+        let existing = crate::ws::test_support::create_user(&pool, "ssolocked").await;
+        db::users::lock_until(&pool, existing.id, Utc::now() + Duration::minutes(5))
+            .await
+            .unwrap();
+        mock.lock().unwrap().claims = serde_json::json!({
+            "sub": Uuid::new_v4().to_string(),
+            "email": existing.email,
+            "email_verified": true,
+        });
+
+        let (listener, addr) = listener().await;
+        let app = Router::new()
+            .route("/api/auth/oidc/callback", get(crate::api::oidc::callback))
+            .with_state(state.clone());
+        tokio::spawn(axum::serve(listener, app).into_future());
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+
+        let login_state = authorize(&state, &mock).await;
+        let response = client
+            .get(format!("http://{}/api/auth/oidc/callback", addr))
+            .query(&[("code", "the-code"), ("state", login_state.as_str())])
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_redirection());
+        let location = Url::parse(response.headers()["location"].to_str().unwrap()).unwrap();
+        assert!(location.fragment().is_none());
+        assert!(location.query_pairs().any(|(key, _)| key == "sso_error"));
+
+        delete_users(&pool, &[&existing]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_wrong_code_is_rejected() {
+        let pool = test_pool().await;
+        let mock = mock_issuer().await;
+        let state = oidc_state(pool.clone(), &mock);
+
+        let login_state = authorize(&state, &mock).await;
+        assert!(matches!(
+            complete_login(&state, "forged-code", &login_state).await,
+            Err(OidcError::Provider(_))
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+//! Password hashing. New hashes are Argon2id in PHC string format with the
+//! parameters from `Config`; legacy bcrypt hashes still verify and are
+//! replaced on the next successful login.
+
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::rngs::OsRng;
+
+use crate::config::Config;
+
+/// Result of checking a password against a stored hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// Correct, but hashed with an older algorithm or parameters
+    NeedsRehash,
+}
+
+/// The Argon2id parameters from `ARGON2_*`, checked at startup
+pub fn params(config: &Config) -> Result<Params> {
+    Params::new(
+        config.argon2_memory_kib,
+        config.argon2_iterations,
+        config.argon2_parallelism,
+        None,
+    )
+    .map_err(|e| anyhow!("Invalid ARGON2_* settings: {}", e))
+}
+
+fn argon2(config: &Config) -> Result<Argon2<'static>> {
+    Ok(Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        params(config)?,
+    ))
+}
+
+pub fn hash(config: &Config, password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = argon2(config)?
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("Failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+pub fn verify(config: &Config, password: &str, stored: &str) -> Verification {
+    if stored.starts_with("$2") {
+        return match bcrypt::verify(password, stored) {
+            Ok(true) => Verification::NeedsRehash,
+            _ => Verification::Invalid,
+        };
+    }
+
+    // Anything else unparseable, such as the marker for accounts without a
+    // password, never matches
+    let parsed = match PasswordHash::new(stored) {
+        Ok(parsed) => parsed,
+        Err(_) => return Verification::Invalid,
+    };
+    // Argon2 reads the algorithm and parameters from the hash itself
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return Verification::Invalid;
+    }
+    if is_current(config, &parsed) {
+        Verification::Valid
+    } else {
+        Verification::NeedsRehash
+    }
+}
+
+fn is_current(config: &Config, parsed: &PasswordHash) -> bool {
+    let current = match params(config) {
+        Ok(params) => params,
+        Err(_) => return true,
+    };
+    parsed.algorithm == Algorithm::Argon2id.ident()
+        && parsed.version == Some(Version::V0x13.into())
+        && Params::try_from(parsed).is_ok_and(|p| {
+            p.m_cost() == current.m_cost()
+                && p.t_cost() == current.t_cost()
+                && p.p_cost() == current.p_cost()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::test_support::test_config;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let config = test_config();
+        let hash = hash(&config, "hunter22").unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$"));
+        assert_eq!(verify(&config, "hunter22", &hash), Verification::Valid);
+        assert_eq!(verify(&config, "hunter23", &hash), Verification::Invalid);
+    }
+
+    #[test]
+    fn test_legacy_bcrypt_needs_rehash() {
+        let config = test_config();
+        let legacy = bcrypt::hash("hunter22", 4).unwrap();
+        assert_eq!(
+            verify(&config, "hunter22", &legacy),
+            Verification::NeedsRehash
+        );
+        assert_eq!(verify(&config, "wrong", &legacy), Verification::Invalid);
+    }
+
+    #[test]
+    fn test_changed_parameters_need_rehash() {
+        let mut config = test_config();
+        let hash = hash(&config, "hunter22").unwrap();
+        config.argon2_iterations += 1;
+        assert_eq!(
+            verify(&config, "hunter22", &hash),
+            Verification::NeedsRehash
+        );
+    }
+
+    #[test]
+    fn test_unusable_hashes_never_match() {
+        let config = test_config();
+        for stored in ["!", "", "x", "$2b$garbage"] {
+            assert_eq!(verify(&config, "", stored), Verification::Invalid);
+        }
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let mut config = test_config();
+        config.argon2_parallelism = 0;
+        assert!(params(&config).is_err());
+        assert!(hash(&config, "hunter22").is_err());
+    }
+}
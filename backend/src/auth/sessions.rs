@@ -0,0 +1,151 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{generate_secret_token, hash_secret_token};
+use crate::db;
+use crate::ws::handler::AppState;
+
+/// An access token plus the refresh token that renews it
+#[derive(Debug, Serialize)]
+pub struct IssuedTokens {
+    pub token: String,
+    pub refresh_token: String,
+    /// Access token lifetime in seconds
+    pub expires_in: i64,
+}
+
+/// Outcome of presenting a refresh token
+pub enum Refresh {
+    Issued {
+        tokens: IssuedTokens,
+        user: Box<db::users::User>,
+    },
+    /// The token was already rotated out; its session has been revoked
+    Reused,
+    Invalid,
+}
+
+fn issue(
+    state: &AppState,
+    user: &db::users::User,
+    session_id: Uuid,
+    refresh_token: String,
+) -> Result<IssuedTokens> {
+    let token = super::create_token(
+        user.id,
+        &user.username,
+        session_id,
+        &state.jwt_secret,
+        state.config.access_token_minutes,
+    )?;
+    Ok(IssuedTokens {
+        token,
+        refresh_token,
+        expires_in: state.config.access_token_minutes * 60,
+    })
+}
+
+/// Open a new session for a user who just authenticated, joining any boards
+/// their address has been invited to
+pub async fn start_session(
+    state: &AppState,
+    user: &db::users::User,
+    user_agent: Option<&str>,
+) -> Result<IssuedTokens> {
+    if let Err(e) = super::email::accept_invitations(state, user).await {
+        tracing::error!("Accept invitations error: {}", e);
+    }
+    let refresh_token = generate_secret_token();
+    let expires_at = Utc::now() + Duration::days(state.config.refresh_token_days);
+    let session = db::sessions::create_session(
+        &state.pool,
+        user.id,
+        &hash_secret_token(&refresh_token),
+        user_agent,
+        expires_at,
+    )
+    .await?;
+    issue(state, user, session.id, refresh_token)
+}
+
+/// Rotate a refresh token, returning a fresh token pair
+pub async fn refresh_session(state: &AppState, refresh_token: &str) -> Result<Refresh> {
+    let hash = hash_secret_token(refresh_token);
+    let next_token = generate_secret_token();
+    let expires_at = Utc::now() + Duration::days(state.config.refresh_token_days);
+    let session = match db::sessions::rotate_session(
+        &state.pool,
+        &hash,
+        &hash_secret_token(&next_token),
+        expires_at,
+    )
+    .await?
+    {
+        Some(session) => session,
+        None => {
+            if db::sessions::revoke_reused_token(&state.pool, &hash).await? {
+                return Ok(Refresh::Reused);
+            }
+            return Ok(Refresh::Invalid);
+        }
+    };
+
+    let user = match db::users::find_by_id(&state.pool, session.user_id).await? {
+        Some(user) => user,
+        None => return Ok(Refresh::Invalid),
+    };
+    let tokens = issue(state, &user, session.id, next_token)?;
+    Ok(Refresh::Issued {
+        tokens,
+        user: Box::new(user),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_refresh_rotates_and_detects_reuse() {
+        use crate::ws::test_support::{create_user, delete_users, test_pool, test_state};
+
+        let pool = test_pool().await;
+        let user = create_user(&pool, "refresh").await;
+        let state = test_state(pool.clone());
+
+        let first = start_session(&state, &user, Some("test-agent")).await.unwrap();
+        let claims = super::super::verify_token(&first.token, &state.jwt_secret).unwrap();
+        assert_eq!(first.expires_in, 900);
+
+        let second = match refresh_session(&state, &first.refresh_token).await.unwrap() {
+            Refresh::Issued { tokens, user: refreshed } => {
+                assert_eq!(refreshed.id, user.id);
+                tokens
+            }
+            _ => panic!("expected a new token pair"),
+        };
+        assert_ne!(second.refresh_token, first.refresh_token);
+        let rotated = super::super::verify_token(&second.token, &state.jwt_secret).unwrap();
+        assert_eq!(rotated.sid, claims.sid);
+
+        // Replaying the rotated-out token ends the session for both holders
+        assert!(matches!(
+            refresh_session(&state, &first.refresh_token).await.unwrap(),
+            Refresh::Reused
+        ));
+        assert!(!db::sessions::is_session_active(&pool, claims.sid).await.unwrap());
+        assert!(matches!(
+            refresh_session(&state, &second.refresh_token).await.unwrap(),
+            Refresh::Invalid
+        ));
+        assert!(matches!(
+            refresh_session(&state, "unknown").await.unwrap(),
+            Refresh::Invalid
+        ));
+
+        delete_users(&pool, &[&user]).await;
+    }
+}
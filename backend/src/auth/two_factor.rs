@@ -0,0 +1,256 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{generate_secret_token, hash_secret_token, totp};
+use crate::db;
+use crate::ws::handler::AppState;
+
+const CHALLENGE_MINUTES: i64 = 5;
+const RECOVERY_CODES: usize = 10;
+
+/// Challenge purposes: prove a code, or enrol because 2FA is required
+pub const CHALLENGE_VERIFY: &str = "2fa";
+pub const CHALLENGE_SETUP: &str = "2fa_setup";
+
+/// Issued after a correct password when a second step is still needed. It
+/// has no session, so it is never accepted as an access token.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeClaims {
+    sub: Uuid,
+    purpose: String,
+    exp: usize,
+    iat: usize,
+}
+
+pub fn create_challenge(user_id: Uuid, purpose: &str, secret: &str) -> Result<String> {
+    let now = Utc::now();
+    let claims = ChallengeClaims {
+        sub: user_id,
+        purpose: purpose.to_string(),
+        exp: (now + Duration::minutes(CHALLENGE_MINUTES)).timestamp() as usize,
+        iat: now.timestamp() as usize,
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?)
+}
+
+/// The user a live challenge for the given purpose was issued to
+pub fn verify_challenge(token: &str, purpose: &str, secret: &str) -> Option<Uuid> {
+    let data = decode::<ChallengeClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &Validation::default(),
+    )
+    .ok()?;
+    (data.claims.purpose == purpose).then_some(data.claims.sub)
+}
+
+pub fn challenge_seconds() -> i64 {
+    CHALLENGE_MINUTES * 60
+}
+
+/// A pending secret for the user to add to their authenticator app
+#[derive(Debug, Serialize)]
+pub struct Setup {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+fn new_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let raw = generate_secret_token();
+            format!(
+                "{}-{}-{}-{}",
+                &raw[..4],
+                &raw[4..8],
+                &raw[8..12],
+                &raw[12..16]
+            )
+        })
+        .collect()
+}
+
+/// Recovery codes are accepted without dashes, spaces or capitals
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_secret_token(&normalized)
+}
+
+/// Start enrolment with a fresh secret. Returns None if 2FA is already on.
+pub async fn begin_setup(state: &AppState, user: &db::users::User) -> Result<Option<Setup>> {
+    let secret = totp::generate_secret();
+    if !db::two_factor::set_pending_secret(&state.pool, user.id, &secret).await? {
+        return Ok(None);
+    }
+    let provisioning_uri =
+        totp::provisioning_uri(&secret, &user.username, &state.config.two_factor_issuer);
+    Ok(Some(Setup {
+        secret,
+        provisioning_uri,
+    }))
+}
+
+/// Turn 2FA on once the user proves their app has the pending secret.
+/// Returns the new recovery codes, or None if the code is wrong.
+pub async fn confirm_setup(
+    state: &AppState,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<Vec<String>>> {
+    let pending = match db::two_factor::get_totp(&state.pool, user_id).await? {
+        Some(t) if t.totp_enabled_at.is_none() => t,
+        _ => return Ok(None),
+    };
+    let secret = match pending.totp_secret {
+        Some(secret) => secret,
+        None => return Ok(None),
+    };
+    let step = match totp::verify(&secret, code, now(), pending.totp_last_step) {
+        Some(step) => step,
+        None => return Ok(None),
+    };
+    if !db::two_factor::record_step(&state.pool, user_id, step).await? {
+        return Ok(None);
+    }
+
+    let codes = new_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+    db::two_factor::enable(&state.pool, user_id, &hashes).await?;
+    Ok(Some(codes))
+}
+
+/// Check a code from the authenticator app, or use up a recovery code
+pub async fn verify_code(state: &AppState, user_id: Uuid, code: &str) -> Result<bool> {
+    let enabled = match db::two_factor::get_totp(&state.pool, user_id).await? {
+        Some(t) if t.totp_enabled_at.is_some() => t,
+        _ => return Ok(false),
+    };
+    let code = code.trim();
+    if code.len() == 6 {
+        let secret = enabled.totp_secret.unwrap_or_default();
+        return match totp::verify(&secret, code, now(), enabled.totp_last_step) {
+            Some(step) => db::two_factor::record_step(&state.pool, user_id, step).await,
+            None => Ok(false),
+        };
+    }
+    db::two_factor::consume_recovery_code(&state.pool, user_id, &hash_recovery_code(code)).await
+}
+
+/// Turn 2FA off after one last code. Returns false if the code is wrong.
+pub async fn disable(state: &AppState, user_id: Uuid, code: &str) -> Result<bool> {
+    if !verify_code(state, user_id, code).await? {
+        return Ok(false);
+    }
+    db::two_factor::disable(&state.pool, user_id).await?;
+    Ok(true)
+}
+
+/// Replace the recovery codes after a code check
+pub async fn regenerate_recovery_codes(
+    state: &AppState,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<Vec<String>>> {
+    if !verify_code(state, user_id, code).await? {
+        return Ok(None);
+    }
+    let codes = new_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+    db::two_factor::replace_recovery_codes(&state.pool, user_id, &hashes).await?;
+    Ok(Some(codes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_purpose_and_secret() {
+        let user_id = Uuid::new_v4();
+        let token = create_challenge(user_id, CHALLENGE_VERIFY, "secret").unwrap();
+        assert_eq!(
+            verify_challenge(&token, CHALLENGE_VERIFY, "secret"),
+            Some(user_id)
+        );
+        assert_eq!(verify_challenge(&token, CHALLENGE_SETUP, "secret"), None);
+        assert_eq!(verify_challenge(&token, CHALLENGE_VERIFY, "other"), None);
+    }
+
+    #[test]
+    fn test_challenge_is_not_an_access_token() {
+        let token = create_challenge(Uuid::new_v4(), CHALLENGE_VERIFY, "secret").unwrap();
+        assert!(super::super::verify_token(&token, "secret").is_err());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = new_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert_eq!(codes[0].len(), 19);
+        assert_ne!(codes[0], codes[1]);
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&codes[0].replace('-', " ").to_uppercase())
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_enrol_verify_and_recover() {
+        use crate::ws::test_support::{create_user, delete_users, test_pool, test_state};
+
+        let pool = test_pool().await;
+        let user = create_user(&pool, "totp").await;
+        let state = test_state(pool.clone());
+
+        let setup = begin_setup(&state, &user).await.unwrap().unwrap();
+        assert!(setup.provisioning_uri.contains(&setup.secret));
+        // Not enabled until confirmed
+        assert!(!verify_code(&state, user.id, "000000").await.unwrap());
+
+        let code = totp::code_at(&setup.secret, now());
+        let recovery = confirm_setup(&state, user.id, &code)
+            .await
+            .unwrap()
+            .expect("setup should confirm");
+        assert_eq!(recovery.len(), RECOVERY_CODES);
+        assert!(begin_setup(&state, &user).await.unwrap().is_none());
+
+        // The code that confirmed setup cannot be replayed
+        assert!(!verify_code(&state, user.id, &code).await.unwrap());
+
+        // Recovery codes work once each
+        assert!(verify_code(&state, user.id, &recovery[0]).await.unwrap());
+        assert!(!verify_code(&state, user.id, &recovery[0]).await.unwrap());
+        assert_eq!(
+            db::two_factor::count_recovery_codes(&pool, user.id)
+                .await
+                .unwrap(),
+            RECOVERY_CODES as i64 - 1
+        );
+
+        assert!(disable(&state, user.id, &recovery[1]).await.unwrap());
+        let user = db::users::find_by_id(&pool, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!user.is_two_factor_enabled());
+
+        delete_users(&pool, &[&user]).await;
+    }
+}
@@ -6,6 +6,48 @@ pub struct Config {
     pub jwt_secret: String,
     pub host: String,
     pub port: u16,
+    pub snapshot_interval_minutes: u64,
+    pub snapshot_retention: i64,
+    pub compaction_interval_seconds: u64,
+    pub access_token_minutes: i64,
+    pub refresh_token_days: i64,
+    /// Base URL of the app, used for links in outgoing email
+    pub public_url: String,
+    pub smtp_url: Option<String>,
+    pub mail_from: String,
+    pub mail_outbox_dir: String,
+    /// OpenID Connect login is enabled when the issuer and client id are set
+    pub oidc_issuer_url: Option<String>,
+    pub oidc_client_id: Option<String>,
+    pub oidc_client_secret: Option<String>,
+    pub oidc_scopes: String,
+    /// Label for the login button
+    pub oidc_provider_name: String,
+    /// Every password login must pass a TOTP check; users without it enrol first
+    pub require_two_factor: bool,
+    /// Shown in authenticator apps next to the account name
+    pub two_factor_issuer: String,
+    /// Requests allowed per client IP, and per account named in the body,
+    /// on the public routes within each window
+    pub rate_limit_window_seconds: u64,
+    pub rate_limit_per_ip: u32,
+    pub rate_limit_per_account: u32,
+    /// `memory` for one instance, `postgres` to share limits across instances
+    pub rate_limit_store: String,
+    /// Take the client IP from the last `X-Forwarded-For` entry; only safe
+    /// behind a proxy that appends to it
+    pub trust_proxy_headers: bool,
+    /// Failed logins before an account locks; each further failure doubles
+    /// the lockout, up to the maximum
+    pub lockout_threshold: i32,
+    pub lockout_base_seconds: i64,
+    pub lockout_max_seconds: i64,
+    /// Argon2id cost for new password hashes; older hashes are upgraded at login
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    /// How long a personal data export stays downloadable
+    pub export_link_hours: i64,
 }
 
 impl Config {
@@ -20,6 +62,94 @@ impl Config {
                 .unwrap_or_else(|_| "3000".to_string())
                 .parse()
                 .context("PORT must be a valid number")?,
+            snapshot_interval_minutes: std::env::var("SNAPSHOT_INTERVAL_MINUTES")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .context("SNAPSHOT_INTERVAL_MINUTES must be a valid number")?,
+            snapshot_retention: std::env::var("SNAPSHOT_RETENTION")
+                .unwrap_or_else(|_| "50".to_string())
+                .parse()
+                .context("SNAPSHOT_RETENTION must be a valid number")?,
+            compaction_interval_seconds: std::env::var("COMPACTION_INTERVAL_SECONDS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .context("COMPACTION_INTERVAL_SECONDS must be a valid number")?,
+            access_token_minutes: std::env::var("ACCESS_TOKEN_MINUTES")
+                .unwrap_or_else(|_| "15".to_string())
+                .parse()
+                .context("ACCESS_TOKEN_MINUTES must be a valid number")?,
+            refresh_token_days: std::env::var("REFRESH_TOKEN_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("REFRESH_TOKEN_DAYS must be a valid number")?,
+            public_url: std::env::var("PUBLIC_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            smtp_url: std::env::var("SMTP_URL").ok().filter(|url| !url.is_empty()),
+            mail_from: std::env::var("MAIL_FROM")
+                .unwrap_or_else(|_| "Whiteboard <noreply@localhost>".to_string()),
+            mail_outbox_dir: std::env::var("MAIL_OUTBOX_DIR")
+                .unwrap_or_else(|_| "mail-outbox".to_string()),
+            oidc_issuer_url: std::env::var("OIDC_ISSUER_URL").ok().filter(|v| !v.is_empty()),
+            oidc_client_id: std::env::var("OIDC_CLIENT_ID").ok().filter(|v| !v.is_empty()),
+            oidc_client_secret: std::env::var("OIDC_CLIENT_SECRET")
+                .ok()
+                .filter(|v| !v.is_empty()),
+            oidc_scopes: std::env::var("OIDC_SCOPES")
+                .unwrap_or_else(|_| "openid email profile".to_string()),
+            oidc_provider_name: std::env::var("OIDC_PROVIDER_NAME")
+                .unwrap_or_else(|_| "Single sign-on".to_string()),
+            require_two_factor: std::env::var("REQUIRE_2FA")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .context("REQUIRE_2FA must be true or false")?,
+            two_factor_issuer: std::env::var("TWO_FACTOR_ISSUER")
+                .unwrap_or_else(|_| "Udstillerguide Whiteboard".to_string()),
+            rate_limit_window_seconds: std::env::var("RATE_LIMIT_WINDOW_SECONDS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .context("RATE_LIMIT_WINDOW_SECONDS must be a valid number")?,
+            rate_limit_per_ip: std::env::var("RATE_LIMIT_PER_IP")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .context("RATE_LIMIT_PER_IP must be a valid number")?,
+            rate_limit_per_account: std::env::var("RATE_LIMIT_PER_ACCOUNT")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .context("RATE_LIMIT_PER_ACCOUNT must be a valid number")?,
+            rate_limit_store: std::env::var("RATE_LIMIT_STORE")
+                .unwrap_or_else(|_| "memory".to_string()),
+            trust_proxy_headers: std::env::var("TRUST_PROXY_HEADERS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .context("TRUST_PROXY_HEADERS must be true or false")?,
+            lockout_threshold: std::env::var("LOCKOUT_THRESHOLD")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .context("LOCKOUT_THRESHOLD must be a valid number")?,
+            lockout_base_seconds: std::env::var("LOCKOUT_BASE_SECONDS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("LOCKOUT_BASE_SECONDS must be a valid number")?,
+            lockout_max_seconds: std::env::var("LOCKOUT_MAX_SECONDS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .context("LOCKOUT_MAX_SECONDS must be a valid number")?,
+            argon2_memory_kib: std::env::var("ARGON2_MEMORY_KIB")
+                .unwrap_or_else(|_| "19456".to_string())
+                .parse()
+                .context("ARGON2_MEMORY_KIB must be a valid number")?,
+            argon2_iterations: std::env::var("ARGON2_ITERATIONS")
+                .unwrap_or_else(|_| "2".to_string())
+                .parse()
+                .context("ARGON2_ITERATIONS must be a valid number")?,
+            argon2_parallelism: std::env::var("ARGON2_PARALLELISM")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .context("ARGON2_PARALLELISM must be a valid number")?,
+            export_link_hours: std::env::var("EXPORT_LINK_HOURS")
+                .unwrap_or_else(|_| "24".to_string())
+                .parse()
+                .context("EXPORT_LINK_HOURS must be a valid number")?,
         })
     }
 }
@@ -61,6 +191,59 @@ mod tests {
         let config = Config::from_env().expect("should parse config");
         assert_eq!(config.host, "0.0.0.0");
         assert_eq!(config.port, 3000);
+        assert_eq!(config.snapshot_interval_minutes, 10);
+        assert_eq!(config.snapshot_retention, 50);
+        assert_eq!(config.compaction_interval_seconds, 300);
+        assert_eq!(config.access_token_minutes, 15);
+        assert_eq!(config.refresh_token_days, 30);
+        assert_eq!(config.public_url, "http://localhost:3000");
+        assert_eq!(config.smtp_url, None);
+        assert_eq!(config.mail_from, "Whiteboard <noreply@localhost>");
+        assert_eq!(config.mail_outbox_dir, "mail-outbox");
+        assert_eq!(config.oidc_issuer_url, None);
+        assert_eq!(config.oidc_client_id, None);
+        assert_eq!(config.oidc_scopes, "openid email profile");
+        assert_eq!(config.oidc_provider_name, "Single sign-on");
+        assert!(!config.require_two_factor);
+        assert_eq!(config.two_factor_issuer, "Udstillerguide Whiteboard");
+        assert_eq!(config.rate_limit_window_seconds, 60);
+        assert_eq!(config.rate_limit_per_ip, 60);
+        assert_eq!(config.rate_limit_per_account, 10);
+        assert_eq!(config.rate_limit_store, "memory");
+        assert!(!config.trust_proxy_headers);
+        assert_eq!(config.lockout_threshold, 5);
+        assert_eq!(config.lockout_base_seconds, 30);
+        assert_eq!(config.lockout_max_seconds, 3600);
+        assert_eq!(config.argon2_memory_kib, 19456);
+        assert_eq!(config.argon2_iterations, 2);
+        assert_eq!(config.argon2_parallelism, 1);
+        assert_eq!(config.export_link_hours, 24);
+    }
+
+    #[test]
+    fn test_config_invalid_require_two_factor() {
+        let _lock = ENV_MUTEX.lock().unwrap();
+        std::env::set_var("DATABASE_URL", "postgres://localhost/test");
+        std::env::set_var("JWT_SECRET", "secret");
+        std::env::set_var("REQUIRE_2FA", "sometimes");
+
+        let result = Config::from_env();
+        assert!(result.is_err());
+
+        std::env::remove_var("REQUIRE_2FA");
+    }
+
+    #[test]
+    fn test_config_invalid_snapshot_interval() {
+        let _lock = ENV_MUTEX.lock().unwrap();
+        std::env::set_var("DATABASE_URL", "postgres://localhost/test");
+        std::env::set_var("JWT_SECRET", "secret");
+        std::env::set_var("SNAPSHOT_INTERVAL_MINUTES", "often");
+
+        let result = Config::from_env();
+        assert!(result.is_err());
+
+        std::env::remove_var("SNAPSHOT_INTERVAL_MINUTES");
     }
 
     #[test]
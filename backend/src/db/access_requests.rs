@@ -0,0 +1,146 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const PENDING: &str = "pending";
+pub const APPROVED: &str = "approved";
+pub const DENIED: &str = "denied";
+
+/// A request from someone without access to be let onto a board, with the
+/// requester's username for display
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct AccessRequest {
+    pub id: Uuid,
+    pub board_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub message: Option<String>,
+    pub status: String,
+    /// The role granted, once approved
+    pub role: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub decided_by: Option<Uuid>,
+}
+
+/// Ask for access, or replace the message of the request already pending
+pub async fn create_request(
+    pool: &PgPool,
+    board_id: Uuid,
+    user_id: Uuid,
+    message: Option<&str>,
+) -> Result<AccessRequest> {
+    let request = sqlx::query_as::<_, AccessRequest>(
+        "WITH r AS (
+             INSERT INTO access_requests (board_id, user_id, message)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (board_id, user_id) WHERE status = 'pending' DO UPDATE
+             SET message = $3, created_at = NOW()
+             RETURNING *
+         )
+         SELECT r.id, r.board_id, r.user_id, u.username, r.message, r.status, r.role,
+                r.created_at, r.decided_at, r.decided_by
+         FROM r JOIN users u ON u.id = r.user_id",
+    )
+    .bind(board_id)
+    .bind(user_id)
+    .bind(message)
+    .fetch_one(pool)
+    .await?;
+    Ok(request)
+}
+
+/// The board's requests still waiting for a decision, oldest first
+pub async fn list_pending(pool: &PgPool, board_id: Uuid) -> Result<Vec<AccessRequest>> {
+    let requests = sqlx::query_as::<_, AccessRequest>(
+        "SELECT r.id, r.board_id, r.user_id, u.username, r.message, r.status, r.role,
+                r.created_at, r.decided_at, r.decided_by
+         FROM access_requests r
+         JOIN users u ON u.id = r.user_id
+         WHERE r.board_id = $1 AND r.status = $2
+         ORDER BY r.created_at",
+    )
+    .bind(board_id)
+    .bind(PENDING)
+    .fetch_all(pool)
+    .await?;
+    Ok(requests)
+}
+
+/// Approve a pending request, making the requester a collaborator with the
+/// role. Returns `None` if there is no such pending request.
+pub async fn approve(
+    pool: &PgPool,
+    board_id: Uuid,
+    request_id: Uuid,
+    role: &str,
+    decided_by: Uuid,
+) -> Result<Option<AccessRequest>> {
+    let mut tx = pool.begin().await?;
+    let request = match decide(
+        &mut *tx,
+        board_id,
+        request_id,
+        APPROVED,
+        Some(role),
+        decided_by,
+    )
+    .await?
+    {
+        Some(request) => request,
+        None => return Ok(None),
+    };
+    sqlx::query(
+        "INSERT INTO board_collaborators (board_id, user_id, role)
+         SELECT $1, $2, $3 FROM boards WHERE id = $1 AND owner_id <> $2
+         ON CONFLICT (board_id, user_id) DO UPDATE SET role = $3",
+    )
+    .bind(board_id)
+    .bind(request.user_id)
+    .bind(role)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(request))
+}
+
+/// Deny a pending request. Returns `None` if there is no such pending request.
+pub async fn deny(
+    pool: &PgPool,
+    board_id: Uuid,
+    request_id: Uuid,
+    decided_by: Uuid,
+) -> Result<Option<AccessRequest>> {
+    decide(pool, board_id, request_id, DENIED, None, decided_by).await
+}
+
+async fn decide<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    board_id: Uuid,
+    request_id: Uuid,
+    status: &str,
+    role: Option<&str>,
+    decided_by: Uuid,
+) -> Result<Option<AccessRequest>> {
+    let request = sqlx::query_as::<_, AccessRequest>(
+        "WITH r AS (
+             UPDATE access_requests
+             SET status = $3, role = $4, decided_by = $5, decided_at = NOW()
+             WHERE id = $1 AND board_id = $2 AND status = $6
+             RETURNING *
+         )
+         SELECT r.id, r.board_id, r.user_id, u.username, r.message, r.status, r.role,
+                r.created_at, r.decided_at, r.decided_by
+         FROM r JOIN users u ON u.id = r.user_id",
+    )
+    .bind(request_id)
+    .bind(board_id)
+    .bind(status)
+    .bind(role)
+    .bind(decided_by)
+    .bind(PENDING)
+    .fetch_optional(executor)
+    .await?;
+    Ok(request)
+}
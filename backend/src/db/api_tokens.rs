@@ -0,0 +1,119 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub board_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A token as shown to its owner
+#[derive(Debug, serde::Serialize)]
+pub struct ApiTokenSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub board_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiToken> for ApiTokenSummary {
+    fn from(t: ApiToken) -> Self {
+        ApiTokenSummary {
+            id: t.id,
+            name: t.name,
+            scopes: t.scopes,
+            board_id: t.board_id,
+            created_at: t.created_at,
+            last_used_at: t.last_used_at,
+            expires_at: t.expires_at,
+        }
+    }
+}
+
+pub async fn create_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    token_hash: &str,
+    scopes: &[String],
+    board_id: Option<Uuid>,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<ApiToken> {
+    let token = sqlx::query_as::<_, ApiToken>(
+        "INSERT INTO api_tokens (user_id, name, token_hash, scopes, board_id, expires_at)
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(token_hash)
+    .bind(scopes)
+    .bind(board_id)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+    Ok(token)
+}
+
+/// Look up a live token by hash and record that it was used
+pub async fn use_token(pool: &PgPool, token_hash: &str) -> Result<Option<ApiToken>> {
+    let token = sqlx::query_as::<_, ApiToken>(
+        "UPDATE api_tokens SET last_used_at = NOW()
+         WHERE token_hash = $1 AND revoked_at IS NULL
+           AND (expires_at IS NULL OR expires_at > NOW())
+         RETURNING *",
+    )
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await?;
+    Ok(token)
+}
+
+/// A user's live tokens, newest first
+pub async fn list_tokens(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>> {
+    let tokens = sqlx::query_as::<_, ApiToken>(
+        "SELECT * FROM api_tokens
+         WHERE user_id = $1 AND revoked_at IS NULL
+           AND (expires_at IS NULL OR expires_at > NOW())
+         ORDER BY created_at DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(tokens)
+}
+
+pub async fn revoke_token(pool: &PgPool, user_id: Uuid, token_id: Uuid) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE api_tokens SET revoked_at = NOW()
+         WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+    )
+    .bind(token_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Every token a user has created, including revoked and expired ones
+pub async fn list_token_history(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>> {
+    let tokens = sqlx::query_as::<_, ApiToken>(
+        "SELECT * FROM api_tokens WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(tokens)
+}
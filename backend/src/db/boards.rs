@@ -7,6 +7,8 @@ pub struct Board {
     pub id: Uuid,
     pub name: String,
     pub owner_id: Uuid,
+    pub org_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
     pub yrs_state: Option<Vec<u8>>,
     pub thumbnail: Option<Vec<u8>>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
@@ -18,6 +20,8 @@ pub struct BoardSummary {
     pub id: Uuid,
     pub name: String,
     pub owner_id: Uuid,
+    pub org_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -28,6 +32,8 @@ impl From<Board> for BoardSummary {
             id: b.id,
             name: b.name,
             owner_id: b.owner_id,
+            org_id: b.org_id,
+            folder_id: b.folder_id,
             created_at: b.created_at,
             updated_at: b.updated_at,
         }
@@ -50,14 +56,22 @@ pub struct ShareLink {
     pub role: String,
     pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_by: Option<Uuid>,
 }
 
-pub async fn create_board(pool: &PgPool, name: &str, owner_id: Uuid) -> Result<Board> {
+/// Create a board, optionally inside an organisation
+pub async fn create_board(
+    pool: &PgPool,
+    name: &str,
+    owner_id: Uuid,
+    org_id: Option<Uuid>,
+) -> Result<Board> {
     let board = sqlx::query_as::<_, Board>(
-        "INSERT INTO boards (name, owner_id) VALUES ($1, $2) RETURNING *",
+        "INSERT INTO boards (name, owner_id, org_id) VALUES ($1, $2, $3) RETURNING *",
     )
     .bind(name)
     .bind(owner_id)
+    .bind(org_id)
     .fetch_one(pool)
     .await?;
     Ok(board)
@@ -72,18 +86,28 @@ pub async fn get_board(pool: &PgPool, board_id: Uuid) -> Result<Option<Board>> {
 }
 
 pub async fn list_boards_for_user(pool: &PgPool, user_id: Uuid) -> Result<Vec<Board>> {
-    let boards = sqlx::query_as::<_, Board>(
-        "SELECT b.* FROM boards b
+    let sql = format!(
+        "{} SELECT b.* FROM boards b
          WHERE b.owner_id = $1
          UNION
          SELECT b.* FROM boards b
          JOIN board_collaborators bc ON bc.board_id = b.id
          WHERE bc.user_id = $1
+         UNION
+         SELECT b.* FROM boards b
+         JOIN memberships m ON m.org_id = b.org_id
+         WHERE m.user_id = $1 AND m.role <> $2
+         UNION
+         SELECT b.* FROM boards b
+         JOIN visible v ON v.id = b.folder_id
          ORDER BY updated_at DESC",
-    )
-    .bind(user_id)
-    .fetch_all(pool)
-    .await?;
+        super::folders::VISIBLE_FOLDERS
+    );
+    let boards = sqlx::query_as::<_, Board>(&sql)
+        .bind(user_id)
+        .bind(super::organizations::GUEST)
+        .fetch_all(pool)
+        .await?;
     Ok(boards)
 }
 
@@ -102,21 +126,120 @@ pub async fn update_board_name(
     Ok(board)
 }
 
-pub async fn delete_board(pool: &PgPool, board_id: Uuid) -> Result<bool> {
-    let result = sqlx::query("DELETE FROM boards WHERE id = $1")
+/// Hand a board to a new owner. The previous owner stays on as an admin.
+pub async fn transfer_ownership(
+    pool: &PgPool,
+    board_id: Uuid,
+    new_owner_id: Uuid,
+) -> Result<Option<Board>> {
+    let mut tx = pool.begin().await?;
+    let board = transfer(&mut tx, board_id, new_owner_id).await?;
+    tx.commit().await?;
+    Ok(board)
+}
+
+/// Hand every board the user owns in an organisation, directly or through
+/// one of its folders, to a new owner. Returns the boards moved.
+pub async fn transfer_org_boards(
+    pool: &PgPool,
+    org_id: Uuid,
+    from_user_id: Uuid,
+    new_owner_id: Uuid,
+) -> Result<Vec<Uuid>> {
+    let mut tx = pool.begin().await?;
+    let ids: Vec<(Uuid,)> = sqlx::query_as(
+        "SELECT b.id FROM boards b
+         LEFT JOIN folders f ON f.id = b.folder_id
+         WHERE b.owner_id = $1 AND (b.org_id = $2 OR f.org_id = $2)
+         FOR UPDATE OF b",
+    )
+    .bind(from_user_id)
+    .bind(org_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    for (id,) in &ids {
+        transfer(&mut tx, *id, new_owner_id).await?;
+    }
+    tx.commit().await?;
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
+
+async fn transfer(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    board_id: Uuid,
+    new_owner_id: Uuid,
+) -> Result<Option<Board>> {
+    let previous: Option<(Uuid,)> =
+        sqlx::query_as("SELECT owner_id FROM boards WHERE id = $1 FOR UPDATE")
+            .bind(board_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+    let previous_owner_id = match previous {
+        Some((id,)) => id,
+        None => return Ok(None),
+    };
+    let board = sqlx::query_as::<_, Board>(
+        "UPDATE boards SET owner_id = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+    )
+    .bind(new_owner_id)
+    .bind(board_id)
+    .fetch_one(&mut **tx)
+    .await?;
+    sqlx::query("DELETE FROM board_collaborators WHERE board_id = $1 AND user_id = $2")
         .bind(board_id)
-        .execute(pool)
+        .bind(new_owner_id)
+        .execute(&mut **tx)
         .await?;
-    Ok(result.rows_affected() > 0)
+    sqlx::query(
+        "INSERT INTO board_collaborators (board_id, user_id, role)
+         VALUES ($1, $2, 'admin')
+         ON CONFLICT (board_id, user_id) DO UPDATE SET role = 'admin'",
+    )
+    .bind(board_id)
+    .bind(previous_owner_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(Some(board))
 }
 
-pub async fn save_yrs_state(pool: &PgPool, board_id: Uuid, state: &[u8]) -> Result<()> {
-    sqlx::query("UPDATE boards SET yrs_state = $1, updated_at = NOW() WHERE id = $2")
-        .bind(state)
+/// Move a board into a folder, or out of any with `None`
+pub async fn set_board_folder(
+    pool: &PgPool,
+    board_id: Uuid,
+    folder_id: Option<Uuid>,
+) -> Result<Option<Board>> {
+    let board = sqlx::query_as::<_, Board>(
+        "UPDATE boards SET folder_id = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+    )
+    .bind(folder_id)
+    .bind(board_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(board)
+}
+
+/// Move a board into an organisation, or out of one with `None`
+pub async fn set_board_org(
+    pool: &PgPool,
+    board_id: Uuid,
+    org_id: Option<Uuid>,
+) -> Result<Option<Board>> {
+    let board = sqlx::query_as::<_, Board>(
+        "UPDATE boards SET org_id = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+    )
+    .bind(org_id)
+    .bind(board_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(board)
+}
+
+pub async fn delete_board(pool: &PgPool, board_id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM boards WHERE id = $1")
         .bind(board_id)
         .execute(pool)
         .await?;
-    Ok(())
+    Ok(result.rows_affected() > 0)
 }
 
 pub async fn add_collaborator(
@@ -159,29 +282,77 @@ pub async fn get_collaborators(pool: &PgPool, board_id: Uuid) -> Result<Vec<Boar
     Ok(collabs)
 }
 
+#[derive(sqlx::FromRow)]
+struct AccessRow {
+    owner_id: Uuid,
+    folder_id: Option<Uuid>,
+    collaborator_role: Option<String>,
+    org_role: Option<String>,
+}
+
+/// The user's role on a board: "owner" for its owner, otherwise the strongest
+/// of their collaborator role, the role inherited from the board's
+/// organisation and the role they hold on the folder it sits in
 pub async fn user_has_access(
     pool: &PgPool,
     board_id: Uuid,
     user_id: Uuid,
 ) -> Result<Option<String>> {
-    // Check if owner
-    let board = sqlx::query_as::<_, Board>("SELECT * FROM boards WHERE id = $1 AND owner_id = $2")
-        .bind(board_id)
-        .bind(user_id)
-        .fetch_optional(pool)
-        .await?;
-    if board.is_some() {
-        return Ok(Some("owner".to_string()));
-    }
-    // Check collaborator role
-    let collab = sqlx::query_as::<_, BoardCollaborator>(
-        "SELECT * FROM board_collaborators WHERE board_id = $1 AND user_id = $2",
+    let row = sqlx::query_as::<_, AccessRow>(
+        "SELECT b.owner_id, b.folder_id, c.role AS collaborator_role, m.role AS org_role
+         FROM boards b
+         LEFT JOIN board_collaborators c ON c.board_id = b.id AND c.user_id = $2
+         LEFT JOIN memberships m ON m.org_id = b.org_id AND m.user_id = $2
+         WHERE b.id = $1",
     )
     .bind(board_id)
     .bind(user_id)
     .fetch_optional(pool)
     .await?;
-    Ok(collab.map(|c| c.role))
+    let AccessRow {
+        owner_id,
+        folder_id,
+        collaborator_role,
+        org_role,
+    } = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if owner_id == user_id {
+        return Ok(Some("owner".to_string()));
+    }
+    let inherited = org_role
+        .as_deref()
+        .and_then(super::organizations::board_role)
+        .map(str::to_string);
+    // Owning the folder makes someone an admin of the boards in it, not their owner
+    let from_folder = match folder_id {
+        Some(folder_id) => super::folders::folder_role(pool, folder_id, user_id)
+            .await?
+            .map(|role| if role == "owner" { "admin".to_string() } else { role }),
+        None => None,
+    };
+    Ok(strongest_role(
+        collaborator_role.into_iter().chain(inherited).chain(from_folder),
+    ))
+}
+
+/// Roles that can be granted on a board or folder; ownership is transferred
+pub const COLLABORATOR_ROLES: [&str; 3] = ["admin", "editor", "viewer"];
+
+fn role_rank(role: &str) -> u8 {
+    match role {
+        "owner" => 4,
+        "admin" => 3,
+        "editor" => 2,
+        "viewer" => 1,
+        _ => 0,
+    }
+}
+
+/// The most powerful of the given board roles
+pub fn strongest_role(roles: impl IntoIterator<Item = String>) -> Option<String> {
+    roles.into_iter().max_by_key(|role| role_rank(role))
 }
 
 pub async fn create_share_link(
@@ -190,14 +361,17 @@ pub async fn create_share_link(
     token: &str,
     role: &str,
     expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_by: Uuid,
 ) -> Result<ShareLink> {
     let link = sqlx::query_as::<_, ShareLink>(
-        "INSERT INTO share_links (board_id, token, role, expires_at) VALUES ($1, $2, $3, $4) RETURNING *",
+        "INSERT INTO share_links (board_id, token, role, expires_at, created_by)
+         VALUES ($1, $2, $3, $4, $5) RETURNING *",
     )
     .bind(board_id)
     .bind(token)
     .bind(role)
     .bind(expires_at)
+    .bind(created_by)
     .fetch_one(pool)
     .await?;
     Ok(link)
@@ -213,9 +387,10 @@ pub async fn get_share_link_by_token(pool: &PgPool, token: &str) -> Result<Optio
     Ok(link)
 }
 
-pub async fn delete_share_link(pool: &PgPool, link_id: Uuid) -> Result<bool> {
-    let result = sqlx::query("DELETE FROM share_links WHERE id = $1")
+pub async fn delete_share_link(pool: &PgPool, board_id: Uuid, link_id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM share_links WHERE id = $1 AND board_id = $2")
         .bind(link_id)
+        .bind(board_id)
         .execute(pool)
         .await?;
     Ok(result.rows_affected() > 0)
@@ -228,3 +403,78 @@ pub async fn get_share_links_for_board(pool: &PgPool, board_id: Uuid) -> Result<
         .await?;
     Ok(links)
 }
+
+/// Boards the user owns that nobody else collaborates on and that belong to
+/// no organisation with other members, which would be deleted along with the
+/// account
+pub async fn list_unshared_owned(pool: &PgPool, user_id: Uuid) -> Result<Vec<BoardSummary>> {
+    let sql = format!(
+        "{} SELECT * FROM boards b
+         WHERE b.owner_id = $1
+           AND NOT EXISTS (
+               SELECT 1 FROM board_collaborators c
+               WHERE c.board_id = b.id AND c.user_id <> $1
+           )
+           AND NOT EXISTS (
+               SELECT 1 FROM memberships m
+               WHERE m.org_id = b.org_id AND m.user_id <> $1 AND m.role <> $2
+           )
+           AND NOT EXISTS (SELECT 1 FROM folder_grants g WHERE g.board_id = b.id)
+         ORDER BY b.updated_at DESC",
+        super::folders::OWNED_BOARD_FOLDER_GRANTS
+    );
+    let boards = sqlx::query_as::<_, Board>(&sql)
+        .bind(user_id)
+        .bind(super::organizations::GUEST)
+        .fetch_all(pool)
+        .await?;
+    Ok(boards.into_iter().map(Into::into).collect())
+}
+
+/// A board the user collaborates on, with their role
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct Membership {
+    pub board_id: Uuid,
+    pub board_name: String,
+    pub role: String,
+    pub invited_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub async fn list_owned_boards(pool: &PgPool, user_id: Uuid) -> Result<Vec<Board>> {
+    let boards = sqlx::query_as::<_, Board>(
+        "SELECT * FROM boards WHERE owner_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(boards)
+}
+
+pub async fn list_memberships(pool: &PgPool, user_id: Uuid) -> Result<Vec<Membership>> {
+    let memberships = sqlx::query_as::<_, Membership>(
+        "SELECT c.board_id, b.name AS board_name, c.role, c.invited_at
+         FROM board_collaborators c
+         JOIN boards b ON b.id = c.board_id
+         WHERE c.user_id = $1
+         ORDER BY c.invited_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(memberships)
+}
+
+/// Share links the user created. Links from before creators were recorded
+/// count as the board owner's.
+pub async fn list_share_links_created_by(pool: &PgPool, user_id: Uuid) -> Result<Vec<ShareLink>> {
+    let links = sqlx::query_as::<_, ShareLink>(
+        "SELECT l.* FROM share_links l
+         JOIN boards b ON b.id = l.board_id
+         WHERE l.created_by = $1 OR (l.created_by IS NULL AND b.owner_id = $1)
+         ORDER BY l.created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(links)
+}
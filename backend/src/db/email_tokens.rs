@@ -0,0 +1,51 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Token purposes
+pub const VERIFY_EMAIL: &str = "verify_email";
+pub const RESET_PASSWORD: &str = "reset_password";
+pub const CHANGE_EMAIL: &str = "change_email";
+
+/// Store a new token, replacing any unused token the user has for the same purpose
+pub async fn create_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    purpose: &str,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM email_tokens WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL")
+        .bind(user_id)
+        .bind(purpose)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO email_tokens (user_id, purpose, token_hash, expires_at)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user_id)
+    .bind(purpose)
+    .bind(token_hash)
+    .bind(expires_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Mark a live token as used and return its user. Each token works once.
+pub async fn consume_token(pool: &PgPool, purpose: &str, token_hash: &str) -> Result<Option<Uuid>> {
+    let user_id: Option<(Uuid,)> = sqlx::query_as(
+        "UPDATE email_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND purpose = $2 AND used_at IS NULL AND expires_at > NOW()
+         RETURNING user_id",
+    )
+    .bind(token_hash)
+    .bind(purpose)
+    .fetch_optional(pool)
+    .await?;
+    Ok(user_id.map(|(id,)| id))
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Export statuses
+pub const PENDING: &str = "pending";
+pub const READY: &str = "ready";
+pub const FAILED: &str = "failed";
+
+/// A personal data export, built in the background and downloaded by token.
+/// Only the hash of the download token is stored.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DataExport {
+    pub id: Uuid,
+    pub status: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub async fn create_export(
+    pool: &PgPool,
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> Result<DataExport> {
+    let export = sqlx::query_as::<_, DataExport>(
+        "INSERT INTO data_exports (user_id, status, expires_at) VALUES ($1, $2, $3)
+         RETURNING id, status, created_at, completed_at, expires_at",
+    )
+    .bind(user_id)
+    .bind(PENDING)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+    Ok(export)
+}
+
+/// The user's newest export that has neither failed nor expired
+pub async fn latest_export(pool: &PgPool, user_id: Uuid) -> Result<Option<DataExport>> {
+    let export = sqlx::query_as::<_, DataExport>(
+        "SELECT id, status, created_at, completed_at, expires_at
+         FROM data_exports
+         WHERE user_id = $1 AND status <> $2 AND expires_at > NOW()
+         ORDER BY created_at DESC
+         LIMIT 1",
+    )
+    .bind(user_id)
+    .bind(FAILED)
+    .fetch_optional(pool)
+    .await?;
+    Ok(export)
+}
+
+/// Store the finished archive; the download link lasts until `expires_at`
+pub async fn complete_export(
+    pool: &PgPool,
+    id: Uuid,
+    archive: &[u8],
+    expires_at: DateTime<Utc>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE data_exports
+         SET status = $4, archive = $2, completed_at = NOW(), expires_at = $3
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(archive)
+    .bind(expires_at)
+    .bind(READY)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Set the export's download token; links with an earlier token stop working
+pub async fn set_token(pool: &PgPool, id: Uuid, token_hash: &str) -> Result<()> {
+    sqlx::query("UPDATE data_exports SET token_hash = $2 WHERE id = $1")
+        .bind(id)
+        .bind(token_hash)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn fail_export(pool: &PgPool, id: Uuid) -> Result<()> {
+    sqlx::query("UPDATE data_exports SET status = $2, completed_at = NOW() WHERE id = $1")
+        .bind(id)
+        .bind(FAILED)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The archive behind a download link, if it is ready and has not expired
+pub async fn get_archive(
+    pool: &PgPool,
+    token_hash: &str,
+) -> Result<Option<(Vec<u8>, DateTime<Utc>)>> {
+    let archive: Option<(Vec<u8>, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT archive, COALESCE(completed_at, NOW()) FROM data_exports
+         WHERE token_hash = $1 AND status = $2 AND expires_at > NOW()",
+    )
+    .bind(token_hash)
+    .bind(READY)
+    .fetch_optional(pool)
+    .await?;
+    Ok(archive)
+}
+
+/// Delete expired exports and their archives
+pub async fn delete_expired(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM data_exports WHERE expires_at <= NOW()")
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,267 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::boards::{strongest_role, Board};
+use super::organizations;
+
+/// Folders the user can see: those in their own or their organisations'
+/// spaces, plus everything below a folder they were invited to.
+/// Binds the user as `$1` and the guest role as `$2`.
+pub(crate) const VISIBLE_FOLDERS: &str = "WITH RECURSIVE visible AS (
+         SELECT f.id FROM folders f
+         LEFT JOIN memberships m ON m.org_id = f.org_id AND m.user_id = $1
+         LEFT JOIN folder_collaborators fc ON fc.folder_id = f.id AND fc.user_id = $1
+         WHERE f.owner_id = $1 OR m.role <> $2 OR fc.user_id IS NOT NULL
+         UNION
+         SELECT f.id FROM folders f JOIN visible v ON f.parent_id = v.id
+     )";
+
+/// Everyone a folder gives access to the boards owned by `$1`: the owner of
+/// the folder's space, and anyone invited to the folder or one above it.
+/// Defines `folder_grants (board_id, user_id, role, since)`.
+pub(crate) const OWNED_BOARD_FOLDER_GRANTS: &str = "WITH RECURSIVE chain AS (
+         SELECT b.id AS board_id, f.id AS folder_id, f.parent_id
+         FROM boards b JOIN folders f ON f.id = b.folder_id
+         WHERE b.owner_id = $1
+         UNION ALL
+         SELECT c.board_id, f.id, f.parent_id FROM folders f JOIN chain c ON f.id = c.parent_id
+     ),
+     folder_grants AS (
+         SELECT c.board_id, fc.user_id, fc.role, fc.invited_at AS since
+         FROM chain c JOIN folder_collaborators fc ON fc.folder_id = c.folder_id
+         WHERE fc.user_id <> $1
+         UNION ALL
+         SELECT b.id, f.owner_id, 'admin', f.created_at
+         FROM boards b JOIN folders f ON f.id = b.folder_id
+         WHERE b.owner_id = $1 AND f.owner_id <> $1
+     )";
+
+/// A folder lives in one space, a user's or an organisation's, and its
+/// subfolders share it
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct Folder {
+    pub id: Uuid,
+    pub name: String,
+    pub parent_id: Option<Uuid>,
+    pub owner_id: Option<Uuid>,
+    pub org_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct FolderCollaborator {
+    pub folder_id: Uuid,
+    pub user_id: Uuid,
+    pub role: String,
+    pub invited_at: Option<DateTime<Utc>>,
+}
+
+pub async fn create_folder(
+    pool: &PgPool,
+    name: &str,
+    parent_id: Option<Uuid>,
+    owner_id: Option<Uuid>,
+    org_id: Option<Uuid>,
+) -> Result<Folder> {
+    let folder = sqlx::query_as::<_, Folder>(
+        "INSERT INTO folders (name, parent_id, owner_id, org_id) VALUES ($1, $2, $3, $4)
+         RETURNING *",
+    )
+    .bind(name)
+    .bind(parent_id)
+    .bind(owner_id)
+    .bind(org_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(folder)
+}
+
+pub async fn get_folder(pool: &PgPool, folder_id: Uuid) -> Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>("SELECT * FROM folders WHERE id = $1")
+        .bind(folder_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(folder)
+}
+
+pub async fn list_folders_for_user(pool: &PgPool, user_id: Uuid) -> Result<Vec<Folder>> {
+    let sql = format!(
+        "{} SELECT f.* FROM folders f JOIN visible v ON v.id = f.id ORDER BY f.name",
+        VISIBLE_FOLDERS
+    );
+    let folders = sqlx::query_as::<_, Folder>(&sql)
+        .bind(user_id)
+        .bind(organizations::GUEST)
+        .fetch_all(pool)
+        .await?;
+    Ok(folders)
+}
+
+pub async fn list_children(pool: &PgPool, folder_id: Uuid) -> Result<Vec<Folder>> {
+    let folders =
+        sqlx::query_as::<_, Folder>("SELECT * FROM folders WHERE parent_id = $1 ORDER BY name")
+            .bind(folder_id)
+            .fetch_all(pool)
+            .await?;
+    Ok(folders)
+}
+
+pub async fn list_boards(pool: &PgPool, folder_id: Uuid) -> Result<Vec<Board>> {
+    let boards = sqlx::query_as::<_, Board>(
+        "SELECT * FROM boards WHERE folder_id = $1 ORDER BY updated_at DESC",
+    )
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(boards)
+}
+
+pub async fn rename_folder(pool: &PgPool, folder_id: Uuid, name: &str) -> Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>(
+        "UPDATE folders SET name = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+    )
+    .bind(name)
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(folder)
+}
+
+/// Move a folder under another, or to the top level with `None`
+pub async fn move_folder(
+    pool: &PgPool,
+    folder_id: Uuid,
+    parent_id: Option<Uuid>,
+) -> Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>(
+        "UPDATE folders SET parent_id = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+    )
+    .bind(parent_id)
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(folder)
+}
+
+/// Delete a folder and its subfolders. The boards inside stay, outside any folder.
+pub async fn delete_folder(pool: &PgPool, folder_id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM folders WHERE id = $1")
+        .bind(folder_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Whether `folder_id` is `ancestor_id` or lies somewhere below it
+pub async fn is_within(pool: &PgPool, folder_id: Uuid, ancestor_id: Uuid) -> Result<bool> {
+    let (within,): (bool,) = sqlx::query_as(
+        "WITH RECURSIVE chain AS (
+             SELECT id, parent_id FROM folders WHERE id = $1
+             UNION ALL
+             SELECT f.id, f.parent_id FROM folders f JOIN chain c ON f.id = c.parent_id
+         )
+         SELECT EXISTS (SELECT 1 FROM chain WHERE id = $2)",
+    )
+    .bind(folder_id)
+    .bind(ancestor_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(within)
+}
+
+/// Boards in the folder and all its subfolders
+pub async fn list_board_ids_in_tree(pool: &PgPool, folder_id: Uuid) -> Result<Vec<Uuid>> {
+    let ids: Vec<(Uuid,)> = sqlx::query_as(
+        "WITH RECURSIVE tree AS (
+             SELECT id FROM folders WHERE id = $1
+             UNION ALL
+             SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id
+         )
+         SELECT b.id FROM boards b JOIN tree t ON t.id = b.folder_id",
+    )
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
+
+/// The user's role on a folder: "owner" in their own space, the role their
+/// organisation membership grants in an organisation's, or the strongest
+/// role they were given on the folder or any folder above it
+pub async fn folder_role(pool: &PgPool, folder_id: Uuid, user_id: Uuid) -> Result<Option<String>> {
+    let row: Option<(Option<Uuid>, Option<String>, Vec<String>)> = sqlx::query_as(
+        "WITH RECURSIVE chain AS (
+             SELECT id, parent_id FROM folders WHERE id = $1
+             UNION ALL
+             SELECT f.id, f.parent_id FROM folders f JOIN chain c ON f.id = c.parent_id
+         )
+         SELECT f.owner_id, m.role,
+                ARRAY(
+                    SELECT fc.role FROM folder_collaborators fc
+                    JOIN chain c ON c.id = fc.folder_id
+                    WHERE fc.user_id = $2
+                )
+         FROM folders f
+         LEFT JOIN memberships m ON m.org_id = f.org_id AND m.user_id = $2
+         WHERE f.id = $1",
+    )
+    .bind(folder_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    let (owner_id, org_role, granted) = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if owner_id == Some(user_id) {
+        return Ok(Some("owner".to_string()));
+    }
+    let inherited = org_role
+        .as_deref()
+        .and_then(organizations::board_role)
+        .map(str::to_string);
+    Ok(strongest_role(granted.into_iter().chain(inherited)))
+}
+
+pub async fn add_collaborator(
+    pool: &PgPool,
+    folder_id: Uuid,
+    user_id: Uuid,
+    role: &str,
+) -> Result<FolderCollaborator> {
+    let collab = sqlx::query_as::<_, FolderCollaborator>(
+        "INSERT INTO folder_collaborators (folder_id, user_id, role)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (folder_id, user_id) DO UPDATE SET role = $3
+         RETURNING *",
+    )
+    .bind(folder_id)
+    .bind(user_id)
+    .bind(role)
+    .fetch_one(pool)
+    .await?;
+    Ok(collab)
+}
+
+pub async fn remove_collaborator(pool: &PgPool, folder_id: Uuid, user_id: Uuid) -> Result<bool> {
+    let result =
+        sqlx::query("DELETE FROM folder_collaborators WHERE folder_id = $1 AND user_id = $2")
+            .bind(folder_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_collaborators(pool: &PgPool, folder_id: Uuid) -> Result<Vec<FolderCollaborator>> {
+    let collabs = sqlx::query_as::<_, FolderCollaborator>(
+        "SELECT * FROM folder_collaborators WHERE folder_id = $1 ORDER BY invited_at",
+    )
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(collabs)
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// An invitation to a board for an address that may not have an account yet.
+/// Addresses are stored lowercased.
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub board_id: Uuid,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Invite an address, or renew the invitation it already has
+pub async fn create_invitation(
+    pool: &PgPool,
+    board_id: Uuid,
+    email: &str,
+    role: &str,
+    invited_by: Uuid,
+    expires_at: DateTime<Utc>,
+) -> Result<Invitation> {
+    let invitation = sqlx::query_as::<_, Invitation>(
+        "INSERT INTO board_invitations (board_id, email, role, invited_by, expires_at)
+         VALUES ($1, LOWER($2), $3, $4, $5)
+         ON CONFLICT (board_id, email) DO UPDATE
+         SET role = $3, invited_by = $4, expires_at = $5, created_at = NOW()
+         RETURNING *",
+    )
+    .bind(board_id)
+    .bind(email)
+    .bind(role)
+    .bind(invited_by)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+    Ok(invitation)
+}
+
+/// The board's invitations that have not expired
+pub async fn list_for_board(pool: &PgPool, board_id: Uuid) -> Result<Vec<Invitation>> {
+    let invitations = sqlx::query_as::<_, Invitation>(
+        "SELECT * FROM board_invitations
+         WHERE board_id = $1 AND expires_at > NOW()
+         ORDER BY created_at",
+    )
+    .bind(board_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(invitations)
+}
+
+pub async fn delete_invitation(pool: &PgPool, board_id: Uuid, id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM board_invitations WHERE id = $1 AND board_id = $2")
+        .bind(id)
+        .bind(board_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Turn the live invitations for the address into collaborator entries for
+/// the user, or only the one given in `only`. Boards they own are left as
+/// they are; on boards they already collaborate on the invitation can raise
+/// their role but never lower it. Returns the boards joined or upgraded,
+/// with the role.
+pub async fn accept_for_user(
+    pool: &PgPool,
+    user_id: Uuid,
+    email: &str,
+    only: Option<Uuid>,
+) -> Result<Vec<(Uuid, String)>> {
+    let joined: Vec<(Uuid, String)> = sqlx::query_as(
+        "WITH accepted AS (
+             DELETE FROM board_invitations
+             WHERE email = LOWER($2) AND expires_at > NOW() AND ($3::UUID IS NULL OR id = $3)
+             RETURNING board_id, role
+         )
+         INSERT INTO board_collaborators AS c (board_id, user_id, role)
+         SELECT a.board_id, $1, a.role
+         FROM accepted a
+         JOIN boards b ON b.id = a.board_id
+         WHERE b.owner_id <> $1
+         ON CONFLICT (board_id, user_id) DO UPDATE SET role = EXCLUDED.role
+         WHERE (CASE EXCLUDED.role WHEN 'admin' THEN 3 WHEN 'editor' THEN 2 ELSE 1 END)
+             > (CASE c.role WHEN 'admin' THEN 3 WHEN 'editor' THEN 2 ELSE 1 END)
+         RETURNING board_id, role",
+    )
+    .bind(user_id)
+    .bind(email)
+    .bind(only)
+    .fetch_all(pool)
+    .await?;
+    Ok(joined)
+}
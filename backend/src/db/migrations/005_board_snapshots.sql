@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS board_snapshots (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    board_id UUID NOT NULL REFERENCES boards(id) ON DELETE CASCADE,
+    name VARCHAR(255),
+    kind VARCHAR(20) NOT NULL DEFAULT 'manual',
+    yrs_state BYTEA NOT NULL,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_board_snapshots_board ON board_snapshots(board_id, created_at DESC);
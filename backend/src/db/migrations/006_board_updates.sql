@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS board_updates (
+    id BIGSERIAL PRIMARY KEY,
+    board_id UUID NOT NULL REFERENCES boards(id) ON DELETE CASCADE,
+    update_data BYTEA NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_board_updates_board ON board_updates(board_id, id);
@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name VARCHAR(100);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_color VARCHAR(7);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pending_email VARCHAR(255);
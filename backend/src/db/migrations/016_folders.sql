@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS folders (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(255) NOT NULL,
+    parent_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+    owner_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    org_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    CHECK ((owner_id IS NULL) <> (org_id IS NULL))
+);
+
+CREATE TABLE IF NOT EXISTS folder_collaborators (
+    folder_id UUID NOT NULL REFERENCES folders(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL DEFAULT 'editor',
+    invited_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (folder_id, user_id)
+);
+
+ALTER TABLE boards ADD COLUMN IF NOT EXISTS folder_id UUID REFERENCES folders(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_folders_parent ON folders(parent_id);
+CREATE INDEX IF NOT EXISTS idx_folders_owner ON folders(owner_id);
+CREATE INDEX IF NOT EXISTS idx_folders_org ON folders(org_id);
+CREATE INDEX IF NOT EXISTS idx_folder_collaborators_user ON folder_collaborators(user_id);
+CREATE INDEX IF NOT EXISTS idx_boards_folder ON boards(folder_id);
@@ -1,4 +1,18 @@
+pub mod access_requests;
+pub mod api_tokens;
 pub mod boards;
+pub mod email_tokens;
+pub mod exports;
+pub mod folders;
+pub mod invitations;
+pub mod notifications;
+pub mod oidc;
+pub mod organizations;
+pub mod rate_limits;
+pub mod sessions;
+pub mod snapshots;
+pub mod two_factor;
+pub mod updates;
 pub mod users;
 
 use anyhow::Result;
@@ -52,9 +66,212 @@ pub async fn run_migrations(pool: &PgPool) -> Result<()> {
             created_at TIMESTAMPTZ DEFAULT NOW()
         );
 
+        CREATE TABLE IF NOT EXISTS board_snapshots (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            board_id UUID NOT NULL REFERENCES boards(id) ON DELETE CASCADE,
+            name VARCHAR(255),
+            kind VARCHAR(20) NOT NULL DEFAULT 'manual',
+            yrs_state BYTEA NOT NULL,
+            created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        );
+
+        CREATE TABLE IF NOT EXISTS board_updates (
+            id BIGSERIAL PRIMARY KEY,
+            board_id UUID NOT NULL REFERENCES boards(id) ON DELETE CASCADE,
+            update_data BYTEA NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        );
+
+        CREATE TABLE IF NOT EXISTS sessions (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            refresh_token_hash VARCHAR(64) UNIQUE NOT NULL,
+            previous_token_hash VARCHAR(64),
+            user_agent TEXT,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            last_used_at TIMESTAMPTZ DEFAULT NOW(),
+            expires_at TIMESTAMPTZ NOT NULL,
+            revoked_at TIMESTAMPTZ
+        );
+
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS email_verified_at TIMESTAMPTZ;
+
+        CREATE TABLE IF NOT EXISTS email_tokens (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            purpose VARCHAR(32) NOT NULL,
+            token_hash VARCHAR(64) UNIQUE NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            expires_at TIMESTAMPTZ NOT NULL,
+            used_at TIMESTAMPTZ
+        );
+
+        CREATE TABLE IF NOT EXISTS user_identities (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            issuer TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            email VARCHAR(255),
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            last_login_at TIMESTAMPTZ DEFAULT NOW(),
+            UNIQUE (issuer, subject)
+        );
+
+        CREATE TABLE IF NOT EXISTS oidc_states (
+            state_hash VARCHAR(64) PRIMARY KEY,
+            code_verifier VARCHAR(128) NOT NULL,
+            nonce VARCHAR(64) NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            expires_at TIMESTAMPTZ NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS api_tokens (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name VARCHAR(100) NOT NULL,
+            token_hash VARCHAR(64) UNIQUE NOT NULL,
+            scopes TEXT[] NOT NULL,
+            board_id UUID REFERENCES boards(id) ON DELETE CASCADE,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            last_used_at TIMESTAMPTZ,
+            expires_at TIMESTAMPTZ,
+            revoked_at TIMESTAMPTZ
+        );
+
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret VARCHAR(64);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled_at TIMESTAMPTZ;
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT;
+
+        CREATE TABLE IF NOT EXISTS recovery_codes (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            code_hash VARCHAR(64) NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            used_at TIMESTAMPTZ
+        );
+
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_login_count INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
+
+        CREATE TABLE IF NOT EXISTS rate_limits (
+            key VARCHAR(320) PRIMARY KEY,
+            resets_at TIMESTAMPTZ NOT NULL,
+            hits INTEGER NOT NULL
+        );
+
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name VARCHAR(100);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_color VARCHAR(7);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS pending_email VARCHAR(255);
+
+        ALTER TABLE share_links ADD COLUMN IF NOT EXISTS created_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+        CREATE TABLE IF NOT EXISTS data_exports (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            token_hash VARCHAR(64) UNIQUE,
+            status VARCHAR(20) NOT NULL DEFAULT 'pending',
+            archive BYTEA,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            completed_at TIMESTAMPTZ,
+            expires_at TIMESTAMPTZ NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS organizations (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            name VARCHAR(255) NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            updated_at TIMESTAMPTZ DEFAULT NOW()
+        );
+
+        CREATE TABLE IF NOT EXISTS memberships (
+            org_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            role VARCHAR(20) NOT NULL DEFAULT 'member',
+            joined_at TIMESTAMPTZ DEFAULT NOW(),
+            PRIMARY KEY (org_id, user_id)
+        );
+
+        ALTER TABLE boards ADD COLUMN IF NOT EXISTS org_id UUID REFERENCES organizations(id) ON DELETE SET NULL;
+
+        CREATE TABLE IF NOT EXISTS folders (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            name VARCHAR(255) NOT NULL,
+            parent_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+            owner_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            org_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            updated_at TIMESTAMPTZ DEFAULT NOW(),
+            CHECK ((owner_id IS NULL) <> (org_id IS NULL))
+        );
+
+        CREATE TABLE IF NOT EXISTS folder_collaborators (
+            folder_id UUID NOT NULL REFERENCES folders(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            role VARCHAR(20) NOT NULL DEFAULT 'editor',
+            invited_at TIMESTAMPTZ DEFAULT NOW(),
+            PRIMARY KEY (folder_id, user_id)
+        );
+
+        ALTER TABLE boards ADD COLUMN IF NOT EXISTS folder_id UUID REFERENCES folders(id) ON DELETE SET NULL;
+
+        CREATE TABLE IF NOT EXISTS board_invitations (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            board_id UUID NOT NULL REFERENCES boards(id) ON DELETE CASCADE,
+            email VARCHAR(255) NOT NULL,
+            role VARCHAR(20) NOT NULL DEFAULT 'editor',
+            invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            expires_at TIMESTAMPTZ NOT NULL,
+            UNIQUE (board_id, email)
+        );
+
+        CREATE TABLE IF NOT EXISTS access_requests (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            board_id UUID NOT NULL REFERENCES boards(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            message TEXT,
+            status VARCHAR(20) NOT NULL DEFAULT 'pending',
+            role VARCHAR(20),
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            decided_at TIMESTAMPTZ,
+            decided_by UUID REFERENCES users(id) ON DELETE SET NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS notifications (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            kind VARCHAR(50) NOT NULL,
+            board_id UUID REFERENCES boards(id) ON DELETE CASCADE,
+            actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+            role VARCHAR(20),
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            read_at TIMESTAMPTZ
+        );
+
         CREATE INDEX IF NOT EXISTS idx_boards_owner ON boards(owner_id);
         CREATE INDEX IF NOT EXISTS idx_collaborators_user ON board_collaborators(user_id);
         CREATE INDEX IF NOT EXISTS idx_share_links_token ON share_links(token);
+        CREATE INDEX IF NOT EXISTS idx_board_snapshots_board ON board_snapshots(board_id, created_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_board_updates_board ON board_updates(board_id, id);
+        CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id);
+        CREATE INDEX IF NOT EXISTS idx_sessions_previous_token ON sessions(previous_token_hash);
+        CREATE INDEX IF NOT EXISTS idx_email_tokens_user ON email_tokens(user_id, purpose);
+        CREATE INDEX IF NOT EXISTS idx_user_identities_user ON user_identities(user_id);
+        CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens(user_id);
+        CREATE INDEX IF NOT EXISTS idx_recovery_codes_user ON recovery_codes(user_id);
+        CREATE INDEX IF NOT EXISTS idx_rate_limits_resets_at ON rate_limits(resets_at);
+        CREATE INDEX IF NOT EXISTS idx_data_exports_user ON data_exports(user_id, created_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_memberships_user ON memberships(user_id);
+        CREATE INDEX IF NOT EXISTS idx_boards_org ON boards(org_id);
+        CREATE INDEX IF NOT EXISTS idx_folders_parent ON folders(parent_id);
+        CREATE INDEX IF NOT EXISTS idx_folders_owner ON folders(owner_id);
+        CREATE INDEX IF NOT EXISTS idx_folders_org ON folders(org_id);
+        CREATE INDEX IF NOT EXISTS idx_folder_collaborators_user ON folder_collaborators(user_id);
+        CREATE INDEX IF NOT EXISTS idx_boards_folder ON boards(folder_id);
+        CREATE INDEX IF NOT EXISTS idx_board_invitations_email ON board_invitations(email);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_access_requests_pending ON access_requests(board_id, user_id) WHERE status = 'pending';
+        CREATE INDEX IF NOT EXISTS idx_notifications_user ON notifications(user_id, created_at DESC);
         "#,
     )
     .execute(pool)
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The user's request for access to a board was approved
+pub const ACCESS_APPROVED: &str = "access_approved";
+/// The user's request for access to a board was denied
+pub const ACCESS_DENIED: &str = "access_denied";
+
+/// An in-app notice for a user, with the board's name and the acting user's
+/// username filled in where they still exist
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub board_id: Option<Uuid>,
+    pub board_name: Option<String>,
+    pub actor_id: Option<Uuid>,
+    pub actor_username: Option<String>,
+    pub role: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+pub async fn create_notification(
+    pool: &PgPool,
+    user_id: Uuid,
+    kind: &str,
+    board_id: Option<Uuid>,
+    actor_id: Option<Uuid>,
+    role: Option<&str>,
+) -> Result<Uuid> {
+    let (id,): (Uuid,) = sqlx::query_as(
+        "INSERT INTO notifications (user_id, kind, board_id, actor_id, role)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING id",
+    )
+    .bind(user_id)
+    .bind(kind)
+    .bind(board_id)
+    .bind(actor_id)
+    .bind(role)
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+/// The user's notifications, newest first
+pub async fn list_for_user(
+    pool: &PgPool,
+    user_id: Uuid,
+    unread_only: bool,
+) -> Result<Vec<Notification>> {
+    let notifications = sqlx::query_as::<_, Notification>(
+        "SELECT n.id, n.user_id, n.kind, n.board_id, b.name AS board_name,
+                n.actor_id, u.username AS actor_username, n.role, n.created_at, n.read_at
+         FROM notifications n
+         LEFT JOIN boards b ON b.id = n.board_id
+         LEFT JOIN users u ON u.id = n.actor_id
+         WHERE n.user_id = $1 AND (NOT $2 OR n.read_at IS NULL)
+         ORDER BY n.created_at DESC
+         LIMIT 100",
+    )
+    .bind(user_id)
+    .bind(unread_only)
+    .fetch_all(pool)
+    .await?;
+    Ok(notifications)
+}
+
+/// Mark one of the user's notifications read
+pub async fn mark_read(pool: &PgPool, user_id: Uuid, id: Uuid) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read_at = COALESCE(read_at, NOW())
+         WHERE id = $1 AND user_id = $2",
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
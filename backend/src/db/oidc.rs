@@ -0,0 +1,109 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// An account at an external identity provider, linked to a local user
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct UserIdentity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// The secrets of a login that was sent to the provider and has not come back yet
+#[derive(Debug, sqlx::FromRow)]
+pub struct PendingLogin {
+    pub code_verifier: String,
+    pub nonce: String,
+}
+
+/// Remember a started login, dropping any that expired unused
+pub async fn create_state(
+    pool: &PgPool,
+    state_hash: &str,
+    code_verifier: &str,
+    nonce: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<()> {
+    sqlx::query("DELETE FROM oidc_states WHERE expires_at <= NOW()")
+        .execute(pool)
+        .await?;
+    sqlx::query(
+        "INSERT INTO oidc_states (state_hash, code_verifier, nonce, expires_at)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(state_hash)
+    .bind(code_verifier)
+    .bind(nonce)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Remove a live state and return its secrets. Each state works once.
+pub async fn consume_state(pool: &PgPool, state_hash: &str) -> Result<Option<PendingLogin>> {
+    let pending = sqlx::query_as::<_, PendingLogin>(
+        "DELETE FROM oidc_states WHERE state_hash = $1 AND expires_at > NOW()
+         RETURNING code_verifier, nonce",
+    )
+    .bind(state_hash)
+    .fetch_optional(pool)
+    .await?;
+    Ok(pending)
+}
+
+/// Find the identity for a provider account and record the login
+pub async fn touch_identity(
+    pool: &PgPool,
+    issuer: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<Option<UserIdentity>> {
+    let identity = sqlx::query_as::<_, UserIdentity>(
+        "UPDATE user_identities SET last_login_at = NOW(), email = COALESCE($3, email)
+         WHERE issuer = $1 AND subject = $2
+         RETURNING *",
+    )
+    .bind(issuer)
+    .bind(subject)
+    .bind(email)
+    .fetch_optional(pool)
+    .await?;
+    Ok(identity)
+}
+
+pub async fn link_identity(
+    pool: &PgPool,
+    user_id: Uuid,
+    issuer: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<UserIdentity> {
+    let identity = sqlx::query_as::<_, UserIdentity>(
+        "INSERT INTO user_identities (user_id, issuer, subject, email)
+         VALUES ($1, $2, $3, $4) RETURNING *",
+    )
+    .bind(user_id)
+    .bind(issuer)
+    .bind(subject)
+    .bind(email)
+    .fetch_one(pool)
+    .await?;
+    Ok(identity)
+}
+
+pub async fn list_identities(pool: &PgPool, user_id: Uuid) -> Result<Vec<UserIdentity>> {
+    let identities = sqlx::query_as::<_, UserIdentity>(
+        "SELECT * FROM user_identities WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(identities)
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Organisation roles, strongest first
+pub const OWNER: &str = "owner";
+pub const ADMIN: &str = "admin";
+pub const MEMBER: &str = "member";
+pub const GUEST: &str = "guest";
+pub const ROLES: [&str; 4] = [OWNER, ADMIN, MEMBER, GUEST];
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// An organisation as seen by one of its members
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct UserOrganization {
+    pub id: Uuid,
+    pub name: String,
+    pub role: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct OrgMember {
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub avatar_color: Option<String>,
+    pub role: String,
+    pub joined_at: Option<DateTime<Utc>>,
+}
+
+/// Create an organisation with the given user as its first owner
+pub async fn create_organization(
+    pool: &PgPool,
+    name: &str,
+    owner_id: Uuid,
+) -> Result<Organization> {
+    let mut tx = pool.begin().await?;
+    let org = sqlx::query_as::<_, Organization>(
+        "INSERT INTO organizations (name) VALUES ($1) RETURNING *",
+    )
+    .bind(name)
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("INSERT INTO memberships (org_id, user_id, role) VALUES ($1, $2, $3)")
+        .bind(org.id)
+        .bind(owner_id)
+        .bind(OWNER)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(org)
+}
+
+/// The organisation with the user's role in it, if they belong to it
+pub async fn get_for_user(
+    pool: &PgPool,
+    org_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<UserOrganization>> {
+    let org = sqlx::query_as::<_, UserOrganization>(
+        "SELECT o.id, o.name, m.role, o.created_at, o.updated_at
+         FROM organizations o
+         JOIN memberships m ON m.org_id = o.id
+         WHERE o.id = $1 AND m.user_id = $2",
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(org)
+}
+
+pub async fn list_for_user(pool: &PgPool, user_id: Uuid) -> Result<Vec<UserOrganization>> {
+    let orgs = sqlx::query_as::<_, UserOrganization>(
+        "SELECT o.id, o.name, m.role, o.created_at, o.updated_at
+         FROM organizations o
+         JOIN memberships m ON m.org_id = o.id
+         WHERE m.user_id = $1
+         ORDER BY o.name",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(orgs)
+}
+
+pub async fn update_name(pool: &PgPool, org_id: Uuid, name: &str) -> Result<Option<Organization>> {
+    let org = sqlx::query_as::<_, Organization>(
+        "UPDATE organizations SET name = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+    )
+    .bind(name)
+    .bind(org_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(org)
+}
+
+/// Delete an organisation. Its boards stay with their owners.
+pub async fn delete_organization(pool: &PgPool, org_id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM organizations WHERE id = $1")
+        .bind(org_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The user's role in the organisation, if they belong to it
+pub async fn get_role(pool: &PgPool, org_id: Uuid, user_id: Uuid) -> Result<Option<String>> {
+    let role: Option<(String,)> =
+        sqlx::query_as("SELECT role FROM memberships WHERE org_id = $1 AND user_id = $2")
+            .bind(org_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(role.map(|(role,)| role))
+}
+
+pub async fn list_members(pool: &PgPool, org_id: Uuid) -> Result<Vec<OrgMember>> {
+    let members = sqlx::query_as::<_, OrgMember>(
+        "SELECT u.id AS user_id, u.username, u.email, u.display_name, u.avatar_color,
+                m.role, m.joined_at
+         FROM memberships m
+         JOIN users u ON u.id = m.user_id
+         WHERE m.org_id = $1
+         ORDER BY m.joined_at, u.username",
+    )
+    .bind(org_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(members)
+}
+
+/// Add a member, or change the role of an existing one
+pub async fn set_member(pool: &PgPool, org_id: Uuid, user_id: Uuid, role: &str) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO memberships (org_id, user_id, role) VALUES ($1, $2, $3)
+         ON CONFLICT (org_id, user_id) DO UPDATE SET role = $3",
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(role)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn remove_member(pool: &PgPool, org_id: Uuid, user_id: Uuid) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM memberships WHERE org_id = $1 AND user_id = $2")
+        .bind(org_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn count_owners(pool: &PgPool, org_id: Uuid) -> Result<i64> {
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM memberships WHERE org_id = $1 AND role = $2")
+            .bind(org_id)
+            .bind(OWNER)
+            .fetch_one(pool)
+            .await?;
+    Ok(count)
+}
+
+pub async fn list_board_ids(pool: &PgPool, org_id: Uuid) -> Result<Vec<Uuid>> {
+    let ids: Vec<(Uuid,)> = sqlx::query_as("SELECT id FROM boards WHERE org_id = $1")
+        .bind(org_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
+
+/// The board role an organisation role grants on the organisation's boards.
+/// Guests only see boards they are invited to directly.
+pub fn board_role(org_role: &str) -> Option<&'static str> {
+    match org_role {
+        OWNER | ADMIN => Some("admin"),
+        MEMBER => Some("editor"),
+        _ => None,
+    }
+}
+
+/// Whether the role may manage the organisation's members and settings
+pub fn can_manage(org_role: &str) -> bool {
+    org_role == OWNER || org_role == ADMIN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_board_role() {
+        assert_eq!(board_role(OWNER), Some("admin"));
+        assert_eq!(board_role(ADMIN), Some("admin"));
+        assert_eq!(board_role(MEMBER), Some("editor"));
+        assert_eq!(board_role(GUEST), None);
+        assert_eq!(board_role("unknown"), None);
+    }
+}
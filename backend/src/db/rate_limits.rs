@@ -0,0 +1,31 @@
+use anyhow::Result;
+use sqlx::PgPool;
+
+/// Count a hit in the key's current window, starting a new window if the
+/// last one has ended. Returns the hits so far and the seconds until the
+/// window resets.
+pub async fn hit(pool: &PgPool, key: &str, window_seconds: f64) -> Result<(i32, f64)> {
+    let row: (i32, f64) = sqlx::query_as(
+        "INSERT INTO rate_limits (key, resets_at, hits)
+         VALUES ($1, NOW() + $2 * INTERVAL '1 second', 1)
+         ON CONFLICT (key) DO UPDATE SET
+             hits = CASE WHEN rate_limits.resets_at <= NOW() THEN 1
+                         ELSE rate_limits.hits + 1 END,
+             resets_at = CASE WHEN rate_limits.resets_at <= NOW() THEN EXCLUDED.resets_at
+                              ELSE rate_limits.resets_at END
+         RETURNING hits, EXTRACT(EPOCH FROM resets_at - NOW())::FLOAT8",
+    )
+    .bind(key)
+    .bind(window_seconds)
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Delete windows that have ended
+pub async fn prune(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM rate_limits WHERE resets_at <= NOW()")
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
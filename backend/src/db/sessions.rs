@@ -0,0 +1,177 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub refresh_token_hash: String,
+    pub previous_token_hash: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A session as shown to its owner
+#[derive(Debug, serde::Serialize)]
+pub struct SessionSummary {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<Session> for SessionSummary {
+    fn from(s: Session) -> Self {
+        SessionSummary {
+            id: s.id,
+            user_agent: s.user_agent,
+            created_at: s.created_at,
+            last_used_at: s.last_used_at,
+            expires_at: s.expires_at,
+        }
+    }
+}
+
+pub async fn create_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    refresh_token_hash: &str,
+    user_agent: Option<&str>,
+    expires_at: DateTime<Utc>,
+) -> Result<Session> {
+    let session = sqlx::query_as::<_, Session>(
+        "INSERT INTO sessions (user_id, refresh_token_hash, user_agent, expires_at)
+         VALUES ($1, $2, $3, $4) RETURNING *",
+    )
+    .bind(user_id)
+    .bind(refresh_token_hash)
+    .bind(user_agent)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+    Ok(session)
+}
+
+/// Swap a live session's refresh token for a new one and extend its expiry.
+/// Returns None if the token does not belong to an active session.
+pub async fn rotate_session(
+    pool: &PgPool,
+    refresh_token_hash: &str,
+    new_token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<Option<Session>> {
+    let session = sqlx::query_as::<_, Session>(
+        "UPDATE sessions
+         SET previous_token_hash = refresh_token_hash, refresh_token_hash = $2,
+             expires_at = $3, last_used_at = NOW()
+         WHERE refresh_token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW()
+         RETURNING *",
+    )
+    .bind(refresh_token_hash)
+    .bind(new_token_hash)
+    .bind(expires_at)
+    .fetch_optional(pool)
+    .await?;
+    Ok(session)
+}
+
+/// Revoke the session a rotated-out refresh token belonged to. A replayed
+/// token means it leaked, so the whole session is ended.
+pub async fn revoke_reused_token(pool: &PgPool, refresh_token_hash: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = NOW()
+         WHERE previous_token_hash = $1 AND revoked_at IS NULL",
+    )
+    .bind(refresh_token_hash)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn is_session_active(pool: &PgPool, session_id: Uuid) -> Result<bool> {
+    let active: Option<(Uuid,)> = sqlx::query_as(
+        "SELECT id FROM sessions WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW()",
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(active.is_some())
+}
+
+/// When an active session was started, which is when its user signed in
+pub async fn started_at(pool: &PgPool, session_id: Uuid) -> Result<Option<DateTime<Utc>>> {
+    let started: Option<(Option<DateTime<Utc>>,)> = sqlx::query_as(
+        "SELECT created_at FROM sessions
+         WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW()",
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(started.and_then(|(created_at,)| created_at))
+}
+
+/// A user's active sessions, most recently used first
+pub async fn list_sessions(pool: &PgPool, user_id: Uuid) -> Result<Vec<Session>> {
+    let sessions = sqlx::query_as::<_, Session>(
+        "SELECT * FROM sessions
+         WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+         ORDER BY last_used_at DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(sessions)
+}
+
+pub async fn revoke_session(pool: &PgPool, user_id: Uuid, session_id: Uuid) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = NOW()
+         WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// End every session of a user, e.g. after a password reset
+pub async fn revoke_all_sessions(pool: &PgPool, user_id: Uuid) -> Result<u64> {
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// End every session of a user except the one in use, e.g. after a password change
+pub async fn revoke_other_sessions(pool: &PgPool, user_id: Uuid, keep: Uuid) -> Result<u64> {
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = NOW()
+         WHERE user_id = $1 AND id <> $2 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(keep)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Every session a user has had, including ended ones, oldest first
+pub async fn list_session_history(pool: &PgPool, user_id: Uuid) -> Result<Vec<Session>> {
+    let sessions = sqlx::query_as::<_, Session>(
+        "SELECT * FROM sessions WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(sessions)
+}
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const KIND_MANUAL: &str = "manual";
+pub const KIND_AUTO: &str = "auto";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct BoardSnapshot {
+    pub id: Uuid,
+    pub board_id: Uuid,
+    pub name: Option<String>,
+    pub kind: String,
+    pub yrs_state: Vec<u8>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct SnapshotSummary {
+    pub id: Uuid,
+    pub board_id: Uuid,
+    pub name: Option<String>,
+    pub kind: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<BoardSnapshot> for SnapshotSummary {
+    fn from(s: BoardSnapshot) -> Self {
+        SnapshotSummary {
+            id: s.id,
+            board_id: s.board_id,
+            name: s.name,
+            kind: s.kind,
+            created_by: s.created_by,
+            created_at: s.created_at,
+        }
+    }
+}
+
+pub async fn create_snapshot(
+    pool: &PgPool,
+    board_id: Uuid,
+    name: Option<&str>,
+    kind: &str,
+    state: &[u8],
+    created_by: Option<Uuid>,
+) -> Result<BoardSnapshot> {
+    let snapshot = sqlx::query_as::<_, BoardSnapshot>(
+        "INSERT INTO board_snapshots (board_id, name, kind, yrs_state, created_by)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING *",
+    )
+    .bind(board_id)
+    .bind(name)
+    .bind(kind)
+    .bind(state)
+    .bind(created_by)
+    .fetch_one(pool)
+    .await?;
+    Ok(snapshot)
+}
+
+pub async fn list_snapshots(pool: &PgPool, board_id: Uuid) -> Result<Vec<SnapshotSummary>> {
+    let snapshots = sqlx::query_as::<_, SnapshotSummary>(
+        "SELECT id, board_id, name, kind, created_by, created_at FROM board_snapshots
+         WHERE board_id = $1
+         ORDER BY created_at DESC",
+    )
+    .bind(board_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(snapshots)
+}
+
+pub async fn get_snapshot(
+    pool: &PgPool,
+    board_id: Uuid,
+    snapshot_id: Uuid,
+) -> Result<Option<BoardSnapshot>> {
+    let snapshot = sqlx::query_as::<_, BoardSnapshot>(
+        "SELECT * FROM board_snapshots WHERE id = $1 AND board_id = $2",
+    )
+    .bind(snapshot_id)
+    .bind(board_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(snapshot)
+}
+
+pub async fn get_latest_state(pool: &PgPool, board_id: Uuid) -> Result<Option<Vec<u8>>> {
+    let state: Option<(Vec<u8>,)> = sqlx::query_as(
+        "SELECT yrs_state FROM board_snapshots
+         WHERE board_id = $1
+         ORDER BY created_at DESC
+         LIMIT 1",
+    )
+    .bind(board_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(state.map(|s| s.0))
+}
+
+/// Delete all but the newest `keep` automatic snapshots of a board
+pub async fn prune_auto_snapshots(pool: &PgPool, board_id: Uuid, keep: i64) -> Result<u64> {
+    let result = sqlx::query(
+        "DELETE FROM board_snapshots
+         WHERE board_id = $1 AND kind = $2 AND id NOT IN (
+             SELECT id FROM board_snapshots
+             WHERE board_id = $1 AND kind = $2
+             ORDER BY created_at DESC
+             LIMIT $3
+         )",
+    )
+    .bind(board_id)
+    .bind(KIND_AUTO)
+    .bind(keep)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Snapshots the user took, on any board
+pub async fn list_created_by(pool: &PgPool, user_id: Uuid) -> Result<Vec<SnapshotSummary>> {
+    let snapshots = sqlx::query_as::<_, SnapshotSummary>(
+        "SELECT id, board_id, name, kind, created_by, created_at FROM board_snapshots
+         WHERE created_by = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(snapshots)
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A user's TOTP secret, which is pending until the first code confirms it
+#[derive(Debug, sqlx::FromRow)]
+pub struct Totp {
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    pub totp_last_step: Option<i64>,
+}
+
+pub async fn get_totp(pool: &PgPool, user_id: Uuid) -> Result<Option<Totp>> {
+    let totp = sqlx::query_as::<_, Totp>(
+        "SELECT totp_secret, totp_enabled_at, totp_last_step FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(totp)
+}
+
+/// Store a new secret awaiting confirmation. Returns false if 2FA is already on.
+pub async fn set_pending_secret(pool: &PgPool, user_id: Uuid, secret: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users SET totp_secret = $2, totp_last_step = NULL, updated_at = NOW()
+         WHERE id = $1 AND totp_enabled_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(secret)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record the time step of an accepted code. Returns false if that step or a
+/// later one was already used, so each code works once.
+pub async fn record_step(pool: &PgPool, user_id: Uuid, step: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users SET totp_last_step = $2
+         WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)",
+    )
+    .bind(user_id)
+    .bind(step)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Turn 2FA on with the pending secret and replace the recovery codes
+pub async fn enable(pool: &PgPool, user_id: Uuid, recovery_code_hashes: &[String]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE users SET totp_enabled_at = NOW(), updated_at = NOW() WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    replace_recovery_codes_in(&mut tx, user_id, recovery_code_hashes).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn disable(pool: &PgPool, user_id: Uuid) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE users
+         SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL, updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn replace_recovery_codes(
+    pool: &PgPool,
+    user_id: Uuid,
+    code_hashes: &[String],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    replace_recovery_codes_in(&mut tx, user_id, code_hashes).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn replace_recovery_codes_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+    code_hashes: &[String],
+) -> Result<()> {
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO recovery_codes (user_id, code_hash)
+         SELECT $1, hash FROM UNNEST($2::VARCHAR[]) AS hash",
+    )
+    .bind(user_id)
+    .bind(code_hashes)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Mark an unused recovery code as used. Returns false if there is none.
+pub async fn consume_recovery_code(pool: &PgPool, user_id: Uuid, code_hash: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE recovery_codes SET used_at = NOW()
+         WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(code_hash)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn count_recovery_codes(pool: &PgPool, user_id: Uuid) -> Result<i64> {
+    let (count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct BoardUpdate {
+    pub id: i64,
+    pub board_id: Uuid,
+    pub update_data: Vec<u8>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Append an applied Yrs update to the board's update log. Touching the board
+/// row in the same statement makes appends wait for a running compaction.
+pub async fn append_update(pool: &PgPool, board_id: Uuid, update: &[u8]) -> Result<()> {
+    sqlx::query(
+        "WITH inserted AS (
+             INSERT INTO board_updates (board_id, update_data) VALUES ($1, $2)
+         )
+         UPDATE boards SET updated_at = NOW() WHERE id = $1",
+    )
+    .bind(board_id)
+    .bind(update)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// All updates not yet merged into `boards.yrs_state`, oldest first
+pub async fn list_updates(pool: &PgPool, board_id: Uuid) -> Result<Vec<BoardUpdate>> {
+    let updates = sqlx::query_as::<_, BoardUpdate>(
+        "SELECT * FROM board_updates WHERE board_id = $1 ORDER BY id",
+    )
+    .bind(board_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(updates)
+}
+
+/// Boards with at least `min_updates` entries in their update log
+pub async fn boards_with_updates(pool: &PgPool, min_updates: i64) -> Result<Vec<Uuid>> {
+    let boards: Vec<(Uuid,)> = sqlx::query_as(
+        "SELECT board_id FROM board_updates GROUP BY board_id HAVING COUNT(*) >= $1",
+    )
+    .bind(min_updates)
+    .fetch_all(pool)
+    .await?;
+    Ok(boards.into_iter().map(|b| b.0).collect())
+}
+
+/// Merge the update log into `boards.yrs_state`. `merge` receives the current
+/// base state and the log tail and returns the new base state. Runs in a
+/// transaction holding the board row lock, so concurrent compactions of the
+/// same board serialize and appends made meanwhile stay in the log.
+/// Returns the number of updates merged.
+pub async fn compact<F>(pool: &PgPool, board_id: Uuid, merge: F) -> Result<usize>
+where
+    F: FnOnce(Option<&[u8]>, &[Vec<u8>]) -> Result<Vec<u8>>,
+{
+    let mut tx = pool.begin().await?;
+
+    let base: Option<(Option<Vec<u8>>,)> =
+        sqlx::query_as("SELECT yrs_state FROM boards WHERE id = $1 FOR UPDATE")
+            .bind(board_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let base = match base {
+        Some((state,)) => state,
+        None => return Ok(0),
+    };
+
+    let tail: Vec<(i64, Vec<u8>)> =
+        sqlx::query_as("SELECT id, update_data FROM board_updates WHERE board_id = $1 ORDER BY id")
+            .bind(board_id)
+            .fetch_all(&mut *tx)
+            .await?;
+    let last_id = match tail.last() {
+        Some((id, _)) => *id,
+        None => return Ok(0),
+    };
+
+    let updates: Vec<Vec<u8>> = tail.into_iter().map(|(_, u)| u).collect();
+    let merged = merge(base.as_deref(), &updates)?;
+
+    sqlx::query("UPDATE boards SET yrs_state = $1 WHERE id = $2")
+        .bind(&merged)
+        .bind(board_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM board_updates WHERE board_id = $1 AND id <= $2")
+        .bind(board_id)
+        .bind(last_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(updates.len())
+}
@@ -2,6 +2,8 @@ use anyhow::Result;
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use super::organizations;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: Uuid,
@@ -10,6 +12,23 @@ pub struct User {
     pub password_hash: String,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub email_verified_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub totp_enabled_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub locked_until: Option<chrono::DateTime<chrono::Utc>>,
+    pub display_name: Option<String>,
+    pub avatar_color: Option<String>,
+    /// A new address awaiting confirmation; `email` stays in use until then
+    pub pending_email: Option<String>,
+}
+
+impl User {
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    pub fn is_two_factor_enabled(&self) -> bool {
+        self.totp_enabled_at.is_some()
+    }
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -17,14 +36,24 @@ pub struct UserPublic {
     pub id: Uuid,
     pub username: String,
     pub email: String,
+    pub email_verified: bool,
+    pub two_factor_enabled: bool,
+    pub display_name: Option<String>,
+    pub avatar_color: Option<String>,
+    pub pending_email: Option<String>,
 }
 
 impl From<User> for UserPublic {
     fn from(u: User) -> Self {
         UserPublic {
+            email_verified: u.is_email_verified(),
+            two_factor_enabled: u.is_two_factor_enabled(),
             id: u.id,
             username: u.username,
             email: u.email,
+            display_name: u.display_name,
+            avatar_color: u.avatar_color,
+            pending_email: u.pending_email,
         }
     }
 }
@@ -69,3 +98,272 @@ pub async fn find_by_id(pool: &PgPool, id: Uuid) -> Result<Option<User>> {
         .await?;
     Ok(user)
 }
+
+pub async fn mark_email_verified(pool: &PgPool, id: Uuid) -> Result<()> {
+    sqlx::query(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn update_password(pool: &PgPool, id: Uuid, password_hash: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET password_hash = $2, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .bind(password_hash)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Count a failed login and return how many have happened in a row
+pub async fn record_failed_login(pool: &PgPool, id: Uuid) -> Result<i32> {
+    let (count,): (i32,) = sqlx::query_as(
+        "UPDATE users SET failed_login_count = failed_login_count + 1 WHERE id = $1
+         RETURNING failed_login_count",
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
+
+/// Lock the account until the given time, never shortening an existing lock
+pub async fn lock_until(
+    pool: &PgPool,
+    id: Uuid,
+    until: chrono::DateTime<chrono::Utc>,
+) -> Result<()> {
+    sqlx::query("UPDATE users SET locked_until = GREATEST(locked_until, $2) WHERE id = $1")
+        .bind(id)
+        .bind(until)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn clear_failed_logins(pool: &PgPool, id: Uuid) -> Result<()> {
+    sqlx::query(
+        "UPDATE users SET failed_login_count = 0, locked_until = NULL
+         WHERE id = $1 AND (failed_login_count > 0 OR locked_until IS NOT NULL)",
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn update_profile(
+    pool: &PgPool,
+    id: Uuid,
+    display_name: Option<&str>,
+    avatar_color: Option<&str>,
+) -> Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(
+        "UPDATE users SET display_name = $2, avatar_color = $3, updated_at = NOW()
+         WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .bind(display_name)
+    .bind(avatar_color)
+    .fetch_optional(pool)
+    .await?;
+    Ok(user)
+}
+
+pub async fn set_pending_email(pool: &PgPool, id: Uuid, email: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET pending_email = $2, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .bind(email)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Switch to the pending address, which the confirmation link has verified.
+/// Returns false if there is none or someone else has taken it since.
+pub async fn apply_pending_email(pool: &PgPool, id: Uuid) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users u
+         SET email = u.pending_email, pending_email = NULL,
+             email_verified_at = NOW(), updated_at = NOW()
+         WHERE u.id = $1 AND u.pending_email IS NOT NULL
+           AND NOT EXISTS (SELECT 1 FROM users o WHERE o.email = u.pending_email)",
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// What happened to a deleted user's boards
+#[derive(Debug, Default)]
+pub struct DeletedUser {
+    /// Boards that cascaded with the user
+    pub deleted_boards: Vec<Uuid>,
+    /// Boards that passed to an organisation owner, a collaborator or someone
+    /// their folder gives access, with the new owner
+    pub transferred_boards: Vec<(Uuid, Uuid)>,
+    /// Boards the user collaborated on
+    pub left_boards: Vec<Uuid>,
+}
+
+/// Delete a user. Boards they own pass to an owner of the board's
+/// organisation, or else to a collaborator, editors first and then whoever
+/// was invited earliest; the rest cascade with the user.
+pub async fn delete_user(pool: &PgPool, id: Uuid) -> Result<DeletedUser> {
+    let mut tx = pool.begin().await?;
+    // Organisations the user alone owns pass to their longest-standing admin,
+    // or member if there is none. Those with nobody else left are deleted.
+    sqlx::query(
+        "WITH sole AS (
+             SELECT m.org_id FROM memberships m
+             WHERE m.user_id = $1 AND m.role = $2
+               AND NOT EXISTS (
+                   SELECT 1 FROM memberships o
+                   WHERE o.org_id = m.org_id AND o.role = $2 AND o.user_id <> $1
+               )
+         ),
+         successors AS (
+             SELECT DISTINCT ON (m.org_id) m.org_id, m.user_id
+             FROM memberships m
+             JOIN sole s ON s.org_id = m.org_id
+             WHERE m.user_id <> $1 AND m.role <> $3
+             ORDER BY m.org_id, (m.role = $4) DESC, m.joined_at, m.user_id
+         )
+         UPDATE memberships m SET role = $2
+         FROM successors s
+         WHERE m.org_id = s.org_id AND m.user_id = s.user_id",
+    )
+    .bind(id)
+    .bind(organizations::OWNER)
+    .bind(organizations::GUEST)
+    .bind(organizations::ADMIN)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "DELETE FROM organizations o
+         WHERE EXISTS (SELECT 1 FROM memberships m WHERE m.org_id = o.id AND m.user_id = $1)
+           AND NOT EXISTS (
+               SELECT 1 FROM memberships m
+               WHERE m.org_id = o.id AND m.user_id <> $1 AND m.role <> $2
+           )",
+    )
+    .bind(id)
+    .bind(organizations::GUEST)
+    .execute(&mut *tx)
+    .await?;
+    // Boards go to an owner of their organisation first, then to a
+    // collaborator, then to someone their folder gives access
+    let sql = format!(
+        "{},
+         candidates AS (
+             SELECT b.id AS board_id, m.user_id, 0 AS rank, m.joined_at AS since
+             FROM boards b
+             JOIN memberships m ON m.org_id = b.org_id
+             WHERE b.owner_id = $1 AND m.user_id <> $1 AND m.role = $2
+             UNION ALL
+             SELECT c.board_id, c.user_id, CASE WHEN c.role = 'editor' THEN 1 ELSE 2 END,
+                    c.invited_at
+             FROM board_collaborators c
+             JOIN boards b ON b.id = c.board_id
+             WHERE b.owner_id = $1 AND c.user_id <> $1
+             UNION ALL
+             SELECT g.board_id, g.user_id, CASE WHEN g.role = 'viewer' THEN 4 ELSE 3 END,
+                    g.since
+             FROM folder_grants g
+         ),
+         successors AS (
+             SELECT DISTINCT ON (board_id) board_id, user_id
+             FROM candidates
+             ORDER BY board_id, rank, since, user_id
+         )
+         UPDATE boards b SET owner_id = s.user_id, updated_at = NOW()
+         FROM successors s
+         WHERE b.id = s.board_id
+         RETURNING b.id, s.user_id",
+        super::folders::OWNED_BOARD_FOLDER_GRANTS
+    );
+    let transferred_boards: Vec<(Uuid, Uuid)> = sqlx::query_as(&sql)
+        .bind(id)
+        .bind(organizations::OWNER)
+        .fetch_all(&mut *tx)
+        .await?;
+    // The new owners no longer need a collaborator entry
+    for (board_id, user_id) in &transferred_boards {
+        sqlx::query("DELETE FROM board_collaborators WHERE board_id = $1 AND user_id = $2")
+            .bind(board_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let deleted_boards: Vec<(Uuid,)> = sqlx::query_as("SELECT id FROM boards WHERE owner_id = $1")
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+    let left_boards: Vec<(Uuid,)> =
+        sqlx::query_as("SELECT board_id FROM board_collaborators WHERE user_id = $1")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(DeletedUser {
+        deleted_boards: deleted_boards.into_iter().map(|(id,)| id).collect(),
+        transferred_boards,
+        left_boards: left_boards.into_iter().map(|(id,)| id).collect(),
+    })
+}
+
+/// Another user as the directory knows them
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DirectoryUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub avatar_color: Option<String>,
+    /// Whether they are in one of the searcher's organisations, rather than
+    /// only sharing a board
+    pub shares_org: bool,
+}
+
+/// Users the given user shares a board with, as owner or collaborator, and
+/// fellow members of organisations where they are more than a guest
+pub async fn list_connected_users(pool: &PgPool, user_id: Uuid) -> Result<Vec<DirectoryUser>> {
+    let users = sqlx::query_as::<_, DirectoryUser>(
+        "WITH my_boards AS (
+             SELECT id AS board_id FROM boards WHERE owner_id = $1
+             UNION
+             SELECT board_id FROM board_collaborators WHERE user_id = $1
+         ),
+         connected AS (
+             SELECT b.owner_id AS user_id, FALSE AS in_org
+             FROM boards b JOIN my_boards m ON m.board_id = b.id
+             UNION
+             SELECT c.user_id, FALSE
+             FROM board_collaborators c JOIN my_boards m ON m.board_id = c.board_id
+             UNION
+             SELECT o.user_id, TRUE FROM memberships o
+             JOIN memberships m ON m.org_id = o.org_id
+             WHERE m.user_id = $1 AND m.role <> $2
+         )
+         SELECT u.id, u.username, u.email, u.display_name, u.avatar_color,
+                BOOL_OR(c.in_org) AS shares_org
+         FROM users u
+         JOIN connected c ON c.user_id = u.id
+         WHERE u.id <> $1
+         GROUP BY u.id",
+    )
+    .bind(user_id)
+    .bind(organizations::GUEST)
+    .fetch_all(pool)
+    .await?;
+    Ok(users)
+}
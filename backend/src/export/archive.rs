@@ -0,0 +1,70 @@
+use std::io::{Cursor, Write};
+
+use anyhow::Result;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Files collected for an export, written out as a zip archive at the end
+#[derive(Default)]
+pub struct Archive {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl Archive {
+    pub fn add(&mut self, name: impl Into<String>, contents: Vec<u8>) {
+        self.files.push((name.into(), contents));
+    }
+
+    pub fn add_json(
+        &mut self,
+        name: impl Into<String>,
+        value: &impl serde::Serialize,
+    ) -> Result<()> {
+        self.add(name, serde_json::to_vec_pretty(value)?);
+        Ok(())
+    }
+
+    /// Compress everything into a zip file. This is CPU bound, so call it
+    /// off the async runtime.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        for (name, contents) in self.files {
+            zip.start_file(name, options)?;
+            zip.write_all(&contents)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let mut archive = Archive::default();
+        archive
+            .add_json("profile.json", &serde_json::json!({"username": "jane"}))
+            .unwrap();
+        archive.add("boards/1/state.yrs", vec![0, 1, 2]);
+        let bytes = archive.finish().unwrap();
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.len(), 2);
+        let mut profile = String::new();
+        zip.by_name("profile.json")
+            .unwrap()
+            .read_to_string(&mut profile)
+            .unwrap();
+        assert!(profile.contains("\"jane\""));
+        let mut state = Vec::new();
+        zip.by_name("boards/1/state.yrs")
+            .unwrap()
+            .read_to_end(&mut state)
+            .unwrap();
+        assert_eq!(state, vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,183 @@
+//! Personal data exports. An export is built in the background into a zip
+//! archive, which is then downloadable through an unguessable link until the
+//! link expires.
+
+pub mod archive;
+
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use yrs::Doc;
+
+use crate::auth::{generate_secret_token, hash_secret_token};
+use crate::db;
+use crate::db::exports::DataExport;
+use crate::ws::handler::AppState;
+use crate::ws::{elements, storage, sync};
+use archive::Archive;
+
+/// A build that has not finished by then is assumed lost, e.g. to a restart,
+/// and the next request starts over
+const BUILD_MINUTES: i64 = 30;
+const CLEANUP_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+const README: &str = "\
+Your data from Udstillerguide Whiteboard
+
+profile.json            your account, and accounts linked through single sign-on
+boards/<id>/board.json  each board you own, with its elements
+boards/<id>/state.yrs   the board's raw Yrs document state (update v1 encoding)
+memberships.json        boards you collaborate on and your role there
+share_links.json        share links you created
+activity.json           your sessions, access tokens and snapshots you took
+";
+
+/// The user's live export, or a new one started in the background
+pub async fn request_export(state: &Arc<AppState>, user_id: Uuid) -> Result<DataExport> {
+    if let Some(export) = db::exports::latest_export(&state.pool, user_id).await? {
+        return Ok(export);
+    }
+
+    let expires_at = Utc::now() + Duration::minutes(BUILD_MINUTES);
+    let export = db::exports::create_export(&state.pool, user_id, expires_at).await?;
+
+    let state = state.clone();
+    let export_id = export.id;
+    tokio::spawn(async move {
+        if let Err(e) = run_export(&state, export_id, user_id).await {
+            tracing::error!("Data export {} error: {}", export_id, e);
+            if let Err(e) = db::exports::fail_export(&state.pool, export_id).await {
+                tracing::error!("Data export {} error: {}", export_id, e);
+            }
+        }
+    });
+    Ok(export)
+}
+
+/// A new download token for a finished export. Only its hash is stored, so
+/// each call replaces the link handed out before.
+pub async fn issue_download_token(state: &AppState, export_id: Uuid) -> Result<String> {
+    let token = generate_secret_token();
+    db::exports::set_token(&state.pool, export_id, &hash_secret_token(&token)).await?;
+    Ok(token)
+}
+
+async fn run_export(state: &AppState, export_id: Uuid, user_id: Uuid) -> Result<()> {
+    let archive = collect(state, user_id).await?;
+    let bytes = tokio::task::spawn_blocking(move || archive.finish()).await??;
+    let expires_at = Utc::now() + Duration::hours(state.config.export_link_hours);
+    db::exports::complete_export(&state.pool, export_id, &bytes, expires_at).await?;
+    tracing::info!("Data export {} ready, {} bytes", export_id, bytes.len());
+    Ok(())
+}
+
+/// Gather everything stored about the user
+pub async fn collect(state: &AppState, user_id: Uuid) -> Result<Archive> {
+    let pool = &state.pool;
+    let user = db::users::find_by_id(pool, user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user {} not found", user_id))?;
+    let created_at = user.created_at;
+    let identities = db::oidc::list_identities(pool, user_id).await?;
+    let user: db::users::UserPublic = user.into();
+
+    let mut archive = Archive::default();
+    archive.add("README.txt", README.as_bytes().to_vec());
+    archive.add_json(
+        "profile.json",
+        &serde_json::json!({
+            "user": user,
+            "created_at": created_at,
+            "identities": identities,
+        }),
+    )?;
+
+    for board in db::boards::list_owned_boards(pool, user_id).await? {
+        let (elements, yrs_state) = match board_contents(state, board.id).await? {
+            Some(contents) => contents,
+            None => continue,
+        };
+        let summary: db::boards::BoardSummary = board.into();
+        let dir = format!("boards/{}", summary.id);
+        archive.add_json(
+            format!("{}/board.json", dir),
+            &serde_json::json!({
+                "board": summary,
+                "elements": elements,
+            }),
+        )?;
+        archive.add(format!("{}/state.yrs", dir), yrs_state);
+    }
+
+    archive.add_json(
+        "memberships.json",
+        &db::boards::list_memberships(pool, user_id).await?,
+    )?;
+    archive.add_json(
+        "share_links.json",
+        &db::boards::list_share_links_created_by(pool, user_id).await?,
+    )?;
+
+    let sessions: Vec<db::sessions::SessionSummary> =
+        db::sessions::list_session_history(pool, user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+    let tokens: Vec<db::api_tokens::ApiTokenSummary> =
+        db::api_tokens::list_token_history(pool, user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+    archive.add_json(
+        "activity.json",
+        &serde_json::json!({
+            "sessions": sessions,
+            "access_tokens": tokens,
+            "snapshots": db::snapshots::list_created_by(pool, user_id).await?,
+        }),
+    )?;
+    Ok(archive)
+}
+
+/// A board's elements and full document state, including edits in an open
+/// room that have not been persisted yet
+async fn board_contents(
+    state: &AppState,
+    board_id: Uuid,
+) -> Result<Option<(Vec<serde_json::Value>, Vec<u8>)>> {
+    if let Some(room) = state.room_manager.get_room(&board_id).await {
+        let doc = room.doc.read().await;
+        return Ok(Some((
+            elements::get_elements(&doc),
+            sync::encode_doc_state(&doc),
+        )));
+    }
+    let doc = Doc::new();
+    if !storage::load_board(&state.pool, board_id, &doc).await? {
+        return Ok(None);
+    }
+    Ok(Some((
+        elements::get_elements(&doc),
+        sync::encode_doc_state(&doc),
+    )))
+}
+
+/// Delete expired exports at startup and then hourly, so archives do not pile up
+pub fn spawn_cleanup(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match db::exports::delete_expired(&state.pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("Deleted {} expired data exports", n),
+                Err(e) => tracing::error!("Data export cleanup error: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,38 @@
+pub mod outbox;
+pub mod smtp;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::config::Config;
+
+/// A plain-text message to a single recipient
+#[derive(Debug, Clone, Serialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers outgoing email
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<()>;
+}
+
+/// SMTP when `SMTP_URL` is set, otherwise a file outbox for local development
+pub fn from_config(config: &Config) -> Result<Arc<dyn Mailer>> {
+    match &config.smtp_url {
+        Some(url) => Ok(Arc::new(smtp::SmtpMailer::new(url, &config.mail_from)?)),
+        None => {
+            tracing::info!(
+                "SMTP_URL not set, writing email to {}",
+                config.mail_outbox_dir
+            );
+            Ok(Arc::new(outbox::Outbox::to_dir(&config.mail_outbox_dir)))
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{Email, Mailer};
+
+/// Keeps email instead of delivering it: written to a directory for local
+/// development, or held in memory for tests.
+#[derive(Default)]
+pub struct Outbox {
+    dir: Option<PathBuf>,
+    sent: Mutex<Vec<Email>>,
+}
+
+impl Outbox {
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Outbox::default()
+    }
+
+    pub fn to_dir(dir: impl Into<PathBuf>) -> Self {
+        Outbox {
+            dir: Some(dir.into()),
+            sent: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Every message sent so far, oldest first
+    #[cfg(test)]
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for Outbox {
+    async fn send(&self, email: &Email) -> Result<()> {
+        if let Some(dir) = &self.dir {
+            tokio::fs::create_dir_all(dir).await?;
+            let name = format!(
+                "{}-{}.eml",
+                chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+                uuid::Uuid::new_v4().simple()
+            );
+            let contents = format!(
+                "To: {}\nSubject: {}\n\n{}\n",
+                email.to, email.subject, email.body
+            );
+            let path = dir.join(name);
+            tokio::fs::write(&path, contents).await?;
+            tracing::info!("Wrote email to {} into {}", email.to, path.display());
+        } else {
+            self.sent.lock().unwrap().push(email.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email() -> Email {
+        Email {
+            to: "alice@example.com".to_string(),
+            subject: "Hello".to_string(),
+            body: "Line one\nLine two".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_outbox_records_messages() {
+        let outbox = Outbox::in_memory();
+        outbox.send(&email()).await.unwrap();
+        let sent = outbox.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "alice@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_file_outbox_writes_messages() {
+        let dir = std::env::temp_dir().join(format!("outbox-{}", uuid::Uuid::new_v4()));
+        let outbox = Outbox::to_dir(&dir);
+        outbox.send(&email()).await.unwrap();
+
+        let mut entries = std::fs::read_dir(&dir).unwrap();
+        let path = entries.next().unwrap().unwrap().path();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("To: alice@example.com\nSubject: Hello\n\n"));
+        assert!(contents.contains("Line two"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
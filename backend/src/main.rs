@@ -2,21 +2,28 @@ mod api;
 mod auth;
 mod config;
 mod db;
+mod export;
+mod mail;
+mod model;
+mod ratelimit;
 mod ws;
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{
     middleware,
-    routing::{delete, get, post, put},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 
+use auth::api_tokens::Scope;
 use ws::handler::AppState;
 use ws::room::RoomManager;
+use ws::shutdown::Shutdown;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -32,6 +39,8 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let config = config::Config::from_env()?;
+    // Refuse to start with Argon2 parameters that cannot hash
+    auth::password::params(&config)?;
 
     // Create database pool
     let pool = db::create_pool(&config.database_url).await?;
@@ -39,72 +48,69 @@ async fn main() -> anyhow::Result<()> {
     // Run migrations
     db::run_migrations(&pool).await?;
 
+    let mailer = mail::from_config(&config)?;
+    let rate_limiter = ratelimit::from_config(&config, &pool)?;
+
     // Create shared state
     let state = Arc::new(AppState {
         pool,
         room_manager: RoomManager::new(),
         jwt_secret: config.jwt_secret.clone(),
+        config: config.clone(),
+        mailer,
+        shutdown: Shutdown::new(),
     });
 
+    // Periodic automatic snapshots of open boards
+    ws::snapshots::spawn_periodic_snapshots(state.clone());
+
+    // Merge update logs into the compacted board state in the background
+    ws::storage::spawn_compaction(state.clone());
+
+    // Delete expired personal data exports
+    export::spawn_cleanup(state.clone());
+
+    // Drop ended rate limit windows
+    ratelimit::spawn_pruning(rate_limiter.clone(), &config);
+
     // CORS configuration
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
-    // Auth middleware layer - injects jwt_secret into extensions
-    let jwt_secret = config.jwt_secret.clone();
-    let inject_secret =
-        middleware::from_fn(move |mut request: axum::extract::Request, next: middleware::Next| {
-            let secret = jwt_secret.clone();
-            async move {
-                request.extensions_mut().insert(secret);
-                next.run(request).await
-            }
-        });
-
-    // Public routes (no auth required)
+    // Public routes (no auth required), limited per client IP and per account
     let public_routes = Router::new()
         .route("/api/auth/register", post(api::users::register))
         .route("/api/auth/login", post(api::users::login))
+        .route("/api/auth/refresh", post(api::users::refresh))
+        .route("/api/auth/verify-email", post(api::users::verify_email))
         .route(
-            "/api/share/{token}",
-            get(api::boards::get_board_by_share_token),
-        );
-
-    // Protected routes (auth required)
-    let protected_routes = Router::new()
-        .route("/api/me", get(api::users::me))
-        .route("/api/boards", get(api::boards::list_boards))
-        .route("/api/boards", post(api::boards::create_board))
-        .route("/api/boards/{id}", get(api::boards::get_board))
-        .route("/api/boards/{id}", put(api::boards::update_board))
-        .route("/api/boards/{id}", delete(api::boards::delete_board))
-        .route(
-            "/api/boards/{id}/collaborators",
-            post(api::boards::add_collaborator),
+            "/api/auth/forgot-password",
+            post(api::users::forgot_password),
         )
+        .route("/api/auth/reset-password", post(api::users::reset_password))
+        .route("/api/auth/2fa/verify", post(api::two_factor::verify))
+        .route("/api/auth/2fa/setup", post(api::two_factor::login_setup))
         .route(
-            "/api/boards/{board_id}/collaborators/{user_id}",
-            delete(api::boards::remove_collaborator),
+            "/api/auth/2fa/setup/confirm",
+            post(api::two_factor::login_setup_confirm),
         )
+        .route("/api/auth/oidc", get(api::oidc::provider))
+        .route("/api/auth/oidc/login", get(api::oidc::login))
+        .route("/api/auth/oidc/callback", get(api::oidc::callback))
+        .route("/api/exports/:token", get(api::export::download_export))
         .route(
-            "/api/boards/{id}/share-links",
-            post(api::boards::create_share_link),
-        )
-        .route(
-            "/api/boards/{id}/share-links",
-            get(api::boards::get_share_links),
-        )
-        .route(
-            "/api/boards/{board_id}/share-links/{link_id}",
-            delete(api::boards::delete_share_link),
+            "/api/share/:token",
+            get(api::boards::get_board_by_share_token),
         )
-        .layer(middleware::from_fn(auth::middleware::auth_middleware))
-        .layer(inject_secret);
+        .layer(ratelimit::RateLimitLayer::new(rate_limiter, &config));
+
+    // Protected routes (auth required)
+    let protected_routes = protected_routes(state.clone());
 
     // WebSocket route
-    let ws_routes = Router::new().route("/ws/{board_id}", get(ws::handler::ws_handler));
+    let ws_routes = Router::new().route("/ws/:board_id", get(ws::handler::ws_handler));
 
     // Static file serving
     let static_service = ServeDir::new("static").append_index_html_on_directories(true);
@@ -116,13 +122,240 @@ async fn main() -> anyhow::Result<()> {
         .fallback_service(static_service)
         .layer(cors)
         .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .with_state(state.clone());
 
     let addr = format!("{}:{}", config.host, config.port);
     tracing::info!("Starting server on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    // Connection info gives the rate limiter the client IP
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(ws::shutdown::graceful(state, ws::shutdown::signal()))
+    .await?;
+    tracing::info!("Server stopped");
 
     Ok(())
 }
+
+/// Routes that need a session or personal access token. Each group states
+/// the scope a personal access token needs for it.
+fn protected_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let scope = |scope| middleware::from_fn_with_state(scope, auth::middleware::require_scope);
+
+    let account_routes = Router::new()
+        .route("/api/me", get(api::users::me))
+        .route("/api/me", patch(api::account::update_profile))
+        .route("/api/me", delete(api::account::delete_account))
+        .route("/api/me/password", post(api::account::change_password))
+        .route("/api/me/email", post(api::account::change_email))
+        .route("/api/me/export", get(api::export::export_data))
+        .route("/api/auth/logout", post(api::users::logout))
+        .route(
+            "/api/auth/resend-verification",
+            post(api::users::resend_verification),
+        )
+        .route("/api/me/sessions", get(api::sessions::list_sessions))
+        .route(
+            "/api/me/sessions/:id",
+            delete(api::sessions::delete_session),
+        )
+        .route(
+            "/api/me/notifications",
+            get(api::notifications::list_notifications),
+        )
+        .route(
+            "/api/me/notifications/:id/read",
+            post(api::notifications::mark_read),
+        )
+        .route("/api/me/tokens", get(api::tokens::list_tokens))
+        .route("/api/me/tokens", post(api::tokens::create_token))
+        .route("/api/me/tokens/:id", delete(api::tokens::delete_token))
+        .route("/api/me/2fa", get(api::two_factor::status))
+        .route("/api/me/2fa/setup", post(api::two_factor::setup))
+        .route("/api/me/2fa/confirm", post(api::two_factor::confirm))
+        .route("/api/me/2fa/disable", post(api::two_factor::disable))
+        .route(
+            "/api/me/2fa/recovery-codes",
+            post(api::two_factor::regenerate_recovery_codes),
+        )
+        .route_layer(scope(Scope::Admin));
+
+    let read_routes = Router::new()
+        .route("/api/boards", get(api::boards::list_boards))
+        .route("/api/users/search", get(api::directory::search_users))
+        .route("/api/orgs", get(api::organizations::list_organizations))
+        .route("/api/folders", get(api::folders::list_folders))
+        .route("/api/folders/:folder_id", get(api::folders::get_folder))
+        .route(
+            "/api/folders/:folder_id/collaborators",
+            get(api::folders::list_collaborators),
+        )
+        .route(
+            "/api/orgs/:org_id",
+            get(api::organizations::get_organization),
+        )
+        .route(
+            "/api/orgs/:org_id/members",
+            get(api::organizations::list_members),
+        )
+        .route("/api/boards/:board_id", get(api::boards::get_board))
+        .route(
+            "/api/boards/:board_id/elements",
+            get(api::elements::list_elements),
+        )
+        .route(
+            "/api/boards/:board_id/snapshots",
+            get(api::snapshots::list_snapshots),
+        )
+        .route(
+            "/api/boards/:board_id/snapshots/:snapshot_id",
+            get(api::snapshots::get_snapshot),
+        )
+        .route_layer(scope(Scope::BoardsRead));
+
+    let write_routes = Router::new()
+        .route("/api/boards", post(api::boards::create_board))
+        .route("/api/orgs", post(api::organizations::create_organization))
+        .route("/api/folders", post(api::folders::create_folder))
+        .route("/api/folders/:folder_id", put(api::folders::rename_folder))
+        .route("/api/boards/:board_id", put(api::boards::update_board))
+        .route(
+            "/api/boards/:board_id/elements",
+            post(api::elements::create_element),
+        )
+        .route(
+            "/api/boards/:board_id/elements/:element_id",
+            patch(api::elements::update_element),
+        )
+        .route(
+            "/api/boards/:board_id/elements/:element_id",
+            delete(api::elements::delete_element),
+        )
+        .route(
+            "/api/boards/:board_id/snapshots",
+            post(api::snapshots::create_snapshot),
+        )
+        .route(
+            "/api/boards/:board_id/snapshots/:snapshot_id/restore",
+            post(api::snapshots::restore_snapshot),
+        )
+        .route(
+            "/api/boards/:board_id/access-requests",
+            post(api::access_requests::request_access),
+        )
+        .route_layer(scope(Scope::BoardsWrite));
+
+    // Deleting, sharing and filing boards, and managing organisations and folders
+    let admin_routes = Router::new()
+        .route("/api/boards/:board_id", delete(api::boards::delete_board))
+        .route(
+            "/api/boards/:board_id/folder",
+            put(api::boards::set_board_folder),
+        )
+        .route(
+            "/api/boards/:board_id/transfer",
+            post(api::boards::transfer_board),
+        )
+        .route(
+            "/api/boards/:board_id/org",
+            put(api::boards::set_board_org),
+        )
+        .route(
+            "/api/boards/:board_id/collaborators",
+            post(api::boards::add_collaborator),
+        )
+        .route(
+            "/api/boards/:board_id/collaborators/:user_id",
+            delete(api::boards::remove_collaborator),
+        )
+        .route(
+            "/api/boards/:board_id/share-links",
+            post(api::boards::create_share_link),
+        )
+        .route(
+            "/api/boards/:board_id/share-links",
+            get(api::boards::get_share_links),
+        )
+        .route(
+            "/api/boards/:board_id/share-links/:link_id",
+            delete(api::boards::delete_share_link),
+        )
+        .route(
+            "/api/boards/:board_id/invitations",
+            post(api::invitations::create_invitation),
+        )
+        .route(
+            "/api/boards/:board_id/invitations",
+            get(api::invitations::list_invitations),
+        )
+        .route(
+            "/api/boards/:board_id/invitations/:invitation_id",
+            delete(api::invitations::revoke_invitation),
+        )
+        .route(
+            "/api/boards/:board_id/access-requests",
+            get(api::access_requests::list_access_requests),
+        )
+        .route(
+            "/api/boards/:board_id/access-requests/:request_id/approve",
+            post(api::access_requests::approve_access_request),
+        )
+        .route(
+            "/api/boards/:board_id/access-requests/:request_id/deny",
+            post(api::access_requests::deny_access_request),
+        )
+        .route(
+            "/api/orgs/:org_id",
+            put(api::organizations::update_organization),
+        )
+        .route(
+            "/api/orgs/:org_id",
+            delete(api::organizations::delete_organization),
+        )
+        .route(
+            "/api/orgs/:org_id/members",
+            post(api::organizations::add_member),
+        )
+        .route(
+            "/api/orgs/:org_id/members/:user_id",
+            patch(api::organizations::update_member),
+        )
+        .route(
+            "/api/orgs/:org_id/members/:user_id",
+            delete(api::organizations::remove_member),
+        )
+        .route(
+            "/api/orgs/:org_id/members/:user_id/transfer",
+            post(api::organizations::transfer_boards),
+        )
+        .route(
+            "/api/folders/:folder_id",
+            delete(api::folders::delete_folder),
+        )
+        .route(
+            "/api/folders/:folder_id/parent",
+            put(api::folders::move_folder),
+        )
+        .route(
+            "/api/folders/:folder_id/collaborators",
+            post(api::folders::add_collaborator),
+        )
+        .route(
+            "/api/folders/:folder_id/collaborators/:user_id",
+            delete(api::folders::remove_collaborator),
+        )
+        .route_layer(scope(Scope::Admin));
+
+    Router::new()
+        .merge(account_routes)
+        .merge(read_routes)
+        .merge(write_routes)
+        .merge(admin_routes)
+        .layer(middleware::from_fn_with_state(
+            state,
+            auth::middleware::auth_middleware,
+        ))
+}
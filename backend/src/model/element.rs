@@ -0,0 +1,512 @@
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of points accepted in a freehand drawing
+pub const MAX_DRAWING_POINTS: usize = 10_000;
+/// Maximum length of element ids
+pub const MAX_ID_LEN: usize = 128;
+/// Maximum length of text content and labels
+pub const MAX_TEXT_LEN: usize = 10_000;
+/// Coordinates are clamped to +/- this value
+pub const MAX_COORDINATE: f64 = 1_000_000.0;
+
+const MAX_STROKE_WIDTH: f64 = 100.0;
+const MIN_FONT_SIZE: f64 = 1.0;
+const MAX_FONT_SIZE: f64 = 500.0;
+const MIN_STAR_POINTS: u32 = 3;
+const MAX_STAR_POINTS: u32 = 50;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ElementError {
+    #[error("invalid element: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("element id must be 1-{MAX_ID_LEN} characters")]
+    InvalidId,
+    #[error("field `{0}` must be a finite number")]
+    NotFinite(&'static str),
+    #[error("drawing has more than {MAX_DRAWING_POINTS} points")]
+    TooManyPoints,
+    #[error("field `{0}` exceeds {MAX_TEXT_LEN} characters")]
+    TextTooLong(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sticky {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub color: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default = "default_sticky_font_size")]
+    pub font_size: f64,
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+/// Rect, circle, triangle, diamond and hexagon share the same geometry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shape {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub color: String,
+    #[serde(default = "default_fill")]
+    pub fill: String,
+    #[serde(default = "default_stroke_width")]
+    pub stroke_width: f64,
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Star {
+    #[serde(flatten)]
+    pub shape: Shape,
+    #[serde(default = "default_star_points")]
+    pub points: u32,
+}
+
+/// Lines and arrows run from (x, y) to (x2, y2)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Line {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub x2: f64,
+    pub y2: f64,
+    pub color: String,
+    #[serde(default = "default_stroke_width")]
+    pub stroke_width: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Drawing {
+    pub id: String,
+    pub points: Vec<Point>,
+    pub color: String,
+    #[serde(default = "default_stroke_width")]
+    pub stroke_width: f64,
+    #[serde(default)]
+    pub x: f64,
+    #[serde(default)]
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Text {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    #[serde(default)]
+    pub content: String,
+    pub color: String,
+    #[serde(default = "default_text_font_size")]
+    pub font_size: f64,
+    #[serde(default)]
+    pub width: f64,
+    #[serde(default)]
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextBox {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub color: String,
+    #[serde(default = "default_textbox_fill")]
+    pub fill: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default = "default_sticky_font_size")]
+    pub font_size: f64,
+    #[serde(default = "default_textbox_stroke_width")]
+    pub stroke_width: f64,
+    #[serde(default)]
+    pub rotation: f64,
+    #[serde(default = "default_border_color")]
+    pub border_color: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Connector {
+    pub id: String,
+    pub source_id: Option<String>,
+    pub target_id: Option<String>,
+    #[serde(default = "default_anchor")]
+    pub source_anchor: String,
+    #[serde(default = "default_anchor")]
+    pub target_anchor: String,
+    #[serde(default)]
+    pub start_arrow: bool,
+    #[serde(default = "default_true")]
+    pub end_arrow: bool,
+    #[serde(default)]
+    pub label: String,
+    pub color: String,
+    #[serde(default = "default_stroke_width")]
+    pub stroke_width: f64,
+    #[serde(default = "default_line_style")]
+    pub line_style: String,
+    // Fallback positions if source/target is deleted
+    #[serde(default)]
+    pub x: f64,
+    #[serde(default)]
+    pub y: f64,
+    #[serde(default)]
+    pub x2: f64,
+    #[serde(default)]
+    pub y2: f64,
+}
+
+/// A whiteboard element, mirroring the factory functions in `static/js/canvas.js`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Element {
+    Sticky(Sticky),
+    Rect(Shape),
+    Circle(Shape),
+    Triangle(Shape),
+    Diamond(Shape),
+    Star(Star),
+    Hexagon(Shape),
+    Line(Line),
+    Arrow(Line),
+    Drawing(Drawing),
+    Text(Text),
+    Textbox(TextBox),
+    Connector(Connector),
+}
+
+fn default_fill() -> String {
+    "transparent".to_string()
+}
+
+fn default_textbox_fill() -> String {
+    "#FFFFFF".to_string()
+}
+
+fn default_border_color() -> String {
+    "#cccccc".to_string()
+}
+
+fn default_anchor() -> String {
+    "auto".to_string()
+}
+
+fn default_line_style() -> String {
+    "straight".to_string()
+}
+
+fn default_stroke_width() -> f64 {
+    2.0
+}
+
+fn default_textbox_stroke_width() -> f64 {
+    1.0
+}
+
+fn default_sticky_font_size() -> f64 {
+    14.0
+}
+
+fn default_text_font_size() -> f64 {
+    16.0
+}
+
+fn default_star_points() -> u32 {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn coordinate(field: &'static str, value: &mut f64) -> Result<(), ElementError> {
+    if !value.is_finite() {
+        return Err(ElementError::NotFinite(field));
+    }
+    *value = value.clamp(-MAX_COORDINATE, MAX_COORDINATE);
+    Ok(())
+}
+
+fn size(field: &'static str, value: &mut f64) -> Result<(), ElementError> {
+    if !value.is_finite() {
+        return Err(ElementError::NotFinite(field));
+    }
+    *value = value.clamp(0.0, MAX_COORDINATE);
+    Ok(())
+}
+
+fn clamped(field: &'static str, value: &mut f64, min: f64, max: f64) -> Result<(), ElementError> {
+    if !value.is_finite() {
+        return Err(ElementError::NotFinite(field));
+    }
+    *value = value.clamp(min, max);
+    Ok(())
+}
+
+fn text(field: &'static str, value: &str) -> Result<(), ElementError> {
+    if value.chars().count() > MAX_TEXT_LEN {
+        return Err(ElementError::TextTooLong(field));
+    }
+    Ok(())
+}
+
+fn rotation(value: &mut f64) -> Result<(), ElementError> {
+    if !value.is_finite() {
+        return Err(ElementError::NotFinite("rotation"));
+    }
+    *value %= 360.0;
+    Ok(())
+}
+
+impl Shape {
+    fn validate(&mut self) -> Result<(), ElementError> {
+        coordinate("x", &mut self.x)?;
+        coordinate("y", &mut self.y)?;
+        size("width", &mut self.width)?;
+        size("height", &mut self.height)?;
+        clamped("strokeWidth", &mut self.stroke_width, 0.0, MAX_STROKE_WIDTH)?;
+        rotation(&mut self.rotation)
+    }
+}
+
+impl Line {
+    fn validate(&mut self) -> Result<(), ElementError> {
+        coordinate("x", &mut self.x)?;
+        coordinate("y", &mut self.y)?;
+        coordinate("x2", &mut self.x2)?;
+        coordinate("y2", &mut self.y2)?;
+        clamped("strokeWidth", &mut self.stroke_width, 0.0, MAX_STROKE_WIDTH)
+    }
+}
+
+impl Element {
+    /// Parse and validate an element from its JSON representation
+    pub fn from_json(value: serde_json::Value) -> Result<Self, ElementError> {
+        let mut element: Element = serde_json::from_value(value)?;
+        element.validate()?;
+        Ok(element)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            Element::Sticky(e) => &e.id,
+            Element::Rect(e)
+            | Element::Circle(e)
+            | Element::Triangle(e)
+            | Element::Diamond(e)
+            | Element::Hexagon(e) => &e.id,
+            Element::Star(e) => &e.shape.id,
+            Element::Line(e) | Element::Arrow(e) => &e.id,
+            Element::Drawing(e) => &e.id,
+            Element::Text(e) => &e.id,
+            Element::Textbox(e) => &e.id,
+            Element::Connector(e) => &e.id,
+        }
+    }
+
+    /// Reject values that cannot be rendered and clamp the rest into range
+    pub fn validate(&mut self) -> Result<(), ElementError> {
+        let id = self.id();
+        if id.is_empty() || id.len() > MAX_ID_LEN {
+            return Err(ElementError::InvalidId);
+        }
+
+        match self {
+            Element::Sticky(e) => {
+                coordinate("x", &mut e.x)?;
+                coordinate("y", &mut e.y)?;
+                size("width", &mut e.width)?;
+                size("height", &mut e.height)?;
+                clamped("fontSize", &mut e.font_size, MIN_FONT_SIZE, MAX_FONT_SIZE)?;
+                rotation(&mut e.rotation)?;
+                text("content", &e.content)
+            }
+            Element::Rect(e)
+            | Element::Circle(e)
+            | Element::Triangle(e)
+            | Element::Diamond(e)
+            | Element::Hexagon(e) => e.validate(),
+            Element::Star(e) => {
+                e.points = e.points.clamp(MIN_STAR_POINTS, MAX_STAR_POINTS);
+                e.shape.validate()
+            }
+            Element::Line(e) | Element::Arrow(e) => e.validate(),
+            Element::Drawing(e) => {
+                if e.points.len() > MAX_DRAWING_POINTS {
+                    return Err(ElementError::TooManyPoints);
+                }
+                for p in &mut e.points {
+                    coordinate("points", &mut p.x)?;
+                    coordinate("points", &mut p.y)?;
+                }
+                coordinate("x", &mut e.x)?;
+                coordinate("y", &mut e.y)?;
+                clamped("strokeWidth", &mut e.stroke_width, 0.0, MAX_STROKE_WIDTH)
+            }
+            Element::Text(e) => {
+                coordinate("x", &mut e.x)?;
+                coordinate("y", &mut e.y)?;
+                size("width", &mut e.width)?;
+                size("height", &mut e.height)?;
+                clamped("fontSize", &mut e.font_size, MIN_FONT_SIZE, MAX_FONT_SIZE)?;
+                text("content", &e.content)
+            }
+            Element::Textbox(e) => {
+                coordinate("x", &mut e.x)?;
+                coordinate("y", &mut e.y)?;
+                size("width", &mut e.width)?;
+                size("height", &mut e.height)?;
+                clamped("fontSize", &mut e.font_size, MIN_FONT_SIZE, MAX_FONT_SIZE)?;
+                clamped("strokeWidth", &mut e.stroke_width, 0.0, MAX_STROKE_WIDTH)?;
+                rotation(&mut e.rotation)?;
+                text("content", &e.content)
+            }
+            Element::Connector(e) => {
+                coordinate("x", &mut e.x)?;
+                coordinate("y", &mut e.y)?;
+                coordinate("x2", &mut e.x2)?;
+                coordinate("y2", &mut e.y2)?;
+                clamped("strokeWidth", &mut e.stroke_width, 0.0, MAX_STROKE_WIDTH)?;
+                text("label", &e.label)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_every_element_type() {
+        let elements = vec![
+            json!({"id": "a", "type": "sticky", "x": 0, "y": 0, "width": 200, "height": 200, "color": "#FFF176", "content": "", "fontSize": 14, "rotation": 0}),
+            json!({"id": "b", "type": "rect", "x": 1, "y": 2, "width": 3, "height": 4, "color": "#333333", "fill": "transparent", "strokeWidth": 2, "rotation": 0}),
+            json!({"id": "c", "type": "circle", "x": 1, "y": 2, "width": 3, "height": 4, "color": "#333333", "fill": "transparent", "strokeWidth": 2, "rotation": 0}),
+            json!({"id": "d", "type": "triangle", "x": 1, "y": 2, "width": 3, "height": 4, "color": "#333333"}),
+            json!({"id": "e", "type": "diamond", "x": 1, "y": 2, "width": 3, "height": 4, "color": "#333333"}),
+            json!({"id": "f", "type": "star", "x": 1, "y": 2, "width": 3, "height": 4, "color": "#333333", "points": 5}),
+            json!({"id": "g", "type": "hexagon", "x": 1, "y": 2, "width": 3, "height": 4, "color": "#333333"}),
+            json!({"id": "h", "type": "line", "x": 0, "y": 0, "x2": 10, "y2": 10, "color": "#333333", "strokeWidth": 2}),
+            json!({"id": "i", "type": "arrow", "x": 0, "y": 0, "x2": 10, "y2": 10, "color": "#333333", "strokeWidth": 2}),
+            json!({"id": "j", "type": "drawing", "points": [{"x": 0, "y": 0}, {"x": 1, "y": 1}], "color": "#333333", "strokeWidth": 2, "x": 0, "y": 0}),
+            json!({"id": "k", "type": "text", "x": 0, "y": 0, "content": "hi", "color": "#333333", "fontSize": 16, "width": 0, "height": 0}),
+            json!({"id": "l", "type": "textbox", "x": 0, "y": 0, "width": 100, "height": 50, "color": "#333333", "fill": "#FFFFFF", "content": "", "fontSize": 14, "strokeWidth": 1, "rotation": 0, "borderColor": "#cccccc"}),
+            json!({"id": "m", "type": "connector", "sourceId": "a", "targetId": "b", "sourceAnchor": "auto", "targetAnchor": "auto", "startArrow": false, "endArrow": true, "label": "", "color": "#333333", "strokeWidth": 2, "lineStyle": "straight", "x": 0, "y": 0, "x2": 0, "y2": 0}),
+        ];
+
+        for value in elements {
+            let element = Element::from_json(value.clone()).expect("should parse element");
+            assert_eq!(element.id(), value["id"].as_str().unwrap());
+            assert_eq!(element.to_json()["type"], value["type"]);
+        }
+    }
+
+    #[test]
+    fn test_unknown_type_is_rejected() {
+        let result = Element::from_json(json!({"id": "a", "type": "hologram", "x": 0, "y": 0}));
+        assert!(matches!(result, Err(ElementError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_missing_or_null_coordinate_is_rejected() {
+        // JSON.stringify turns NaN into null
+        let result = Element::from_json(
+            json!({"id": "a", "type": "rect", "x": null, "y": 0, "width": 1, "height": 1, "color": "#000"}),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_nan_coordinate_is_rejected() {
+        let mut element = Element::from_json(
+            json!({"id": "a", "type": "line", "x": 0, "y": 0, "x2": 1, "y2": 1, "color": "#000"}),
+        )
+        .unwrap();
+        if let Element::Line(line) = &mut element {
+            line.x2 = f64::NAN;
+        }
+        assert!(matches!(
+            element.validate(),
+            Err(ElementError::NotFinite("x2"))
+        ));
+    }
+
+    #[test]
+    fn test_negative_size_is_clamped() {
+        let element = Element::from_json(
+            json!({"id": "a", "type": "rect", "x": 0, "y": 0, "width": -50, "height": 20, "color": "#000"}),
+        )
+        .unwrap();
+        match element {
+            Element::Rect(shape) => {
+                assert_eq!(shape.width, 0.0);
+                assert_eq!(shape.height, 20.0);
+            }
+            _ => panic!("expected rect"),
+        }
+    }
+
+    #[test]
+    fn test_oversized_drawing_is_rejected() {
+        let points: Vec<_> = (0..=MAX_DRAWING_POINTS)
+            .map(|i| json!({"x": i, "y": i}))
+            .collect();
+        let result = Element::from_json(
+            json!({"id": "a", "type": "drawing", "points": points, "color": "#000"}),
+        );
+        assert!(matches!(result, Err(ElementError::TooManyPoints)));
+    }
+
+    #[test]
+    fn test_out_of_range_values_are_clamped() {
+        let element = Element::from_json(json!({
+            "id": "a", "type": "star", "x": 1e12, "y": 0, "width": 10, "height": 10,
+            "color": "#000", "strokeWidth": 1000, "points": 1
+        }))
+        .unwrap();
+        match element {
+            Element::Star(star) => {
+                assert_eq!(star.shape.x, MAX_COORDINATE);
+                assert_eq!(star.shape.stroke_width, MAX_STROKE_WIDTH);
+                assert_eq!(star.points, MIN_STAR_POINTS);
+            }
+            _ => panic!("expected star"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_id_is_rejected() {
+        let result =
+            Element::from_json(json!({"id": "", "type": "text", "x": 0, "y": 0, "color": "#000"}));
+        assert!(matches!(result, Err(ElementError::InvalidId)));
+    }
+}
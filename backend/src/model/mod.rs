@@ -0,0 +1,3 @@
+pub mod element;
+
+pub use element::Element;
@@ -0,0 +1,313 @@
+//! A tower layer that limits requests per client IP, and per account when
+//! the JSON body names one in `username` or `email`.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use tower::{Layer, Service};
+
+use super::{Decision, RateLimiter};
+use crate::config::Config;
+
+/// Bodies on the public routes are small; larger ones are refused
+const MAX_BODY_BYTES: usize = 1024 * 16;
+/// Longer account names cannot be real, so they share one key
+const MAX_ACCOUNT_LEN: usize = 255;
+
+#[derive(Clone)]
+struct Limits {
+    window: Duration,
+    per_ip: u32,
+    per_account: u32,
+    trust_proxy_headers: bool,
+}
+
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<dyn RateLimiter>,
+    limits: Limits,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: Arc<dyn RateLimiter>, config: &Config) -> Self {
+        RateLimitLayer {
+            limiter,
+            limits: Limits {
+                window: Duration::from_secs(config.rate_limit_window_seconds),
+                per_ip: config.rate_limit_per_ip,
+                per_account: config.rate_limit_per_account,
+                trust_proxy_headers: config.trust_proxy_headers,
+            },
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: Arc<dyn RateLimiter>,
+    limits: Limits,
+}
+
+#[derive(Deserialize)]
+struct AccountField {
+    username: Option<String>,
+    email: Option<String>,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // Take the service that was polled ready and leave a fresh clone
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let limits = self.limits.clone();
+
+        Box::pin(async move {
+            if let Some(ip) = client_ip(&request, limits.trust_proxy_headers) {
+                let key = format!("ip:{}", ip);
+                if let Some(response) = check(&*limiter, &key, limits.per_ip, limits.window).await {
+                    return Ok(response);
+                }
+            }
+
+            let (parts, body) = request.into_parts();
+            let bytes = match axum::body::to_bytes(body, MAX_BODY_BYTES).await {
+                Ok(bytes) => bytes,
+                Err(_) => {
+                    return Ok((
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        Json(serde_json::json!({"error": "Request body too large"})),
+                    )
+                        .into_response())
+                }
+            };
+            if let Some(account) = account_name(&bytes) {
+                let key = format!("account:{}", account);
+                if let Some(response) =
+                    check(&*limiter, &key, limits.per_account, limits.window).await
+                {
+                    return Ok(response);
+                }
+            }
+
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
+
+/// A 429 response if the key is over its limit. Errors from the store let
+/// the request through rather than locking everyone out.
+async fn check(
+    limiter: &dyn RateLimiter,
+    key: &str,
+    limit: u32,
+    window: Duration,
+) -> Option<Response> {
+    match limiter.hit(key, limit, window).await {
+        Ok(Decision::Allowed) => None,
+        Ok(Decision::Limited { retry_after }) => Some(too_many_requests(
+            retry_after,
+            "Too many requests, try again later",
+        )),
+        Err(e) => {
+            tracing::error!("Rate limit error: {}", e);
+            None
+        }
+    }
+}
+
+/// 429 with `Retry-After` in whole seconds, rounded up
+pub fn too_many_requests(retry_after: Duration, error: &str) -> Response {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, seconds.max(1).to_string())],
+        Json(serde_json::json!({ "error": error })),
+    )
+        .into_response()
+}
+
+/// The client address. Behind a trusted proxy this is the last
+/// `X-Forwarded-For` entry, the one the proxy appended; anything before it
+/// came from the client and may be made up.
+fn client_ip(request: &Request, trust_proxy_headers: bool) -> Option<String> {
+    if trust_proxy_headers {
+        let forwarded = request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty());
+        if let Some(ip) = forwarded {
+            return Some(ip.to_string());
+        }
+    }
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+}
+
+/// The account a body is about, case-folded so `Alice` and `alice` share a limit
+fn account_name(body: &[u8]) -> Option<String> {
+    let fields: AccountField = serde_json::from_slice(body).ok()?;
+    let name = fields.username.or(fields.email)?;
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.chars().take(MAX_ACCOUNT_LEN).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::IntoFuture;
+
+    use axum::{routing::post, Router};
+
+    use super::*;
+    use crate::ratelimit::memory::MemoryLimiter;
+    use crate::ws::test_support::{listener, test_config};
+
+    async fn serve(per_ip: u32, per_account: u32) -> SocketAddr {
+        let mut config = test_config();
+        config.rate_limit_per_ip = per_ip;
+        config.rate_limit_per_account = per_account;
+        let limiter = Arc::new(MemoryLimiter::default());
+        let app = Router::new()
+            .route("/login", post(|body: String| async move { body }))
+            .layer(RateLimitLayer::new(limiter, &config));
+        let (listener, addr) = listener().await;
+        tokio::spawn(
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .into_future(),
+        );
+        addr
+    }
+
+    async fn login(addr: SocketAddr, username: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://{}/login", addr))
+            .json(&serde_json::json!({ "username": username, "password": "x" }))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_limits_per_account() {
+        let addr = serve(100, 2).await;
+        assert_eq!(login(addr, "alice").await.status().as_u16(), 200);
+        // The handler still sees the body the layer read
+        let echoed = login(addr, "Alice").await.text().await.unwrap();
+        assert!(echoed.contains("\"Alice\""));
+
+        let limited = login(addr, "ALICE").await;
+        assert_eq!(limited.status().as_u16(), 429);
+        let retry_after: u64 = limited.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        assert_eq!(login(addr, "bob").await.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_limits_per_ip() {
+        let addr = serve(2, 100).await;
+        assert_eq!(login(addr, "a").await.status().as_u16(), 200);
+        assert_eq!(login(addr, "b").await.status().as_u16(), 200);
+        assert_eq!(login(addr, "c").await.status().as_u16(), 429);
+    }
+
+    #[test]
+    fn test_account_name() {
+        assert_eq!(
+            account_name(br#"{"username": " Bob "}"#),
+            Some("bob".to_string())
+        );
+        assert_eq!(
+            account_name(br#"{"email": "a@b.dk"}"#),
+            Some("a@b.dk".to_string())
+        );
+        assert_eq!(account_name(br#"{"token": "x"}"#), None);
+        assert_eq!(account_name(b""), None);
+    }
+
+    #[test]
+    fn test_client_ip_ignores_spoofed_forwarded_entries() {
+        let request = |forwarded: &str| {
+            let mut request = Request::builder()
+                .header("x-forwarded-for", forwarded)
+                .body(Body::empty())
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+            request
+        };
+        let spoofed = request("1.2.3.4, 5.6.7.8,203.0.113.9");
+        assert_eq!(client_ip(&spoofed, true).as_deref(), Some("203.0.113.9"));
+        assert_eq!(client_ip(&spoofed, false).as_deref(), Some("10.0.0.1"));
+        assert_eq!(
+            client_ip(&request("203.0.113.9"), true).as_deref(),
+            Some("203.0.113.9")
+        );
+        assert_eq!(
+            client_ip(&request("1.2.3.4, "), true).as_deref(),
+            Some("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_retry_after_rounds_up() {
+        let response = too_many_requests(Duration::from_millis(1500), "slow down");
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+        let response = too_many_requests(Duration::ZERO, "slow down");
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{Decision, RateLimiter};
+
+/// Counts held by this process, for a single instance
+#[derive(Default)]
+pub struct MemoryLimiter {
+    /// When each key's window resets, and the hits in it so far
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+#[async_trait]
+impl RateLimiter for MemoryLimiter {
+    async fn hit(&self, key: &str, limit: u32, window: Duration) -> Result<Decision> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let entry = windows.entry(key.to_string()).or_insert((now + window, 0));
+        if entry.0 <= now {
+            *entry = (now + window, 0);
+        }
+        entry.1 = entry.1.saturating_add(1);
+        if entry.1 > limit {
+            Ok(Decision::Limited {
+                retry_after: entry.0 - now,
+            })
+        } else {
+            Ok(Decision::Allowed)
+        }
+    }
+
+    async fn prune(&self) -> Result<()> {
+        let now = Instant::now();
+        self.windows
+            .lock()
+            .unwrap()
+            .retain(|_, (resets_at, _)| *resets_at > now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_limits_each_key_separately() {
+        let limiter = MemoryLimiter::default();
+        let window = Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.hit("ip:a", 3, window).await.unwrap(),
+                Decision::Allowed
+            );
+        }
+        match limiter.hit("ip:a", 3, window).await.unwrap() {
+            Decision::Limited { retry_after } => assert!(retry_after <= window),
+            Decision::Allowed => panic!("fourth hit should be limited"),
+        }
+        assert_eq!(
+            limiter.hit("ip:b", 3, window).await.unwrap(),
+            Decision::Allowed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_window_resets_and_prunes() {
+        let limiter = MemoryLimiter::default();
+        let window = Duration::from_millis(20);
+        limiter.hit("k", 1, window).await.unwrap();
+        assert!(matches!(
+            limiter.hit("k", 1, window).await.unwrap(),
+            Decision::Limited { .. }
+        ));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        limiter.prune().await.unwrap();
+        assert!(limiter.windows.lock().unwrap().is_empty());
+        assert_eq!(
+            limiter.hit("k", 1, window).await.unwrap(),
+            Decision::Allowed
+        );
+    }
+}
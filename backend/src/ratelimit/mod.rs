@@ -0,0 +1,60 @@
+pub mod layer;
+pub mod memory;
+pub mod postgres;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::config::Config;
+
+pub use layer::RateLimitLayer;
+
+/// Whether a request fits within its limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+/// Counts requests per key in fixed windows
+#[async_trait]
+pub trait RateLimiter: Send + Sync {
+    /// Count a hit against `key` and decide whether it is one of the first
+    /// `limit` in the current window
+    async fn hit(&self, key: &str, limit: u32, window: Duration) -> Result<Decision>;
+
+    /// Forget windows that have ended
+    async fn prune(&self) -> Result<()>;
+}
+
+/// In memory by default, or Postgres so every instance shares the counts
+pub fn from_config(config: &Config, pool: &PgPool) -> Result<Arc<dyn RateLimiter>> {
+    match config.rate_limit_store.as_str() {
+        "memory" => Ok(Arc::new(memory::MemoryLimiter::default())),
+        "postgres" => Ok(Arc::new(postgres::PostgresLimiter::new(pool.clone()))),
+        other => bail!("RATE_LIMIT_STORE must be memory or postgres, not {}", other),
+    }
+}
+
+/// Periodically drop ended windows so the store does not grow without bound
+pub fn spawn_pruning(limiter: Arc<dyn RateLimiter>, config: &Config) {
+    let seconds = config.rate_limit_window_seconds;
+    if seconds == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        // The first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = limiter.prune().await {
+                tracing::error!("Rate limit pruning error: {}", e);
+            }
+        }
+    });
+}
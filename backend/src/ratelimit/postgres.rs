@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use super::{Decision, RateLimiter};
+use crate::db;
+
+/// Counts kept in the `rate_limits` table, shared by every instance
+pub struct PostgresLimiter {
+    pool: PgPool,
+}
+
+impl PostgresLimiter {
+    pub fn new(pool: PgPool) -> Self {
+        PostgresLimiter { pool }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for PostgresLimiter {
+    async fn hit(&self, key: &str, limit: u32, window: Duration) -> Result<Decision> {
+        let (hits, remaining) = db::rate_limits::hit(&self.pool, key, window.as_secs_f64()).await?;
+        if hits as i64 > limit as i64 {
+            Ok(Decision::Limited {
+                retry_after: Duration::from_secs_f64(remaining.max(0.0)),
+            })
+        } else {
+            Ok(Decision::Allowed)
+        }
+    }
+
+    async fn prune(&self) -> Result<()> {
+        db::rate_limits::prune(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::test_support::test_pool;
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_shared_window() {
+        let pool = test_pool().await;
+        let key = format!("test:{}", uuid::Uuid::new_v4());
+        // Two limiters on one database act like two instances
+        let first = PostgresLimiter::new(pool.clone());
+        let second = PostgresLimiter::new(pool.clone());
+        let window = Duration::from_secs(60);
+
+        assert_eq!(first.hit(&key, 2, window).await.unwrap(), Decision::Allowed);
+        assert_eq!(
+            second.hit(&key, 2, window).await.unwrap(),
+            Decision::Allowed
+        );
+        match first.hit(&key, 2, window).await.unwrap() {
+            Decision::Limited { retry_after } => {
+                assert!(retry_after > Duration::ZERO && retry_after <= window)
+            }
+            Decision::Allowed => panic!("third hit should be limited"),
+        }
+
+        sqlx::query("DELETE FROM rate_limits WHERE key = $1")
+            .bind(&key)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
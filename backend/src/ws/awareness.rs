@@ -0,0 +1,126 @@
+use anyhow::Result;
+use yrs::encoding::read::Read as YrsRead;
+use yrs::encoding::write::Write as YrsWrite;
+use yrs::sync::{Awareness, AwarenessUpdate};
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+
+use super::sync::MSG_AWARENESS;
+
+/// Yjs client id, as used to key awareness states
+pub type ClientId = u64;
+
+/// Wrap an awareness update in a protocol message
+pub fn create_awareness_message(update: &AwarenessUpdate) -> Vec<u8> {
+    let mut encoder = EncoderV1::new();
+    encoder.write_var(MSG_AWARENESS as u32);
+    encoder.write_buf(update.encode_v1());
+    encoder.to_vec()
+}
+
+/// Extract the update carried by an awareness message
+pub fn decode_awareness_message(msg: &[u8]) -> Result<Option<AwarenessUpdate>> {
+    if msg.is_empty() {
+        return Ok(None);
+    }
+
+    let mut decoder = DecoderV1::from(msg);
+    let msg_type: u32 = decoder.read_var()?;
+    if msg_type != MSG_AWARENESS as u32 {
+        return Ok(None);
+    }
+    Ok(Some(AwarenessUpdate::decode_v1(decoder.read_buf()?)?))
+}
+
+/// Apply a client's awareness message to the room's store and return the
+/// client ids it announced
+pub fn apply_awareness_message(awareness: &Awareness, msg: &[u8]) -> Result<Vec<ClientId>> {
+    let update = match decode_awareness_message(msg)? {
+        Some(update) => update,
+        None => return Ok(Vec::new()),
+    };
+    let clients = update.clients.keys().copied().collect();
+    awareness.apply_update(update)?;
+    Ok(clients)
+}
+
+/// An awareness message with every current state, for clients joining the room.
+/// Returns None if no client has published a state.
+pub fn current_states_message(awareness: &Awareness) -> Result<Option<Vec<u8>>> {
+    let update = awareness.update()?;
+    if update.clients.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(create_awareness_message(&update)))
+}
+
+/// Clear the states of clients whose socket closed. Returns the message that
+/// tells the remaining clients to drop them.
+pub fn remove_clients(awareness: &Awareness, clients: &[ClientId]) -> Result<Option<Vec<u8>>> {
+    if clients.is_empty() {
+        return Ok(None);
+    }
+    for &client_id in clients {
+        awareness.remove_state(client_id);
+    }
+    let update = awareness.update_with_clients(clients.iter().copied())?;
+    Ok(Some(create_awareness_message(&update)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yrs::Doc;
+
+    fn client_message(client_id: ClientId, state: &str) -> Vec<u8> {
+        let client = Awareness::new(Doc::with_client_id(client_id));
+        client.set_local_state_raw(state);
+        create_awareness_message(&client.update().unwrap())
+    }
+
+    #[test]
+    fn test_awareness_message_roundtrip() {
+        let msg = client_message(7, r#"{"cursor":{"x":1,"y":2}}"#);
+        assert_eq!(msg[0], MSG_AWARENESS);
+        let update = decode_awareness_message(&msg).unwrap().unwrap();
+        assert_eq!(&*update.clients[&7].json, r#"{"cursor":{"x":1,"y":2}}"#);
+        assert!(decode_awareness_message(&[0, 0]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_late_joiner_receives_current_states() {
+        let room = Awareness::default();
+        assert!(current_states_message(&room).unwrap().is_none());
+
+        let clients =
+            apply_awareness_message(&room, &client_message(7, r#"{"name":"Alice"}"#)).unwrap();
+        assert_eq!(clients, vec![7]);
+
+        let msg = current_states_message(&room).unwrap().unwrap();
+        let joiner = Awareness::default();
+        apply_awareness_message(&joiner, &msg).unwrap();
+        assert_eq!(
+            joiner.state::<serde_json::Value>(7).unwrap()["name"],
+            "Alice"
+        );
+    }
+
+    #[test]
+    fn test_remove_clients_clears_state() {
+        let room = Awareness::default();
+        apply_awareness_message(&room, &client_message(7, r#"{"name":"Alice"}"#)).unwrap();
+        apply_awareness_message(&room, &client_message(8, r#"{"name":"Bob"}"#)).unwrap();
+
+        // A replica that saw both clients drops client 7 once its socket closes
+        let replica = Awareness::default();
+        apply_awareness_message(&replica, &current_states_message(&room).unwrap().unwrap())
+            .unwrap();
+        let removal = remove_clients(&room, &[7]).unwrap().unwrap();
+        apply_awareness_message(&replica, &removal).unwrap();
+
+        assert!(room.state::<serde_json::Value>(7).is_none());
+        assert!(replica.state::<serde_json::Value>(7).is_none());
+        assert!(replica.state::<serde_json::Value>(8).is_some());
+        assert!(remove_clients(&room, &[]).unwrap().is_none());
+    }
+}
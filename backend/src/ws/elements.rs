@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use yrs::types::ToJson;
+use yrs::{Any, Doc, Map, Transact};
+
+use crate::model::Element;
+
+/// Name of the root Y.Map holding board elements, keyed by element id
+pub const ELEMENTS_MAP: &str = "elements";
+
+/// Insert or replace an element. Returns the encoded Yrs update.
+pub fn upsert_element(doc: &Doc, element: &Element) -> Result<Vec<u8>> {
+    let value: Any = serde_json::from_value(element.to_json())?;
+    let map = doc.get_or_insert_map(ELEMENTS_MAP);
+    let mut txn = doc.transact_mut();
+    map.insert(&mut txn, element.id(), value);
+    Ok(txn.encode_update_v1())
+}
+
+/// Remove an element by id. Returns the encoded Yrs update, or None if it did not exist.
+pub fn remove_element(doc: &Doc, element_id: &str) -> Option<Vec<u8>> {
+    let map = doc.get_or_insert_map(ELEMENTS_MAP);
+    let mut txn = doc.transact_mut();
+    map.remove(&mut txn, element_id)?;
+    Some(txn.encode_update_v1())
+}
+
+/// Merge a full client state: elements the document does not have yet are added,
+/// existing ones are left untouched. Returns the encoded Yrs update, or None if
+/// nothing changed.
+pub fn merge_elements(doc: &Doc, elements: &[Element]) -> Result<Option<Vec<u8>>> {
+    let map = doc.get_or_insert_map(ELEMENTS_MAP);
+    let mut txn = doc.transact_mut();
+    let mut changed = false;
+    for element in elements {
+        if map.contains_key(&txn, element.id()) {
+            continue;
+        }
+        let value: Any = serde_json::from_value(element.to_json())?;
+        map.insert(&mut txn, element.id(), value);
+        changed = true;
+    }
+    Ok(changed.then(|| txn.encode_update_v1()))
+}
+
+/// Make the document's elements match `target`: elements not in `target` are
+/// removed, the rest are inserted or replaced where they differ. Returns the
+/// encoded Yrs update together with the element messages that replay the change
+/// for JSON protocol clients, or None if nothing changed.
+pub fn replace_elements(doc: &Doc, target: &[Element]) -> Result<Option<(Vec<u8>, Vec<Value>)>> {
+    let current: HashMap<String, Value> = get_elements(doc)
+        .into_iter()
+        .filter_map(|v| Some((v.get("id")?.as_str()?.to_string(), v)))
+        .collect();
+    let target_ids: HashSet<&str> = target.iter().map(Element::id).collect();
+
+    let map = doc.get_or_insert_map(ELEMENTS_MAP);
+    let mut txn = doc.transact_mut();
+    let mut messages = Vec::new();
+    for id in current.keys() {
+        if !target_ids.contains(id.as_str()) {
+            map.remove(&mut txn, id);
+            messages.push(serde_json::json!({"type": "element_remove", "elementId": id}));
+        }
+    }
+    for element in target {
+        let json = element.to_json();
+        let msg_type = match current.get(element.id()) {
+            // Compare through the model so number formatting does not matter
+            Some(existing) => match Element::from_json(existing.clone()) {
+                Ok(ref e) if e == element => continue,
+                _ => "element_update",
+            },
+            None => "element_add",
+        };
+        let value: Any = serde_json::from_value(json.clone())?;
+        map.insert(&mut txn, element.id(), value);
+        messages.push(serde_json::json!({"type": msg_type, "element": json}));
+    }
+
+    if messages.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((txn.encode_update_v1(), messages)))
+}
+
+/// Whether the message is an element operation handled by `apply_message`
+pub fn is_element_message(msg: &Value) -> bool {
+    matches!(
+        msg.get("type").and_then(|t| t.as_str()),
+        Some("element_add" | "element_update" | "element_remove" | "sync_state")
+    )
+}
+
+/// Apply a JSON element message (`element_add`, `element_update`, `element_remove`
+/// or `sync_state`) to the document. Returns the encoded Yrs update if the
+/// document changed, or None if it did not or the message is not an element
+/// operation.
+///
+/// Elements are validated against the model and the message is rewritten in
+/// place with the normalized elements, so it can be broadcast as-is. Invalid
+/// elements in a `sync_state` are dropped; an invalid single element is an error.
+pub fn apply_message(doc: &Doc, msg: &mut Value) -> Result<Option<Vec<u8>>> {
+    match msg.get("type").and_then(|t| t.as_str()) {
+        Some("element_add") | Some("element_update") => {
+            let value = msg
+                .get_mut("element")
+                .ok_or_else(|| anyhow!("message is missing an element"))?;
+            let element = Element::from_json(value.take())?;
+            *value = element.to_json();
+            upsert_element(doc, &element).map(Some)
+        }
+        Some("element_remove") => {
+            let id = msg
+                .get("elementId")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("message is missing an elementId"))?;
+            Ok(remove_element(doc, id))
+        }
+        Some("sync_state") => {
+            let values = msg
+                .get_mut("elements")
+                .and_then(|v| v.as_array_mut())
+                .ok_or_else(|| anyhow!("message is missing elements"))?;
+            let elements: Vec<Element> = values
+                .drain(..)
+                .filter_map(|value| match Element::from_json(value) {
+                    Ok(element) => Some(element),
+                    Err(e) => {
+                        tracing::warn!("Dropping invalid element from sync_state: {}", e);
+                        None
+                    }
+                })
+                .collect();
+            values.extend(elements.iter().map(Element::to_json));
+            merge_elements(doc, &elements)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Read all elements as JSON, ordered by id (ids are prefixed with their creation time)
+pub fn get_elements(doc: &Doc) -> Vec<Value> {
+    let map = doc.get_or_insert_map(ELEMENTS_MAP);
+    let txn = doc.transact();
+    let mut entries: Vec<(String, Value)> = map
+        .iter(&txn)
+        .filter_map(|(id, value)| {
+            serde_json::to_value(value.to_json(&txn))
+                .ok()
+                .map(|v| (id.to_string(), v))
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries.into_iter().map(|(_, v)| v).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::sync;
+    use serde_json::json;
+
+    fn element(value: Value) -> Element {
+        Element::from_json(value).expect("valid element")
+    }
+
+    fn rect(id: &str, x: f64) -> Element {
+        element(
+            json!({"id": id, "type": "rect", "x": x, "y": 20.0, "width": 100.0, "height": 50.0, "color": "#333333"}),
+        )
+    }
+
+    fn text(id: &str, content: &str) -> Element {
+        element(
+            json!({"id": id, "type": "text", "x": 0.0, "y": 0.0, "content": content, "color": "#333333"}),
+        )
+    }
+
+    #[test]
+    fn test_upsert_and_get_elements() {
+        let doc = Doc::new();
+        upsert_element(&doc, &rect("el_1", 10.0)).expect("should insert element");
+
+        let elements = get_elements(&doc);
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0]["type"], "rect");
+        assert_eq!(elements[0]["x"], 10.0);
+    }
+
+    #[test]
+    fn test_upsert_replaces_existing() {
+        let doc = Doc::new();
+        upsert_element(&doc, &text("el_1", "a")).unwrap();
+        upsert_element(&doc, &text("el_1", "b")).unwrap();
+
+        let elements = get_elements(&doc);
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0]["content"], "b");
+    }
+
+    #[test]
+    fn test_remove_element() {
+        let doc = Doc::new();
+        upsert_element(&doc, &rect("el_1", 0.0)).unwrap();
+        assert!(remove_element(&doc, "el_1").is_some());
+        assert!(remove_element(&doc, "el_1").is_none());
+        assert!(get_elements(&doc).is_empty());
+    }
+
+    #[test]
+    fn test_merge_keeps_existing_elements() {
+        let doc = Doc::new();
+        upsert_element(&doc, &text("el_1", "server")).unwrap();
+
+        let update = merge_elements(&doc, &[text("el_1", "client"), rect("el_2", 0.0)]).unwrap();
+        assert!(update.is_some());
+
+        let elements = get_elements(&doc);
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0]["content"], "server");
+        assert_eq!(elements[1]["type"], "rect");
+
+        // Merging the same state again changes nothing
+        let update = merge_elements(&doc, &[rect("el_2", 0.0)]).unwrap();
+        assert!(update.is_none());
+    }
+
+    #[test]
+    fn test_apply_message() {
+        let doc = Doc::new();
+        let mut add = json!({"type": "element_add", "element": rect("el_1", 0.0).to_json()});
+        assert!(apply_message(&doc, &mut add).unwrap().is_some());
+
+        let mut update = json!({"type": "element_update", "element": rect("el_1", 5.0).to_json()});
+        assert!(apply_message(&doc, &mut update).unwrap().is_some());
+        assert_eq!(get_elements(&doc)[0]["x"], 5.0);
+
+        let mut remove = json!({"type": "element_remove", "elementId": "el_1"});
+        assert!(apply_message(&doc, &mut remove).unwrap().is_some());
+        assert!(get_elements(&doc).is_empty());
+
+        let mut cursor = json!({"type": "cursor", "x": 1, "y": 2});
+        assert!(apply_message(&doc, &mut cursor).unwrap().is_none());
+
+        let mut missing = json!({"type": "element_add"});
+        assert!(apply_message(&doc, &mut missing).is_err());
+    }
+
+    #[test]
+    fn test_apply_message_rejects_invalid_element() {
+        let doc = Doc::new();
+        let mut add = json!({"type": "element_add", "element": {"id": "el_1", "type": "hologram"}});
+        assert!(apply_message(&doc, &mut add).is_err());
+        assert!(get_elements(&doc).is_empty());
+    }
+
+    #[test]
+    fn test_apply_message_normalizes_element() {
+        let doc = Doc::new();
+        let mut add = json!({"type": "element_add", "element": {
+            "id": "el_1", "type": "rect", "x": 0, "y": 0, "width": -10, "height": 5,
+            "color": "#000", "unknown": true
+        }});
+        apply_message(&doc, &mut add).unwrap();
+        assert_eq!(add["element"]["width"], 0.0);
+        assert!(add["element"].get("unknown").is_none());
+        let stored = Element::from_json(get_elements(&doc)[0].clone()).unwrap();
+        assert_eq!(stored.to_json(), add["element"]);
+    }
+
+    #[test]
+    fn test_sync_state_drops_invalid_elements() {
+        let doc = Doc::new();
+        let mut msg = json!({"type": "sync_state", "elements": [
+            rect("el_1", 0.0).to_json(),
+            {"id": "el_2", "type": "rect", "x": null},
+        ]});
+        assert!(apply_message(&doc, &mut msg).unwrap().is_some());
+        assert_eq!(msg["elements"].as_array().unwrap().len(), 1);
+        assert_eq!(get_elements(&doc).len(), 1);
+    }
+
+    #[test]
+    fn test_replace_elements() {
+        let doc = Doc::new();
+        upsert_element(&doc, &rect("el_1", 0.0)).unwrap();
+        upsert_element(&doc, &rect("el_2", 0.0)).unwrap();
+        upsert_element(&doc, &text("el_3", "same")).unwrap();
+
+        let (update, messages) = replace_elements(
+            &doc,
+            &[
+                rect("el_2", 50.0),
+                text("el_3", "same"),
+                text("el_4", "new"),
+            ],
+        )
+        .unwrap()
+        .expect("should change the document");
+
+        let elements = get_elements(&doc);
+        let ids: Vec<_> = elements.iter().map(|e| e["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["el_2", "el_3", "el_4"]);
+        assert_eq!(elements[0]["x"], 50.0);
+
+        let types: Vec<_> = messages
+            .iter()
+            .map(|m| m["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            vec!["element_remove", "element_update", "element_add"]
+        );
+
+        // A remote copy that receives the update converges to the same elements
+        let remote = Doc::new();
+        sync::load_doc_state(&remote, &sync::encode_doc_state(&doc)).unwrap();
+        sync::load_doc_state(&remote, &update).unwrap();
+        assert_eq!(get_elements(&remote).len(), 3);
+
+        // Replacing with the current content is a no-op
+        let current: Vec<Element> = get_elements(&doc).into_iter().map(element).collect();
+        assert!(replace_elements(&doc, &current).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_elements_survive_persistence_roundtrip() {
+        let doc = Doc::new();
+        let drawing = element(json!({
+            "id": "el_1", "type": "drawing", "points": [{"x": 1.0, "y": 2.0}], "color": "#000"
+        }));
+        upsert_element(&doc, &drawing).unwrap();
+
+        let state = sync::encode_doc_state(&doc);
+        let restored = Doc::new();
+        sync::load_doc_state(&restored, &state).unwrap();
+
+        let elements = get_elements(&restored);
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0]["points"][0]["y"], 2.0);
+        assert_eq!(Element::from_json(elements[0].clone()).unwrap(), drawing);
+    }
+
+    #[test]
+    fn test_update_applies_to_remote_doc() {
+        let doc = Doc::new();
+        let update = upsert_element(&doc, &rect("el_1", 0.0)).unwrap();
+
+        let remote = Doc::new();
+        sync::load_doc_state(&remote, &update).unwrap();
+        assert_eq!(get_elements(&remote)[0]["type"], "rect");
+    }
+}
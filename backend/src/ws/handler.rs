@@ -1,8 +1,9 @@
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{close_code, CloseFrame, Message, WebSocket},
         Path, Query, State, WebSocketUpgrade,
     },
     response::IntoResponse,
@@ -10,12 +11,19 @@ use axum::{
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
 use sqlx::PgPool;
+use tokio::sync::{mpsc, watch};
 use uuid::Uuid;
 
-use super::room::RoomManager;
-use super::sync;
+use super::awareness;
+use super::elements;
+use super::permissions::{self, Denied};
+use super::room::{self, RoomEvent, RoomManager};
+use super::shutdown::{self, Shutdown};
+use super::{snapshots, storage, sync};
 use crate::auth;
+use crate::config::Config;
 use crate::db;
+use crate::mail::Mailer;
 
 #[derive(Debug, Deserialize)]
 pub struct WsQuery {
@@ -27,6 +35,9 @@ pub struct AppState {
     pub pool: PgPool,
     pub room_manager: RoomManager,
     pub jwt_secret: String,
+    pub config: Config,
+    pub mailer: Arc<dyn Mailer>,
+    pub shutdown: Shutdown,
 }
 
 pub async fn ws_handler(
@@ -35,20 +46,49 @@ pub async fn ws_handler(
     Query(query): Query<WsQuery>,
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    // Authenticate the user
-    let (user_id, username) = if let Some(ref token) = query.token {
-        match auth::verify_token(token, &state.jwt_secret) {
-            Ok(claims) => (claims.sub, claims.username),
+    // Refuse new connections while the server is shutting down
+    if state.shutdown.is_triggered() {
+        return axum::http::StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    // Authenticate the user and resolve their role on the board
+    let (user_id, username, role, share_link_id) = if let Some(ref token) = query.token {
+        let claims = match auth::verify_token(token, &state.jwt_secret) {
+            Ok(claims) => claims,
             Err(_) => {
                 return axum::http::StatusCode::UNAUTHORIZED.into_response();
             }
+        };
+        match db::sessions::is_session_active(&state.pool, claims.sid).await {
+            Ok(true) => {}
+            Ok(false) => return axum::http::StatusCode::UNAUTHORIZED.into_response(),
+            Err(e) => {
+                tracing::error!("Failed to check session: {}", e);
+                return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+        match db::boards::get_board(&state.pool, board_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return axum::http::StatusCode::NOT_FOUND.into_response(),
+            Err(e) => {
+                tracing::error!("Failed to load board: {}", e);
+                return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+        match db::boards::user_has_access(&state.pool, board_id, claims.sub).await {
+            Ok(Some(role)) => (claims.sub, claims.username, role, None),
+            Ok(None) => return axum::http::StatusCode::FORBIDDEN.into_response(),
+            Err(e) => {
+                tracing::error!("Failed to check board access: {}", e);
+                return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
         }
     } else if let Some(ref share_token) = query.share_token {
-        // Guest access via share link
+        // Guest access via share link, with the link's role
         match db::boards::get_share_link_by_token(&state.pool, share_token).await {
             Ok(Some(link)) if link.board_id == board_id => {
                 let guest_id = Uuid::new_v4();
-                (guest_id, "Guest".to_string())
+                (guest_id, "Guest".to_string(), link.role, Some(link.id))
             }
             _ => {
                 return axum::http::StatusCode::UNAUTHORIZED.into_response();
@@ -58,7 +98,9 @@ pub async fn ws_handler(
         return axum::http::StatusCode::UNAUTHORIZED.into_response();
     };
 
-    ws.on_upgrade(move |socket| handle_socket(socket, board_id, user_id, username, state))
+    ws.on_upgrade(move |socket| {
+        handle_socket(socket, board_id, user_id, username, role, share_link_id, state)
+    })
 }
 
 async fn handle_socket(
@@ -66,19 +108,17 @@ async fn handle_socket(
     board_id: Uuid,
     user_id: Uuid,
     username: String,
+    role: String,
+    share_link_id: Option<Uuid>,
     state: Arc<AppState>,
 ) {
     let room = state.room_manager.get_or_create_room(board_id).await;
 
     // Load existing state from DB if this is a fresh room
     if room.user_count().await == 0 {
-        if let Ok(Some(board)) = db::boards::get_board(&state.pool, board_id).await {
-            if let Some(yrs_state) = board.yrs_state {
-                let doc = room.doc.read().await;
-                if let Err(e) = sync::load_doc_state(&doc, &yrs_state) {
-                    tracing::error!("Failed to load board state: {}", e);
-                }
-            }
+        let doc = room.doc.read().await;
+        if let Err(e) = storage::load_board(&state.pool, board_id, &doc).await {
+            tracing::error!("Failed to load board state: {}", e);
         }
     }
 
@@ -96,9 +136,32 @@ async fn handle_socket(
         }
     }
 
+    // Send the persisted elements to clients that speak the JSON protocol
+    {
+        let doc = room.doc.read().await;
+        let state_msg = serde_json::json!({
+            "type": "sync_state",
+            "elements": elements::get_elements(&doc),
+            "role": role,
+        });
+        drop(doc);
+        let _ = sender
+            .send(Message::Binary(serde_json::to_vec(&state_msg).unwrap_or_default()))
+            .await;
+    }
+
+    // Send the cursors and selections of everyone already in the room
+    match awareness::current_states_message(&room.awareness) {
+        Ok(Some(msg)) => {
+            let _ = sender.send(Message::Binary(msg)).await;
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to encode awareness states: {}", e),
+    }
+
     // Broadcast join event
     let join_msg = serde_json::json!({
-        "type": "join",
+        "type": room::MSG_JOIN,
         "userId": user_id.to_string(),
         "username": username,
         "users": room.get_users().await,
@@ -109,12 +172,98 @@ async fn handle_socket(
 
     let room_tx = room.tx.clone();
     let room_doc = room.doc.clone();
+    let room_awareness = room.awareness.clone();
+    // Awareness client ids announced over this socket, cleared when it closes
+    let awareness_clients: Arc<Mutex<HashSet<awareness::ClientId>>> = Default::default();
+    let socket_clients = awareness_clients.clone();
+
+    // Messages for this client only, such as errors about its own input
+    let (direct_tx, mut direct_rx) = mpsc::channel::<Vec<u8>>(16);
 
-    // Task: forward broadcast messages to this client
+    // The role can change while connected
+    let (role_tx, role_rx) = watch::channel(role);
+
+    // Task: forward broadcast messages to this client until the server shuts
+    // down or the user loses access
+    let mut events = room.events.subscribe();
+    let mut shutdown_rx = state.shutdown.subscribe();
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if sender.send(Message::Binary(msg)).await.is_err() {
-                break;
+        loop {
+            tokio::select! {
+                msg = rx.recv() => {
+                    let Ok(msg) = msg else { break };
+                    if sender.send(Message::Binary(msg)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(msg) = direct_rx.recv() => {
+                    if sender.send(Message::Binary(msg)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(event) = events.recv() => {
+                    let frame = event
+                        .message()
+                        .map(|msg| serde_json::to_vec(&msg).unwrap_or_default());
+                    let close = match event {
+                        RoomEvent::AccessRevoked { user_id: revoked } if revoked == user_id => {
+                            Some((permissions::CLOSE_ACCESS_REVOKED, "Access revoked"))
+                        }
+                        RoomEvent::ShareLinkRevoked { link_id }
+                            if share_link_id == Some(link_id) =>
+                        {
+                            Some((permissions::CLOSE_ACCESS_REVOKED, "Share link revoked"))
+                        }
+                        RoomEvent::RoleChanged { user_id: changed, role } if changed == user_id => {
+                            role_tx.send_replace(role);
+                            if let Some(frame) = frame {
+                                if sender.send(Message::Binary(frame)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            None
+                        }
+                        RoomEvent::BoardDeleted => {
+                            if let Some(frame) = frame {
+                                let _ = sender.send(Message::Binary(frame)).await;
+                            }
+                            Some((permissions::CLOSE_BOARD_DELETED, "Board deleted"))
+                        }
+                        RoomEvent::AccessRequested { .. }
+                            if matches!(role_tx.borrow().as_str(), "owner" | "admin") =>
+                        {
+                            if let Some(frame) = frame {
+                                if sender.send(Message::Binary(frame)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some((code, reason)) = close {
+                        let _ = sender
+                            .send(Message::Close(Some(CloseFrame {
+                                code,
+                                reason: reason.into(),
+                            })))
+                            .await;
+                        break;
+                    }
+                }
+                true = async { shutdown_rx.wait_for(|&stopping| stopping).await.is_ok() } => {
+                    let msg = shutdown::shutdown_message();
+                    let _ = sender
+                        .send(Message::Binary(serde_json::to_vec(&msg).unwrap_or_default()))
+                        .await;
+                    let _ = sender
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::RESTART,
+                            reason: "Server shutting down".into(),
+                        })))
+                        .await;
+                    break;
+                }
             }
         }
     });
@@ -123,14 +272,26 @@ async fn handle_socket(
     let pool = state.pool.clone();
     let board_id_clone = board_id;
     let mut recv_task = tokio::spawn(async move {
-        let mut save_counter = 0u32;
+        // Drop edits the session's role does not allow, telling the client why
+        let deny = |denied: Denied| {
+            if denied == Denied::ReadOnly {
+                let error = permissions::read_only_error();
+                let _ = direct_tx.try_send(serde_json::to_vec(&error).unwrap_or_default());
+            }
+        };
+
         while let Some(Ok(msg)) = receiver.next().await {
-            match msg {
+            let text = match msg {
                 Message::Binary(data) => {
                     let data = data.to_vec();
                     if data.is_empty() {
                         continue;
                     }
+                    let allowed = permissions::check_binary(&role_rx.borrow(), &data);
+                    if let Err(denied) = allowed {
+                        deny(denied);
+                        continue;
+                    }
 
                     // Check if it's a sync message or awareness
                     let msg_type = data[0];
@@ -138,61 +299,106 @@ async fn handle_socket(
                     if msg_type == sync::MSG_SYNC || (data.len() > 1 && data[0] == 0) {
                         // Handle sync protocol
                         let doc = room_doc.read().await;
-                        match sync::handle_sync_message(&doc, &data) {
+                        let result = sync::handle_sync_message(&doc, &data);
+                        drop(doc);
+                        match result {
                             Ok(Some(response)) => {
                                 let _ = room_tx.send(response);
                             }
-                            Ok(None) => {}
+                            Ok(None) => {
+                                // Persist the applied update to the board's update log
+                                if let Ok(Some(update)) = sync::decode_update_message(&data) {
+                                    if let Err(e) =
+                                        db::updates::append_update(&pool, board_id_clone, &update)
+                                            .await
+                                    {
+                                        tracing::error!("Failed to persist update: {}", e);
+                                    }
+                                }
+                            }
                             Err(e) => {
                                 tracing::warn!("Sync error: {}", e);
                             }
                         }
-                        drop(doc);
 
                         // Broadcast update to all clients
                         let _ = room_tx.send(data);
-
-                        // Periodic save to DB
-                        save_counter += 1;
-                        if save_counter.is_multiple_of(100) {
-                            let doc = room_doc.read().await;
-                            let state_bytes = sync::encode_doc_state(&doc);
-                            if let Err(e) =
-                                db::boards::save_yrs_state(&pool, board_id_clone, &state_bytes)
-                                    .await
-                            {
-                                tracing::error!("Failed to save board state: {}", e);
+                        continue;
+                    }
+                    if msg_type == sync::MSG_AWARENESS {
+                        // Track the state so late joiners receive it, then forward it
+                        match awareness::apply_awareness_message(&room_awareness, &data) {
+                            Ok(clients) => {
+                                socket_clients.lock().unwrap().extend(clients);
+                                let _ = room_tx.send(data);
                             }
+                            Err(e) => tracing::warn!("Invalid awareness message: {}", e),
                         }
-                    } else if msg_type == sync::MSG_AWARENESS {
-                        // Forward awareness messages to all
-                        let _ = room_tx.send(data);
-                    } else {
-                        // Try to parse as JSON (custom messages)
-                        let _ = room_tx.send(data);
+                        continue;
                     }
-                }
-                Message::Text(text) => {
-                    // Check for save_request from auto-save timer
-                    if let Ok(msg) = serde_json::from_str::<serde_json::Value>(&text) {
-                        if msg.get("type").and_then(|t| t.as_str()) == Some("save_request") {
-                            let doc = room_doc.read().await;
-                            let state_bytes = sync::encode_doc_state(&doc);
-                            drop(doc);
-                            if let Err(e) = db::boards::save_yrs_state(&pool, board_id_clone, &state_bytes).await {
-                                tracing::error!("Auto-save failed: {}", e);
-                            } else {
-                                tracing::debug!("Auto-save completed for board {}", board_id_clone);
-                            }
+                    // Anything else must be a JSON message, which goes through
+                    // the same checks as a text frame; other frames are dropped
+                    match String::from_utf8(data) {
+                        Ok(text) if serde_json::from_str::<serde_json::Value>(&text).is_ok() => {
+                            text
+                        }
+                        _ => {
+                            tracing::warn!("Dropping unknown binary message");
                             continue;
                         }
                     }
-                    // Forward text messages (JSON custom messages)
-                    let _ = room_tx.send(text.into_bytes());
                 }
+                Message::Text(text) => text,
                 Message::Close(_) => break,
-                _ => {}
+                _ => continue,
+            };
+
+            let mut text = text;
+            let parsed = serde_json::from_str::<serde_json::Value>(&text);
+            let role = role_rx.borrow().clone();
+            let allowed = match &parsed {
+                Ok(msg) => permissions::check_json(&role, msg),
+                Err(_) if permissions::can_edit(&role) => Ok(()),
+                Err(_) => Err(Denied::ReadOnly),
+            };
+            if let Err(denied) = allowed {
+                deny(denied);
+                continue;
+            }
+            if let Ok(mut msg) = parsed {
+                // Every update is persisted as it is applied, so the
+                // auto-save timer's save_request has nothing left to do
+                if msg.get("type").and_then(|t| t.as_str()) == Some("save_request") {
+                    continue;
+                }
+
+                // Apply element operations to the room document
+                let doc = room_doc.read().await;
+                let result = elements::apply_message(&doc, &mut msg);
+                drop(doc);
+                match result {
+                    Ok(Some(update)) => {
+                        if let Err(e) =
+                            db::updates::append_update(&pool, board_id_clone, &update).await
+                        {
+                            tracing::error!("Failed to persist update: {}", e);
+                        }
+                        // Keep Yjs clients in sync with the JSON protocol
+                        let _ = room_tx.send(sync::create_update_message(&update));
+                    }
+                    // The operation changed nothing, so peers have nothing to apply
+                    Ok(None) if elements::is_element_message(&msg) => continue,
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!("Invalid element message: {}", e);
+                        continue;
+                    }
+                }
+                // Forward the normalised message rather than the raw input
+                text = msg.to_string();
             }
+            // Forward text messages (JSON custom messages)
+            let _ = room_tx.send(text.into_bytes());
         }
     });
 
@@ -206,9 +412,19 @@ async fn handle_socket(
     room.remove_user(&user_id).await;
     tracing::info!("User {} left board {}", username, board_id);
 
+    // Clear this socket's awareness states for the remaining clients
+    let clients: Vec<_> = awareness_clients.lock().unwrap().drain().collect();
+    match awareness::remove_clients(&room.awareness, &clients) {
+        Ok(Some(msg)) => {
+            let _ = room.tx.send(msg);
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to clear awareness states: {}", e),
+    }
+
     // Broadcast leave event
     let leave_msg = serde_json::json!({
-        "type": "leave",
+        "type": room::MSG_LEAVE,
         "userId": user_id.to_string(),
         "users": room.get_users().await,
     });
@@ -216,14 +432,414 @@ async fn handle_socket(
         .tx
         .send(serde_json::to_vec(&leave_msg).unwrap_or_default());
 
-    // Save state before closing if room is empty
+    // Compact the update log and snapshot the board when the room empties,
+    // unless the board was deleted
     if room.user_count().await == 0 {
-        let doc = room.doc.read().await;
-        let state_bytes = sync::encode_doc_state(&doc);
-        if let Err(e) = db::boards::save_yrs_state(&state.pool, board_id, &state_bytes).await {
-            tracing::error!("Failed to save board state on room close: {}", e);
+        if !room.is_deleted() {
+            let doc = room.doc.read().await;
+            let state_bytes = sync::encode_doc_state(&doc);
+            drop(doc);
+            if let Err(e) = storage::compact_board(&state.pool, board_id).await {
+                tracing::error!("Failed to compact board state on room close: {}", e);
+            }
+            if let Err(e) = snapshots::auto_snapshot(
+                &state.pool,
+                board_id,
+                &state_bytes,
+                state.config.snapshot_retention,
+            )
+            .await
+            {
+                tracing::error!("Failed to snapshot board on room close: {}", e);
+            }
         }
-        drop(doc);
         state.room_manager.remove_room_if_empty(&board_id).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::test_support::*;
+
+    #[tokio::test]
+    async fn test_upgrade_requires_credentials() {
+        let addr = serve(offline_state()).await;
+        assert_eq!(rejected_status(addr, Uuid::new_v4(), "").await, 401);
+        assert_eq!(
+            rejected_status(addr, Uuid::new_v4(), "token=not-a-jwt").await,
+            401
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_jwt_users_need_board_access() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let outsider = create_user(&pool, "ws_outsider").await;
+        let board = db::boards::create_board(&pool, "Access test", owner.id, None)
+            .await
+            .unwrap();
+        let addr = serve(test_state(pool.clone())).await;
+
+        let outsider_query = token_query(&pool, &outsider).await;
+        let owner_query = token_query(&pool, &owner).await;
+        assert_eq!(
+            rejected_status(addr, board.id, &outsider_query).await,
+            403
+        );
+        assert_eq!(
+            rejected_status(addr, Uuid::new_v4(), &owner_query).await,
+            404
+        );
+        let mut ws = connect(addr, board.id, &owner_query).await.unwrap();
+        let state = next_json(&mut ws, "sync_state").await.unwrap();
+        assert_eq!(state["role"], "owner");
+
+        delete_users(&pool, &[&owner, &outsider]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_revoked_session_is_rejected() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let board = db::boards::create_board(&pool, "Session test", owner.id, None)
+            .await
+            .unwrap();
+        let addr = serve(test_state(pool.clone())).await;
+
+        let query = token_query(&pool, &owner).await;
+        for session in db::sessions::list_sessions(&pool, owner.id).await.unwrap() {
+            db::sessions::revoke_session(&pool, owner.id, session.id)
+                .await
+                .unwrap();
+        }
+        assert_eq!(rejected_status(addr, board.id, &query).await, 401);
+
+        delete_users(&pool, &[&owner]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_removed_collaborator_is_disconnected() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let editor = create_user(&pool, "ws_editor").await;
+        let board = db::boards::create_board(&pool, "Revoke test", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, editor.id, "editor")
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let addr = serve(state.clone()).await;
+
+        let owner_query = token_query(&pool, &owner).await;
+        let mut owner_ws = connect(addr, board.id, &owner_query).await.unwrap();
+        next_json(&mut owner_ws, "sync_state").await.unwrap();
+        let editor_query = token_query(&pool, &editor).await;
+        let mut editor_ws = connect(addr, board.id, &editor_query).await.unwrap();
+        next_json(&mut editor_ws, "sync_state").await.unwrap();
+
+        db::boards::remove_collaborator(&pool, board.id, editor.id)
+            .await
+            .unwrap();
+        state
+            .room_manager
+            .notify(&board.id, RoomEvent::AccessRevoked { user_id: editor.id })
+            .await;
+
+        assert_eq!(
+            close_code(&mut editor_ws).await,
+            Some(permissions::CLOSE_ACCESS_REVOKED)
+        );
+        // Everyone else stays connected and sees the user leave
+        let leave = next_json(&mut owner_ws, "leave").await.unwrap();
+        assert_eq!(leave["userId"], editor.id.to_string());
+        assert_eq!(
+            rejected_status(addr, board.id, &editor_query).await,
+            403
+        );
+
+        delete_users(&pool, &[&owner, &editor]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_role_change_applies_live() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let editor = create_user(&pool, "ws_editor").await;
+        let board = db::boards::create_board(&pool, "Role test", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, editor.id, "editor")
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let addr = serve(state.clone()).await;
+
+        let editor_query = token_query(&pool, &editor).await;
+        let mut ws = connect(addr, board.id, &editor_query).await.unwrap();
+        next_json(&mut ws, "sync_state").await.unwrap();
+
+        let role = "viewer".to_string();
+        state
+            .room_manager
+            .notify(&board.id, RoomEvent::RoleChanged { user_id: editor.id, role })
+            .await;
+        let changed = next_json(&mut ws, "role_changed").await.unwrap();
+        assert_eq!(changed["role"], "viewer");
+
+        let element = serde_json::json!({"id": "el_1", "type": "sticky", "x": 0, "y": 0});
+        ws.send(tokio_tungstenite::tungstenite::Message::Text(
+            serde_json::json!({"type": "element_add", "element": element}).to_string(),
+        ))
+        .await
+        .unwrap();
+        let error = next_json(&mut ws, "error").await.unwrap();
+        assert_eq!(error["code"], "read_only");
+
+        delete_users(&pool, &[&owner, &editor]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_binary_json_frames_are_validated() {
+        use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let editor = create_user(&pool, "ws_editor").await;
+        let board = db::boards::create_board(&pool, "Binary test", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, editor.id, "editor")
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let addr = serve(state.clone()).await;
+
+        let owner_query = token_query(&pool, &owner).await;
+        let mut owner_ws = connect(addr, board.id, &owner_query).await.unwrap();
+        next_json(&mut owner_ws, "sync_state").await.unwrap();
+        let editor_query = token_query(&pool, &editor).await;
+        let mut editor_ws = connect(addr, board.id, &editor_query).await.unwrap();
+        next_json(&mut editor_ws, "sync_state").await.unwrap();
+
+        let invalid = serde_json::json!({
+            "type": "element_add",
+            "element": {"id": "el_bad", "type": "bogus"},
+        });
+        let valid = serde_json::json!({
+            "type": "element_add",
+            "element": {
+                "id": "el_1", "type": "sticky", "x": 0, "y": 0, "width": 200, "height": 200,
+                "color": "#FFF176", "extra": "dropped",
+            },
+        });
+        for frame in [
+            serde_json::to_vec(&invalid).unwrap(),
+            vec![0xff, 0xfe, 0xfd],
+            serde_json::to_vec(&valid).unwrap(),
+        ] {
+            editor_ws.send(ClientMessage::Binary(frame)).await.unwrap();
+        }
+
+        // Only the valid element arrives, normalised like a text frame
+        let added = next_json(&mut owner_ws, "element_add").await.unwrap();
+        assert_eq!(added["element"]["id"], "el_1");
+        assert!(added["element"].get("extra").is_none());
+        let doc = state.room_manager.get_room(&board.id).await.unwrap().doc;
+        let stored = elements::get_elements(&*doc.read().await);
+        assert_eq!(stored.len(), 1);
+
+        delete_users(&pool, &[&owner, &editor]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_element_ops_that_change_nothing_are_not_relayed() {
+        use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let editor = create_user(&pool, "ws_editor").await;
+        let board = db::boards::create_board(&pool, "No-op test", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, editor.id, "editor")
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let addr = serve(state.clone()).await;
+
+        let owner_query = token_query(&pool, &owner).await;
+        let mut owner_ws = connect(addr, board.id, &owner_query).await.unwrap();
+        next_json(&mut owner_ws, "sync_state").await.unwrap();
+        let editor_query = token_query(&pool, &editor).await;
+        let mut editor_ws = connect(addr, board.id, &editor_query).await.unwrap();
+        next_json(&mut editor_ws, "sync_state").await.unwrap();
+
+        let element = serde_json::json!({
+            "id": "el_1", "type": "sticky", "x": 0, "y": 0, "width": 200, "height": 200,
+            "color": "#FFF176",
+        });
+        for msg in [
+            serde_json::json!({"type": "element_remove", "elementId": "el_missing"}),
+            serde_json::json!({"type": "sync_state", "elements": [{"id": "el_bad", "type": "bogus"}]}),
+            serde_json::json!({"type": "cursor", "x": 1, "y": 2}),
+            serde_json::json!({"type": "element_add", "element": element}),
+        ] {
+            editor_ws
+                .send(ClientMessage::Text(msg.to_string()))
+                .await
+                .unwrap();
+        }
+
+        // Custom messages still pass; the no-op element messages never arrive
+        let mut relayed = Vec::new();
+        while let Some(Ok(msg)) = owner_ws.next().await {
+            let data = match msg {
+                ClientMessage::Binary(data) => data,
+                ClientMessage::Text(text) => text.into_bytes(),
+                _ => continue,
+            };
+            let Ok(value) = serde_json::from_slice::<serde_json::Value>(&data) else {
+                continue;
+            };
+            let msg_type = value["type"].as_str().unwrap_or_default().to_string();
+            relayed.push(msg_type.clone());
+            if msg_type == "element_add" {
+                break;
+            }
+        }
+        assert!(relayed.contains(&"cursor".to_string()));
+        assert!(!relayed.contains(&"element_remove".to_string()));
+        assert!(!relayed.contains(&"sync_state".to_string()));
+
+        delete_users(&pool, &[&owner, &editor]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_spoofed_server_frames_are_not_relayed() {
+        use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let editor = create_user(&pool, "ws_editor").await;
+        let viewer = create_user(&pool, "ws_viewer").await;
+        let board = db::boards::create_board(&pool, "Spoof test", owner.id, None)
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, editor.id, "editor")
+            .await
+            .unwrap();
+        db::boards::add_collaborator(&pool, board.id, viewer.id, "viewer")
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let addr = serve(state.clone()).await;
+
+        let owner_query = token_query(&pool, &owner).await;
+        let mut owner_ws = connect(addr, board.id, &owner_query).await.unwrap();
+        next_json(&mut owner_ws, "sync_state").await.unwrap();
+        let mut senders = Vec::new();
+        for user in [&viewer, &editor] {
+            let query = token_query(&pool, user).await;
+            let mut ws = connect(addr, board.id, &query).await.unwrap();
+            next_json(&mut ws, "sync_state").await.unwrap();
+            senders.push(ws);
+        }
+
+        let spoofed = serde_json::json!({"type": room::MSG_BOARD_DELETED});
+        for ws in senders.iter_mut() {
+            ws.send(ClientMessage::Text(spoofed.to_string()))
+                .await
+                .unwrap();
+            ws.send(ClientMessage::Binary(serde_json::to_vec(&spoofed).unwrap()))
+                .await
+                .unwrap();
+        }
+        let marker = serde_json::json!({"type": "cursor", "x": 1, "y": 2});
+        senders[1]
+            .send(ClientMessage::Text(marker.to_string()))
+            .await
+            .unwrap();
+
+        // Frames are relayed in order, so anything spoofed would arrive first
+        let mut relayed = Vec::new();
+        while let Some(Ok(msg)) = owner_ws.next().await {
+            let data = match msg {
+                ClientMessage::Binary(data) => data,
+                ClientMessage::Text(text) => text.into_bytes(),
+                _ => continue,
+            };
+            let Ok(value) = serde_json::from_slice::<serde_json::Value>(&data) else {
+                continue;
+            };
+            let msg_type = value["type"].as_str().unwrap_or_default().to_string();
+            relayed.push(msg_type.clone());
+            if msg_type == "cursor" {
+                break;
+            }
+        }
+        assert_eq!(relayed.last().map(String::as_str), Some("cursor"));
+        assert!(!relayed.contains(&room::MSG_BOARD_DELETED.to_string()));
+
+        delete_users(&pool, &[&owner, &editor, &viewer]).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_share_link_revocation_and_board_deletion() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "ws_owner").await;
+        let board = db::boards::create_board(&pool, "Delete test", owner.id, None)
+            .await
+            .unwrap();
+        let token = Uuid::new_v4().simple().to_string();
+        let link = db::boards::create_share_link(&pool, board.id, &token, "editor", None, owner.id)
+            .await
+            .unwrap();
+        let state = test_state(pool.clone());
+        let addr = serve(state.clone()).await;
+
+        let owner_query = token_query(&pool, &owner).await;
+        let mut owner_ws = connect(addr, board.id, &owner_query).await.unwrap();
+        next_json(&mut owner_ws, "sync_state").await.unwrap();
+        let share_query = format!("share_token={}", token);
+        let mut guest_ws = connect(addr, board.id, &share_query).await.unwrap();
+        next_json(&mut guest_ws, "sync_state").await.unwrap();
+
+        // Revoking the link only disconnects its guests
+        db::boards::delete_share_link(&pool, board.id, link.id)
+            .await
+            .unwrap();
+        state
+            .room_manager
+            .notify(&board.id, RoomEvent::ShareLinkRevoked { link_id: link.id })
+            .await;
+        assert_eq!(
+            close_code(&mut guest_ws).await,
+            Some(permissions::CLOSE_ACCESS_REVOKED)
+        );
+        next_json(&mut owner_ws, "leave").await.unwrap();
+
+        db::boards::delete_board(&pool, board.id).await.unwrap();
+        state
+            .room_manager
+            .notify(&board.id, RoomEvent::BoardDeleted)
+            .await;
+        next_json(&mut owner_ws, "board_deleted").await.unwrap();
+        assert_eq!(
+            close_code(&mut owner_ws).await,
+            Some(permissions::CLOSE_BOARD_DELETED)
+        );
+
+        delete_users(&pool, &[&owner]).await;
+    }
+}
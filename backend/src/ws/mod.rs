@@ -1,3 +1,12 @@
+pub mod awareness;
+pub mod elements;
 pub mod handler;
+pub mod permissions;
 pub mod room;
+pub mod shutdown;
+pub mod snapshots;
+pub mod storage;
 pub mod sync;
+
+#[cfg(test)]
+pub(crate) mod test_support;
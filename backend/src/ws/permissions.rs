@@ -0,0 +1,165 @@
+use serde_json::Value;
+
+use super::room;
+use super::sync;
+
+/// Close code for sockets whose user lost access to the board
+pub const CLOSE_ACCESS_REVOKED: u16 = 4403;
+
+/// Close code for sockets on a board that was deleted
+pub const CLOSE_BOARD_DELETED: u16 = 4404;
+
+/// Why a message from a socket session was not applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    /// The message would edit the board; the client is told so
+    ReadOnly,
+    /// The message is a client's own state merge on connect, or poses as a
+    /// server frame; dropped quietly
+    Silent,
+}
+
+/// Whether a board role may edit the board
+pub fn can_edit(role: &str) -> bool {
+    matches!(role, "owner" | "admin" | "editor")
+}
+
+/// Check a binary frame against the session's role
+pub fn check_binary(role: &str, data: &[u8]) -> Result<(), Denied> {
+    if can_edit(role) || data.is_empty() {
+        return Ok(());
+    }
+    match data[0] {
+        // Sync step 1 only asks for state; step 2 and updates write it
+        sync::MSG_SYNC => match sync::decode_update_message(data) {
+            Ok(None) => Ok(()),
+            _ => Err(Denied::ReadOnly),
+        },
+        sync::MSG_AWARENESS => Ok(()),
+        // Custom JSON messages sent as binary are forwarded to other clients
+        _ => match serde_json::from_slice::<Value>(data) {
+            Ok(msg) => check_json(role, &msg),
+            Err(_) => Err(Denied::ReadOnly),
+        },
+    }
+}
+
+/// Check a JSON protocol message against the session's role. Messages
+/// posing as server frames are dropped for every role.
+pub fn check_json(role: &str, msg: &Value) -> Result<(), Denied> {
+    let msg_type = msg.get("type").and_then(|t| t.as_str());
+    if msg_type.is_some_and(|t| room::SERVER_MESSAGE_TYPES.contains(&t)) {
+        return Err(Denied::Silent);
+    }
+    if can_edit(role) {
+        return Ok(());
+    }
+    match msg_type {
+        Some("element_add") | Some("element_update") | Some("element_remove") => {
+            Err(Denied::ReadOnly)
+        }
+        Some("sync_state") => Err(Denied::Silent),
+        _ => Ok(()),
+    }
+}
+
+/// The error frame sent back to a client whose edit was dropped
+pub fn read_only_error() -> Value {
+    serde_json::json!({
+        "type": room::MSG_ERROR,
+        "code": "read_only",
+        "message": "You have view-only access to this board",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use yrs::{Doc, Map, Transact};
+
+    const ROLES: [&str; 4] = ["owner", "admin", "editor", "viewer"];
+
+    fn update_frame() -> Vec<u8> {
+        let doc = Doc::new();
+        let map = doc.get_or_insert_map("elements");
+        map.insert(&mut doc.transact_mut(), "el_1", "x");
+        sync::create_update_message(&sync::encode_doc_state(&doc))
+    }
+
+    fn element_messages() -> Vec<Value> {
+        vec![
+            json!({"type": "element_add", "element": {"id": "el_1", "type": "sticky"}}),
+            json!({"type": "element_update", "element": {"id": "el_1", "type": "sticky"}}),
+            json!({"type": "element_remove", "id": "el_1"}),
+        ]
+    }
+
+    #[test]
+    fn test_editing_roles_may_write() {
+        for role in ["owner", "admin", "editor"] {
+            assert!(can_edit(role));
+            assert_eq!(check_binary(role, &update_frame()), Ok(()));
+            for msg in element_messages() {
+                assert_eq!(check_json(role, &msg), Ok(()), "{} {}", role, msg);
+            }
+            assert_eq!(
+                check_json(role, &json!({"type": "sync_state", "elements": []})),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn test_viewer_writes_are_denied() {
+        assert!(!can_edit("viewer"));
+        assert_eq!(
+            check_binary("viewer", &update_frame()),
+            Err(Denied::ReadOnly)
+        );
+        for msg in element_messages() {
+            assert_eq!(check_json("viewer", &msg), Err(Denied::ReadOnly));
+            // Binary JSON frames are forwarded too, so they get the same check
+            let bytes = serde_json::to_vec(&msg).unwrap();
+            assert_eq!(check_binary("viewer", &bytes), Err(Denied::ReadOnly));
+        }
+        assert_eq!(
+            check_json("viewer", &json!({"type": "sync_state", "elements": []})),
+            Err(Denied::Silent)
+        );
+    }
+
+    #[test]
+    fn test_every_role_may_read_and_share_presence() {
+        let doc = Doc::new();
+        let step1 = sync::create_sync_step1(&doc).unwrap();
+        let awareness = [sync::MSG_AWARENESS, 0];
+        for role in ROLES {
+            assert_eq!(check_binary(role, &step1), Ok(()));
+            assert_eq!(check_binary(role, &awareness), Ok(()));
+            assert_eq!(
+                check_json(role, &json!({"type": "cursor", "x": 1, "y": 2})),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn test_server_frames_are_denied_for_every_role() {
+        for role in ROLES {
+            for msg_type in room::SERVER_MESSAGE_TYPES {
+                let msg = json!({"type": msg_type});
+                assert_eq!(check_json(role, &msg), Err(Denied::Silent), "{} {}", role, msg);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_role_is_read_only() {
+        assert!(!can_edit("guest"));
+        assert_eq!(
+            check_binary("guest", &update_frame()),
+            Err(Denied::ReadOnly)
+        );
+    }
+}
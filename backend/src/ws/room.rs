@@ -1,14 +1,89 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 use uuid::Uuid;
+use yrs::sync::Awareness;
 use yrs::Doc;
 
+/// Someone joined the room
+pub const MSG_JOIN: &str = "join";
+/// Someone left the room
+pub const MSG_LEAVE: &str = "leave";
+/// A message from this client was refused
+pub const MSG_ERROR: &str = "error";
+/// The server is going away and the client should reconnect
+pub const MSG_SERVER_SHUTDOWN: &str = "server_shutdown";
+pub const MSG_ROLE_CHANGED: &str = "role_changed";
+pub const MSG_BOARD_DELETED: &str = "board_deleted";
+pub const MSG_ACCESS_REQUESTED: &str = "access_requested";
+
+/// Message types only the server sends. Client frames of these types are
+/// never relayed, whatever the sender's role.
+pub const SERVER_MESSAGE_TYPES: [&str; 7] = [
+    MSG_JOIN,
+    MSG_LEAVE,
+    MSG_ERROR,
+    MSG_SERVER_SHUTDOWN,
+    MSG_ROLE_CHANGED,
+    MSG_BOARD_DELETED,
+    MSG_ACCESS_REQUESTED,
+];
+
+/// Instructions for the sockets connected to a room, outside the document stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomEvent {
+    /// The user lost access to the board and must be disconnected
+    AccessRevoked { user_id: Uuid },
+    /// Guests who joined through this share link must be disconnected
+    ShareLinkRevoked { link_id: Uuid },
+    /// The user's role on the board changed
+    RoleChanged { user_id: Uuid, role: String },
+    /// The board no longer exists; everyone is disconnected
+    BoardDeleted,
+    /// Someone without access asked for it; shown to owners and admins
+    AccessRequested {
+        request_id: Uuid,
+        user_id: Uuid,
+        username: String,
+        message: Option<String>,
+    },
+}
+
+impl RoomEvent {
+    /// The JSON frame a socket the event applies to is sent, if any
+    pub fn message(&self) -> Option<serde_json::Value> {
+        match self {
+            RoomEvent::AccessRevoked { .. } | RoomEvent::ShareLinkRevoked { .. } => None,
+            RoomEvent::RoleChanged { role, .. } => {
+                Some(serde_json::json!({"type": MSG_ROLE_CHANGED, "role": role}))
+            }
+            RoomEvent::BoardDeleted => Some(serde_json::json!({"type": MSG_BOARD_DELETED})),
+            RoomEvent::AccessRequested {
+                request_id,
+                user_id,
+                username,
+                message,
+            } => Some(serde_json::json!({
+                "type": MSG_ACCESS_REQUESTED,
+                "request_id": request_id,
+                "user_id": user_id,
+                "username": username,
+                "message": message,
+            })),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Room {
     pub board_id: Uuid,
     pub doc: Arc<RwLock<Doc>>,
     pub tx: broadcast::Sender<Vec<u8>>,
+    pub awareness: Arc<Awareness>,
+    pub events: broadcast::Sender<RoomEvent>,
+    /// Set once the board is deleted, so closing the room persists nothing
+    pub deleted: Arc<AtomicBool>,
     pub users: Arc<RwLock<HashMap<Uuid, ConnectedUser>>>,
 }
 
@@ -22,10 +97,14 @@ pub struct ConnectedUser {
 impl Room {
     pub fn new(board_id: Uuid) -> Self {
         let (tx, _) = broadcast::channel(256);
+        let (events, _) = broadcast::channel(16);
         Room {
             board_id,
             doc: Arc::new(RwLock::new(Doc::new())),
             tx,
+            awareness: Arc::new(Awareness::default()),
+            events,
+            deleted: Arc::new(AtomicBool::new(false)),
             users: Arc::new(RwLock::new(HashMap::new())),
         }
     }
@@ -48,6 +127,10 @@ impl Room {
         self.users.write().await.remove(user_id);
     }
 
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.load(Ordering::SeqCst)
+    }
+
     pub async fn user_count(&self) -> usize {
         self.users.read().await.len()
     }
@@ -99,6 +182,20 @@ impl RoomManager {
     pub async fn get_room(&self, board_id: &Uuid) -> Option<Room> {
         self.rooms.read().await.get(board_id).cloned()
     }
+
+    pub async fn list_rooms(&self) -> Vec<Room> {
+        self.rooms.read().await.values().cloned().collect()
+    }
+
+    /// Push a permission change to the sockets in a board's open room, if any
+    pub async fn notify(&self, board_id: &Uuid, event: RoomEvent) {
+        if let Some(room) = self.get_room(board_id).await {
+            if event == RoomEvent::BoardDeleted {
+                room.deleted.store(true, Ordering::SeqCst);
+            }
+            let _ = room.events.send(event);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -183,6 +280,33 @@ mod tests {
         assert!(manager.get_room(&board_id).await.is_none());
     }
 
+    #[tokio::test]
+    async fn test_room_manager_notify() {
+        let manager = RoomManager::new();
+        let board_id = Uuid::new_v4();
+        let uid = Uuid::new_v4();
+
+        // No open room, nothing to notify
+        manager
+            .notify(&board_id, RoomEvent::AccessRevoked { user_id: uid })
+            .await;
+
+        let room = manager.get_or_create_room(board_id).await;
+        let mut events = room.events.subscribe();
+        manager
+            .notify(&board_id, RoomEvent::AccessRevoked { user_id: uid })
+            .await;
+        assert_eq!(
+            events.recv().await.unwrap(),
+            RoomEvent::AccessRevoked { user_id: uid }
+        );
+        assert!(!room.is_deleted());
+
+        manager.notify(&board_id, RoomEvent::BoardDeleted).await;
+        assert_eq!(events.recv().await.unwrap(), RoomEvent::BoardDeleted);
+        assert!(room.is_deleted());
+    }
+
     #[tokio::test]
     async fn test_room_manager_multiple_boards() {
         let manager = RoomManager::new();
@@ -194,6 +318,7 @@ mod tests {
 
         assert!(manager.get_room(&board1).await.is_some());
         assert!(manager.get_room(&board2).await.is_some());
+        assert_eq!(manager.list_rooms().await.len(), 2);
 
         // Removing one should not affect the other
         manager.remove_room_if_empty(&board1).await;
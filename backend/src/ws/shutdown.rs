@@ -0,0 +1,208 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use super::handler::AppState;
+use super::room;
+use super::storage;
+
+/// How long clients are told to wait before reconnecting after a shutdown
+pub const RECONNECT_AFTER_MS: u64 = 5000;
+
+/// How long open connections get to close and persist their rooms
+const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+/// Server-wide shutdown flag that WebSocket connections can wait on
+pub struct Shutdown {
+    tx: watch::Sender<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (tx, _) = watch::channel(false);
+        Shutdown { tx }
+    }
+
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.tx.subscribe()
+    }
+}
+
+/// The message sent to every connected client before its socket is closed
+pub fn shutdown_message() -> serde_json::Value {
+    serde_json::json!({
+        "type": room::MSG_SERVER_SHUTDOWN,
+        "reconnectAfterMs": RECONNECT_AFTER_MS,
+    })
+}
+
+/// Resolve on ctrl-c or SIGTERM
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Wait for `signal`, then close every WebSocket connection and persist every
+/// open room. Meant for `axum::serve(..).with_graceful_shutdown(..)`.
+pub async fn graceful(state: Arc<AppState>, signal: impl Future<Output = ()>) {
+    signal.await;
+    tracing::info!("Shutting down, closing WebSocket connections");
+    shutdown(&state).await;
+}
+
+/// Reject new upgrades, tell connected clients to reconnect later and flush
+/// every open room to the database.
+pub async fn shutdown(state: &AppState) {
+    state.shutdown.trigger();
+
+    // Connections persist their room as the last user leaves
+    let deadline = tokio::time::Instant::now() + CLOSE_GRACE;
+    while !state.room_manager.list_rooms().await.is_empty()
+        && tokio::time::Instant::now() < deadline
+    {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Whatever is still open did not close in time
+    for room in state.room_manager.list_rooms().await {
+        if let Err(e) = storage::flush_room(&state.pool, &room).await {
+            tracing::error!(
+                "Failed to persist board {} on shutdown: {}",
+                room.board_id,
+                e
+            );
+        }
+    }
+    tracing::info!("All rooms persisted");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::ws::test_support::*;
+    use futures_util::SinkExt;
+    use std::future::IntoFuture;
+    use tokio_tungstenite::tungstenite::Message;
+    use uuid::Uuid;
+    use yrs::Doc;
+
+    #[test]
+    fn test_shutdown_flag() {
+        let shutdown = Shutdown::new();
+        let rx = shutdown.subscribe();
+        assert!(!shutdown.is_triggered());
+        shutdown.trigger();
+        assert!(shutdown.is_triggered());
+        assert!(*rx.borrow());
+        assert_eq!(shutdown_message()["type"], "server_shutdown");
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_rejected_during_shutdown() {
+        // The shutdown check runs before anything touches the database
+        let state = offline_state();
+        state.shutdown.trigger();
+        let addr = serve(state).await;
+
+        let token =
+            crate::auth::create_token(Uuid::new_v4(), "alice", Uuid::new_v4(), JWT_SECRET, 15)
+                .unwrap();
+        let query = format!("token={}", token);
+        assert_eq!(rejected_status(addr, Uuid::new_v4(), &query).await, 503);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_edits_survive_shutdown() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "shutdown").await;
+        let board = db::boards::create_board(&pool, "Shutdown test", user.id, None)
+            .await
+            .unwrap();
+
+        let state = test_state(pool.clone());
+        let (listener, addr) = listener().await;
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(
+            axum::serve(listener, app(state.clone()))
+                .with_graceful_shutdown(graceful(state.clone(), async {
+                    let _ = signal_rx.await;
+                }))
+                .into_future(),
+        );
+
+        let query = token_query(&pool, &user).await;
+        let mut ws = connect(addr, board.id, &query).await.unwrap();
+        next_json(&mut ws, "sync_state").await.unwrap();
+
+        // Edit right before the shutdown signal
+        let element = serde_json::json!({
+            "id": "el_shutdown", "type": "sticky", "x": 10, "y": 20,
+            "width": 200, "height": 200, "color": "#FFF176", "content": "survives"
+        });
+        ws.send(Message::Text(
+            serde_json::json!({"type": "element_add", "element": element}).to_string(),
+        ))
+        .await
+        .unwrap();
+        next_json(&mut ws, "element_add").await.unwrap();
+        signal_tx.send(()).unwrap();
+
+        let msg = next_json(&mut ws, "server_shutdown").await.unwrap();
+        assert_eq!(msg["reconnectAfterMs"], RECONNECT_AFTER_MS);
+        tokio::time::timeout(Duration::from_secs(10), server)
+            .await
+            .expect("server should exit")
+            .unwrap()
+            .unwrap();
+
+        // A fresh server loads the edit from the database
+        let doc = Doc::new();
+        assert!(storage::load_board(&pool, board.id, &doc).await.unwrap());
+        let elements = crate::ws::elements::get_elements(&doc);
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0]["content"], "survives");
+        assert!(db::updates::list_updates(&pool, board.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        delete_users(&pool, &[&user]).await;
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+use yrs::Doc;
+
+use super::elements;
+use super::handler::AppState;
+use super::sync;
+use crate::db;
+use crate::model::Element;
+
+/// Store an automatic snapshot of a board unless its state is unchanged since
+/// the last snapshot, then prune old automatic snapshots.
+pub async fn auto_snapshot(
+    pool: &PgPool,
+    board_id: Uuid,
+    state: &[u8],
+    retention: i64,
+) -> Result<bool> {
+    if db::snapshots::get_latest_state(pool, board_id)
+        .await?
+        .as_deref()
+        == Some(state)
+    {
+        return Ok(false);
+    }
+    db::snapshots::create_snapshot(pool, board_id, None, db::snapshots::KIND_AUTO, state, None)
+        .await?;
+    db::snapshots::prune_auto_snapshots(pool, board_id, retention).await?;
+    Ok(true)
+}
+
+/// Take an automatic snapshot of every open room
+pub async fn snapshot_open_rooms(state: &AppState) {
+    for room in state.room_manager.list_rooms().await {
+        let doc = room.doc.read().await;
+        let state_bytes = sync::encode_doc_state(&doc);
+        drop(doc);
+        if let Err(e) = auto_snapshot(
+            &state.pool,
+            room.board_id,
+            &state_bytes,
+            state.config.snapshot_retention,
+        )
+        .await
+        {
+            tracing::error!("Failed to snapshot board {}: {}", room.board_id, e);
+        }
+    }
+}
+
+/// Snapshot open rooms every `snapshot_interval_minutes`
+pub fn spawn_periodic_snapshots(state: Arc<AppState>) {
+    let minutes = state.config.snapshot_interval_minutes;
+    if minutes == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(minutes * 60));
+        // The first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            snapshot_open_rooms(&state).await;
+        }
+    });
+}
+
+/// Compute the change that turns `doc` back into the snapshot content. Returns
+/// the encoded Yrs update and the element messages for JSON protocol clients,
+/// or None if the document already matches the snapshot.
+pub fn restore_snapshot(doc: &Doc, snapshot_state: &[u8]) -> Result<Option<(Vec<u8>, Vec<Value>)>> {
+    let target = snapshot_elements(snapshot_state)?;
+    elements::replace_elements(doc, &target)
+}
+
+/// Decode the elements stored in a snapshot
+pub fn snapshot_elements(snapshot_state: &[u8]) -> Result<Vec<Element>> {
+    let snapshot_doc = Doc::new();
+    sync::load_doc_state(&snapshot_doc, snapshot_state)?;
+    Ok(elements::get_elements(&snapshot_doc)
+        .into_iter()
+        .filter_map(|v| Element::from_json(v).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sticky(id: &str, content: &str) -> Element {
+        Element::from_json(json!({
+            "id": id, "type": "sticky", "x": 0, "y": 0, "width": 200, "height": 200,
+            "color": "#FFF176", "content": content
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_restore_snapshot_reverts_changes() {
+        let doc = Doc::new();
+        elements::upsert_element(&doc, &sticky("el_1", "keep me")).unwrap();
+        let snapshot = sync::encode_doc_state(&doc);
+
+        // Bulk delete and new content after the snapshot
+        elements::remove_element(&doc, "el_1");
+        elements::upsert_element(&doc, &sticky("el_2", "added later")).unwrap();
+        let before_restore = sync::encode_doc_state(&doc);
+
+        let (update, messages) = restore_snapshot(&doc, &snapshot)
+            .unwrap()
+            .expect("restore should change the document");
+        assert_eq!(messages.len(), 2);
+
+        let restored = elements::get_elements(&doc);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0]["content"], "keep me");
+
+        // The update is a regular Yrs update that converges connected replicas
+        let replica = Doc::new();
+        sync::load_doc_state(&replica, &before_restore).unwrap();
+        sync::load_doc_state(&replica, &update).unwrap();
+        let replica_elements = elements::get_elements(&replica);
+        assert_eq!(replica_elements.len(), 1);
+        assert_eq!(replica_elements[0]["content"], "keep me");
+    }
+
+    #[test]
+    fn test_restore_unchanged_snapshot_is_noop() {
+        let doc = Doc::new();
+        elements::upsert_element(&doc, &sticky("el_1", "a")).unwrap();
+        let snapshot = sync::encode_doc_state(&doc);
+        assert!(restore_snapshot(&doc, &snapshot).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_snapshot_elements_rejects_invalid_state() {
+        assert!(snapshot_elements(&[0xFF, 0xFF, 0xFF]).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use sqlx::PgPool;
+use uuid::Uuid;
+use yrs::{Doc, ReadTxn, Transact};
+
+use super::handler::AppState;
+use super::room::Room;
+use super::sync;
+use crate::db;
+
+/// Load a board's persisted document: the compacted base state in
+/// `boards.yrs_state` followed by the update log tail. Returns false if the
+/// board does not exist.
+pub async fn load_board(pool: &PgPool, board_id: Uuid, doc: &Doc) -> Result<bool> {
+    let board = match db::boards::get_board(pool, board_id).await? {
+        Some(b) => b,
+        None => return Ok(false),
+    };
+    let updates: Vec<Vec<u8>> = db::updates::list_updates(pool, board_id)
+        .await?
+        .into_iter()
+        .map(|u| u.update_data)
+        .collect();
+    sync::load_doc_with_updates(doc, board.yrs_state.as_deref(), &updates)?;
+    Ok(true)
+}
+
+/// Merge a board's update log into its base state
+pub async fn compact_board(pool: &PgPool, board_id: Uuid) -> Result<usize> {
+    db::updates::compact(pool, board_id, |base, updates| {
+        let doc = Doc::new();
+        sync::load_doc_with_updates(&doc, base, updates)?;
+        Ok(sync::encode_doc_state(&doc))
+    })
+    .await
+}
+
+/// Persist a live room: append whatever its document has that the stored
+/// state lacks, then compact the log.
+pub async fn flush_room(pool: &PgPool, room: &Room) -> Result<()> {
+    let stored = Doc::new();
+    if !load_board(pool, room.board_id, &stored).await? {
+        return Ok(());
+    }
+    let stored_state = sync::encode_doc_state(&stored);
+    let stored_sv = stored.transact().state_vector();
+    let missing = room.doc.read().await.transact().encode_diff_v1(&stored_sv);
+    // The diff always repeats known deletions, so only append if it changes anything
+    sync::load_doc_state(&stored, &missing)?;
+    if sync::encode_doc_state(&stored) != stored_state {
+        db::updates::append_update(pool, room.board_id, &missing).await?;
+    }
+    compact_board(pool, room.board_id).await?;
+    Ok(())
+}
+
+/// Compact every board with a pending update log
+pub async fn compact_all(pool: &PgPool) {
+    let boards = match db::updates::boards_with_updates(pool, 1).await {
+        Ok(b) => b,
+        Err(e) => {
+            tracing::error!("Failed to list boards for compaction: {}", e);
+            return;
+        }
+    };
+    for board_id in boards {
+        match compact_board(pool, board_id).await {
+            Ok(n) => tracing::debug!("Compacted {} updates for board {}", n, board_id),
+            Err(e) => tracing::error!("Failed to compact board {}: {}", board_id, e),
+        }
+    }
+}
+
+/// Compact update logs every `compaction_interval_seconds`
+pub fn spawn_compaction(state: Arc<AppState>) {
+    let seconds = state.config.compaction_interval_seconds;
+    if seconds == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        // The first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            compact_all(&state.pool).await;
+        }
+    });
+}
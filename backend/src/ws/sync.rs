@@ -74,6 +74,37 @@ pub fn handle_sync_message(doc: &Doc, msg: &[u8]) -> Result<Option<Vec<u8>>> {
     }
 }
 
+/// Extract the update carried by a sync step 2 or update message
+pub fn decode_update_message(msg: &[u8]) -> Result<Option<Vec<u8>>> {
+    if msg.is_empty() {
+        return Ok(None);
+    }
+
+    let mut decoder = DecoderV1::from(msg);
+    let msg_type: u32 = decoder.read_var()?;
+    if msg_type != MSG_SYNC as u32 {
+        return Ok(None);
+    }
+
+    let sync_type: u32 = decoder.read_var()?;
+    match sync_type as u8 {
+        MSG_SYNC_STEP2 | MSG_SYNC_UPDATE => Ok(Some(decoder.read_buf()?.to_vec())),
+        _ => Ok(None),
+    }
+}
+
+/// Rebuild a document from a base state followed by incremental updates
+pub fn load_doc_with_updates(doc: &Doc, base: Option<&[u8]>, updates: &[Vec<u8>]) -> Result<()> {
+    let mut txn = doc.transact_mut();
+    if let Some(base) = base {
+        txn.apply_update(Update::decode_v1(base)?)?;
+    }
+    for update in updates {
+        txn.apply_update(Update::decode_v1(update)?)?;
+    }
+    Ok(())
+}
+
 /// Encode full document state for persistence
 pub fn encode_doc_state(doc: &Doc) -> Vec<u8> {
     let txn = doc.transact();
@@ -167,6 +198,49 @@ mod tests {
         assert_eq!(msg[0], MSG_SYNC);
     }
 
+    #[test]
+    fn test_decode_update_message() {
+        let update_data = vec![1, 2, 3, 4];
+        let msg = create_update_message(&update_data);
+        let decoded = decode_update_message(&msg).expect("should decode");
+        assert_eq!(decoded, Some(update_data));
+
+        // Step 1 carries a state vector, not an update
+        let step1 = create_sync_step1(&Doc::new()).unwrap();
+        assert!(decode_update_message(&step1).unwrap().is_none());
+        assert!(decode_update_message(&[MSG_AWARENESS, 0]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_load_doc_with_updates() {
+        let source = Doc::new();
+        let map = source.get_or_insert_map("elements");
+        let base = {
+            let mut txn = source.transact_mut();
+            map.insert(&mut txn, "el1", "rectangle");
+            txn.encode_update_v1()
+        };
+        let tail: Vec<Vec<u8>> = ["el2", "el3"]
+            .iter()
+            .map(|key| {
+                let mut txn = source.transact_mut();
+                map.insert(&mut txn, *key, "circle");
+                txn.encode_update_v1()
+            })
+            .collect();
+
+        let doc = Doc::new();
+        load_doc_with_updates(&doc, Some(&base), &tail).expect("should load");
+        assert_eq!(encode_doc_state(&doc), encode_doc_state(&source));
+
+        // The log alone is enough when there is no compacted base yet
+        let doc = Doc::new();
+        let mut all = vec![base];
+        all.extend(tail);
+        load_doc_with_updates(&doc, None, &all).expect("should load");
+        assert_eq!(encode_doc_state(&doc), encode_doc_state(&source));
+    }
+
     #[test]
     fn test_load_invalid_state_fails() {
         let doc = Doc::new();
@@ -0,0 +1,209 @@
+//! Helpers for tests that drive the WebSocket endpoint over a real socket.
+//! Tests that also need Postgres read `TEST_DATABASE_URL` and are `#[ignore]`d.
+
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::{routing::get, Router};
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+use super::handler::{ws_handler, AppState};
+use super::room::RoomManager;
+use super::shutdown::Shutdown;
+use crate::config::Config;
+use crate::db;
+use crate::mail::outbox::Outbox;
+use crate::mail::Mailer;
+
+pub const JWT_SECRET: &str = "test-secret";
+
+pub type TestSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub fn test_state(pool: PgPool) -> Arc<AppState> {
+    test_state_with_mailer(pool, Arc::new(Outbox::in_memory()))
+}
+
+/// A state whose email goes to the given mailer, so tests can read it back
+pub fn test_state_with_mailer(pool: PgPool, mailer: Arc<dyn Mailer>) -> Arc<AppState> {
+    build_state(pool, test_config(), mailer)
+}
+
+/// A state with adjusted configuration
+pub fn test_state_with(pool: PgPool, config: Config) -> Arc<AppState> {
+    build_state(pool, config, Arc::new(Outbox::in_memory()))
+}
+
+fn build_state(pool: PgPool, config: Config, mailer: Arc<dyn Mailer>) -> Arc<AppState> {
+    Arc::new(AppState {
+        pool,
+        room_manager: RoomManager::new(),
+        jwt_secret: JWT_SECRET.to_string(),
+        config,
+        mailer,
+        shutdown: Shutdown::new(),
+    })
+}
+
+pub fn test_config() -> Config {
+    Config {
+        database_url: String::new(),
+        jwt_secret: JWT_SECRET.to_string(),
+        host: "127.0.0.1".to_string(),
+        port: 0,
+        snapshot_interval_minutes: 0,
+        snapshot_retention: 50,
+        compaction_interval_seconds: 0,
+        access_token_minutes: 15,
+        refresh_token_days: 30,
+        public_url: "http://localhost:3000".to_string(),
+        smtp_url: None,
+        mail_from: "Whiteboard <noreply@localhost>".to_string(),
+        mail_outbox_dir: String::new(),
+        oidc_issuer_url: None,
+        oidc_client_id: None,
+        oidc_client_secret: None,
+        oidc_scopes: "openid email profile".to_string(),
+        oidc_provider_name: "Single sign-on".to_string(),
+        require_two_factor: false,
+        two_factor_issuer: "Udstillerguide Whiteboard".to_string(),
+        rate_limit_window_seconds: 60,
+        rate_limit_per_ip: 60,
+        rate_limit_per_account: 10,
+        rate_limit_store: "memory".to_string(),
+        trust_proxy_headers: false,
+        lockout_threshold: 5,
+        lockout_base_seconds: 30,
+        lockout_max_seconds: 3600,
+        // Cheap hashing keeps the tests fast
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        export_link_hours: 24,
+    }
+}
+
+/// A state whose pool never connects, for paths that reject before touching the database
+pub fn offline_state() -> Arc<AppState> {
+    test_state(PgPool::connect_lazy("postgres://localhost/unused").unwrap())
+}
+
+pub fn app(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/ws/:board_id", get(ws_handler))
+        .with_state(state)
+}
+
+pub async fn listener() -> (tokio::net::TcpListener, SocketAddr) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+/// Serve the WebSocket route in the background
+pub async fn serve(state: Arc<AppState>) -> SocketAddr {
+    let (listener, addr) = listener().await;
+    tokio::spawn(axum::serve(listener, app(state)).into_future());
+    addr
+}
+
+pub async fn connect(
+    addr: SocketAddr,
+    board_id: Uuid,
+    query: &str,
+) -> Result<TestSocket, tungstenite::Error> {
+    let url = format!("ws://{}/ws/{}?{}", addr, board_id, query);
+    tokio_tungstenite::connect_async(url)
+        .await
+        .map(|(ws, _)| ws)
+}
+
+/// The HTTP status a rejected upgrade was answered with
+pub async fn rejected_status(addr: SocketAddr, board_id: Uuid, query: &str) -> u16 {
+    match connect(addr, board_id, query).await {
+        Err(tungstenite::Error::Http(response)) => response.status().as_u16(),
+        Err(e) => panic!("unexpected connection error: {}", e),
+        Ok(_) => panic!("expected the upgrade to be rejected"),
+    }
+}
+
+/// Read until a JSON message of the given type arrives
+pub async fn next_json(ws: &mut TestSocket, msg_type: &str) -> Option<serde_json::Value> {
+    while let Some(Ok(msg)) = ws.next().await {
+        let data = match msg {
+            Message::Binary(data) => data,
+            Message::Text(text) => text.into_bytes(),
+            _ => continue,
+        };
+        if let Ok(value) = serde_json::from_slice::<serde_json::Value>(&data) {
+            if value["type"] == msg_type {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+/// Read until the server closes the socket and return the close code
+pub async fn close_code(ws: &mut TestSocket) -> Option<u16> {
+    while let Some(msg) = ws.next().await {
+        match msg {
+            Ok(Message::Close(frame)) => return frame.map(|f| f.code.into()),
+            Ok(_) => continue,
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+pub async fn test_pool() -> PgPool {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+    let pool = db::create_pool(&url).await.unwrap();
+    db::run_migrations(&pool).await.unwrap();
+    pool
+}
+
+pub async fn create_user(pool: &PgPool, prefix: &str) -> db::users::User {
+    let suffix = Uuid::new_v4().simple().to_string();
+    db::users::create_user(
+        pool,
+        &format!("{}_{}", prefix, &suffix[..12]),
+        &format!("{}_{}@example.com", prefix, suffix),
+        "x",
+    )
+    .await
+    .unwrap()
+}
+
+/// Start a session for the user and return the WebSocket query carrying its access token
+pub async fn token_query(pool: &PgPool, user: &db::users::User) -> String {
+    let refresh_token = crate::auth::generate_secret_token();
+    let session = db::sessions::create_session(
+        pool,
+        user.id,
+        &crate::auth::hash_secret_token(&refresh_token),
+        None,
+        chrono::Utc::now() + chrono::Duration::days(1),
+    )
+    .await
+    .unwrap();
+    let token =
+        crate::auth::create_token(user.id, &user.username, session.id, JWT_SECRET, 15).unwrap();
+    format!("token={}", token)
+}
+
+/// Remove test users; their boards cascade
+pub async fn delete_users(pool: &PgPool, users: &[&db::users::User]) {
+    for user in users {
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user.id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+}
@@ -1,5 +1,9 @@
 // Main app entry point - handles routing between auth, dashboard, and board views
-import { isLoggedIn, login, register, logout, apiFetch, getUser } from '/js/auth.js';
+import {
+    isLoggedIn, login, register, logout, apiFetch, getUser, setUser,
+    verifyEmail, forgotPassword, resetPassword, getSsoProvider, completeSso,
+    verifyTwoFactor, startTwoFactorSetup, confirmTwoFactorSetup,
+} from '/js/auth.js';
 
 class App {
     constructor() {
@@ -17,14 +21,37 @@ class App {
             return;
         }
 
+        // Links from verification and password reset emails
+        const verifyToken = params.get('verify');
+        if (verifyToken) {
+            this.handleVerifyEmail(verifyToken);
+            return;
+        }
+        const resetToken = params.get('reset');
+        if (resetToken) {
+            this.showResetPassword(resetToken);
+            return;
+        }
+
+        // Redirect back from the identity provider
+        const fragment = new URLSearchParams(window.location.hash.slice(1));
+        if (fragment.get('sso_token')) {
+            this.handleSso(fragment);
+            return;
+        }
+        if (fragment.get('challenge_token')) {
+            this.handleSsoChallenge(fragment);
+            return;
+        }
+
         if (!isLoggedIn()) {
-            this.showAuth();
+            this.showAuth(params.get('sso_error'));
         } else {
             this.showDashboard();
         }
     }
 
-    showAuth() {
+    showAuth(ssoError = null) {
         this.appEl.innerHTML = '';
         this.appEl.style.height = '100vh';
         this.appEl.style.overflow = 'auto';
@@ -55,6 +82,10 @@ class App {
                 <div class="auth-link">
                     <a href="#" id="toggle-auth">Don't have an account? Register</a>
                 </div>
+                <div class="auth-link">
+                    <a href="#" id="forgot-password">Forgot password?</a>
+                </div>
+                <a href="/api/auth/oidc/login" class="btn-secondary sso-button" id="sso-login" style="display:none"></a>
             </div>
         `;
         this.appEl.appendChild(container);
@@ -67,6 +98,18 @@ class App {
         const submitBtn = document.getElementById('auth-submit');
         const errorEl = document.getElementById('auth-error');
 
+        if (ssoError) {
+            window.history.replaceState(null, '', '/');
+            errorEl.textContent = ssoError;
+            errorEl.style.display = 'block';
+        }
+        getSsoProvider().then((provider) => {
+            if (!provider.enabled) return;
+            const button = document.getElementById('sso-login');
+            button.textContent = `Sign in with ${provider.name}`;
+            button.style.display = 'block';
+        }).catch(() => {});
+
         toggle.addEventListener('click', (e) => {
             e.preventDefault();
             isLogin = !isLogin;
@@ -77,6 +120,20 @@ class App {
             errorEl.style.display = 'none';
         });
 
+        document.getElementById('forgot-password').addEventListener('click', async (e) => {
+            e.preventDefault();
+            const email = prompt('Enter the email address of your account:');
+            if (!email) return;
+            try {
+                await forgotPassword(email);
+                subtitle.textContent = 'Check your inbox for a link to reset your password';
+                errorEl.style.display = 'none';
+            } catch (err) {
+                errorEl.textContent = err.message;
+                errorEl.style.display = 'block';
+            }
+        });
+
         form.addEventListener('submit', async (e) => {
             e.preventDefault();
             errorEl.style.display = 'none';
@@ -84,12 +141,14 @@ class App {
             const password = document.getElementById('password').value;
 
             try {
+                let result;
                 if (isLogin) {
-                    await login(username, password);
+                    result = await login(username, password);
                 } else {
                     const email = document.getElementById('email').value;
-                    await register(username, email, password);
+                    result = await register(username, email, password);
                 }
+                if (result.two_factor && !await this.completeTwoFactor(result)) return;
                 this.showDashboard();
             } catch (err) {
                 errorEl.textContent = err.message;
@@ -98,6 +157,106 @@ class App {
         });
     }
 
+    // Second login step. Returns false if the user gave up.
+    async completeTwoFactor(challenge) {
+        const token = challenge.challenge_token;
+        if (challenge.two_factor === 'setup') {
+            const setup = await startTwoFactorSetup(token);
+            const code = prompt(
+                'Two-factor authentication is required. Add this key to your authenticator app, '
+                + `then enter the 6-digit code it shows.\n\nKey: ${setup.secret}\n\n${setup.provisioning_uri}`
+            );
+            if (!code) return false;
+            const data = await confirmTwoFactorSetup(token, code);
+            alert(`Save these recovery codes somewhere safe. Each works once if you lose your device:\n\n${data.recovery_codes.join('\n')}`);
+            return true;
+        }
+        const code = prompt('Enter the 6-digit code from your authenticator app, or a recovery code:');
+        if (!code) return false;
+        await verifyTwoFactor(token, code);
+        return true;
+    }
+
+    async handleSso(fragment) {
+        // Drop the tokens from the address bar and history
+        window.history.replaceState(null, '', '/');
+        try {
+            await completeSso(fragment);
+            this.showDashboard();
+        } catch (err) {
+            this.showAuth(err.message);
+        }
+    }
+
+    // Single sign-on for a user who owes a second factor
+    async handleSsoChallenge(fragment) {
+        window.history.replaceState(null, '', '/');
+        try {
+            const challenge = {
+                two_factor: fragment.get('two_factor'),
+                challenge_token: fragment.get('challenge_token'),
+            };
+            if (!await this.completeTwoFactor(challenge)) {
+                this.showAuth();
+                return;
+            }
+            this.showDashboard();
+        } catch (err) {
+            this.showAuth(err.message);
+        }
+    }
+
+    async handleVerifyEmail(token) {
+        // Drop the token from the address bar so a reload does not reuse it
+        window.history.replaceState(null, '', '/');
+        try {
+            await verifyEmail(token);
+            alert('Your email address is verified.');
+        } catch (err) {
+            alert(err.message);
+        }
+        this.route();
+    }
+
+    showResetPassword(token) {
+        window.history.replaceState(null, '', '/');
+        this.appEl.innerHTML = '';
+        this.appEl.style.height = '100vh';
+        document.body.style.overflow = 'auto';
+
+        const container = document.createElement('div');
+        container.className = 'auth-container';
+        container.innerHTML = `
+            <div class="auth-box">
+                <h1>Reset password</h1>
+                <p>Choose a new password. You will be signed out on all devices.</p>
+                <div class="auth-error" id="auth-error"></div>
+                <form id="reset-form">
+                    <div class="form-group">
+                        <label for="password">New password</label>
+                        <input type="password" id="password" minlength="6" required>
+                    </div>
+                    <button type="submit" class="btn-primary" style="width:100%">Set Password</button>
+                </form>
+            </div>
+        `;
+        this.appEl.appendChild(container);
+
+        const errorEl = document.getElementById('auth-error');
+        document.getElementById('reset-form').addEventListener('submit', async (e) => {
+            e.preventDefault();
+            errorEl.style.display = 'none';
+            try {
+                await resetPassword(token, document.getElementById('password').value);
+                alert('Your password has been changed. Sign in with the new password.');
+                await logout();
+            } catch (err) {
+                errorEl.textContent = err.message;
+                errorEl.style.display = 'block';
+            }
+        });
+    }
+
     async showDashboard() {
         this.appEl.innerHTML = '';
         this.appEl.style.height = 'auto';
@@ -115,6 +274,10 @@ class App {
                     <button class="btn-secondary" id="btn-logout">Logout</button>
                 </div>
             </div>
+            <div class="verify-banner" id="verify-banner" style="display:none">
+                Please confirm your email address to create share links.
+                <button class="btn-secondary" id="btn-resend-verification">Resend email</button>
+            </div>
             <div class="boards-grid" id="boards-grid">
                 <div style="color:#999; padding:40px; text-align:center; grid-column: 1/-1;">Loading boards...</div>
             </div>
@@ -123,10 +286,28 @@ class App {
 
         document.getElementById('btn-logout').addEventListener('click', () => logout());
         document.getElementById('btn-new-board').addEventListener('click', () => this.createBoard());
+        document.getElementById('btn-resend-verification').addEventListener('click', async () => {
+            const res = await apiFetch('/api/auth/resend-verification', { method: 'POST' });
+            alert(res.ok ? 'Verification email sent.' : 'Could not send the verification email.');
+        });
 
+        this.loadVerificationStatus();
         await this.loadBoards();
     }
 
+    async loadVerificationStatus() {
+        try {
+            const res = await apiFetch('/api/me');
+            if (!res.ok) return;
+            const user = await res.json();
+            setUser(user);
+            const banner = document.getElementById('verify-banner');
+            if (banner) banner.style.display = user.email_verified ? 'none' : 'flex';
+        } catch (err) {
+            // The banner is a hint only; the server enforces verification
+        }
+    }
+
     async loadBoards() {
         const grid = document.getElementById('boards-grid');
         try {
@@ -11,16 +11,54 @@ export function getUser() {
     return data ? JSON.parse(data) : null;
 }
 
-export function setAuth(token, user) {
-    localStorage.setItem('wb_token', token);
+export function setAuth(data) {
+    localStorage.setItem('wb_token', data.token);
+    localStorage.setItem('wb_refresh', data.refresh_token);
+    localStorage.setItem('wb_token_expires', String(Date.now() + data.expires_in * 1000));
+    localStorage.setItem('wb_user', JSON.stringify(data.user));
+}
+
+export function setUser(user) {
     localStorage.setItem('wb_user', JSON.stringify(user));
 }
 
 export function clearAuth() {
     localStorage.removeItem('wb_token');
+    localStorage.removeItem('wb_refresh');
+    localStorage.removeItem('wb_token_expires');
     localStorage.removeItem('wb_user');
 }
 
+// Concurrent callers share one refresh so a rotated token is never replayed
+let refreshing = null;
+
+export function refreshAuth() {
+    if (!refreshing) {
+        refreshing = (async () => {
+            const refreshToken = localStorage.getItem('wb_refresh');
+            if (!refreshToken) return false;
+            const res = await fetch(`${API_BASE}/api/auth/refresh`, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ refresh_token: refreshToken }),
+            });
+            if (!res.ok) return false;
+            setAuth(await res.json());
+            return true;
+        })().catch(() => false).finally(() => { refreshing = null; });
+    }
+    return refreshing;
+}
+
+// Refresh the access token if it expires within the next minute
+export async function ensureFreshToken() {
+    const expires = Number(localStorage.getItem('wb_token_expires') || 0);
+    if (getToken() && expires - Date.now() < 60000) {
+        await refreshAuth();
+    }
+    return getToken();
+}
+
 export function isLoggedIn() {
     return !!getToken();
 }
@@ -33,7 +71,7 @@ export async function register(username, email, password) {
     });
     const data = await res.json();
     if (!res.ok) throw new Error(data.error || 'Registration failed');
-    setAuth(data.token, data.user);
+    if (!data.challenge_token) setAuth(data);
     return data;
 }
 
@@ -45,11 +83,91 @@ export async function login(username, password) {
     });
     const data = await res.json();
     if (!res.ok) throw new Error(data.error || 'Login failed');
-    setAuth(data.token, data.user);
+    // With two-factor authentication the password only earns a challenge
+    if (!data.challenge_token) setAuth(data);
+    return data;
+}
+
+async function postChallenge(path, body) {
+    const res = await fetch(`${API_BASE}${path}`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(body),
+    });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) throw new Error(data.error || 'Request failed');
+    return data;
+}
+
+export async function verifyTwoFactor(challengeToken, code) {
+    const data = await postChallenge('/api/auth/2fa/verify', { challenge_token: challengeToken, code });
+    setAuth(data);
+    return data;
+}
+
+export function startTwoFactorSetup(challengeToken) {
+    return postChallenge('/api/auth/2fa/setup', { challenge_token: challengeToken });
+}
+
+export async function confirmTwoFactorSetup(challengeToken, code) {
+    const data = await postChallenge('/api/auth/2fa/setup/confirm', { challenge_token: challengeToken, code });
+    setAuth(data);
     return data;
 }
 
-export function logout() {
+async function postJson(path, body) {
+    const res = await fetch(`${API_BASE}${path}`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(body),
+    });
+    if (!res.ok) {
+        const data = await res.json().catch(() => ({}));
+        throw new Error(data.error || 'Request failed');
+    }
+}
+
+export function verifyEmail(token) {
+    return postJson('/api/auth/verify-email', { token });
+}
+
+export function forgotPassword(email) {
+    return postJson('/api/auth/forgot-password', { email });
+}
+
+export function resetPassword(token, password) {
+    return postJson('/api/auth/reset-password', { token, password });
+}
+
+// Whether the server offers single sign-on: { enabled, name }
+export async function getSsoProvider() {
+    const res = await fetch(`${API_BASE}/api/auth/oidc`);
+    return res.ok ? res.json() : { enabled: false };
+}
+
+// Store the session handed over in the URL fragment after single sign-on
+export async function completeSso(fragment) {
+    const token = fragment.get('sso_token');
+    const res = await fetch(`${API_BASE}/api/me`, {
+        headers: { 'Authorization': `Bearer ${token}` },
+    });
+    if (!res.ok) throw new Error('Single sign-on failed');
+    setAuth({
+        token,
+        refresh_token: fragment.get('refresh_token'),
+        expires_in: Number(fragment.get('expires_in')),
+        user: await res.json(),
+    });
+}
+
+export async function logout() {
+    const token = getToken();
+    if (token) {
+        await fetch(`${API_BASE}/api/auth/logout`, {
+            method: 'POST',
+            headers: { 'Authorization': `Bearer ${token}` },
+        }).catch(() => {});
+    }
     clearAuth();
     window.location.href = '/';
 }
@@ -68,7 +186,11 @@ export async function apiFetch(url, options = {}) {
         headers['Content-Type'] = 'application/json';
         options.body = JSON.stringify(options.body);
     }
-    const res = await fetch(`${API_BASE}${url}`, { ...options, headers });
+    await ensureFreshToken();
+    let res = await fetch(`${API_BASE}${url}`, { ...options, headers: { ...headers, ...authHeaders() } });
+    if (res.status === 401 && await refreshAuth()) {
+        res = await fetch(`${API_BASE}${url}`, { ...options, headers: { ...headers, ...authHeaders() } });
+    }
     if (res.status === 401) {
         clearAuth();
         window.location.href = '/';
@@ -1,6 +1,8 @@
 // Sync Manager - handles Yjs document sync and WebSocket communication
 // Uses CDN-loaded Yjs for collaborative editing
 
+import { ensureFreshToken } from '/js/auth.js';
+
 export class SyncManager {
     constructor(app, options = {}) {
         this.app = app;
@@ -12,6 +14,10 @@ export class SyncManager {
         this.reconnectAttempts = 0;
         this.maxReconnectAttempts = 10;
         this.reconnectDelay = 1000;
+        this.shutdownReconnectMs = null;
+        this.role = null;
+        this.readOnlyNotified = false;
+        this.connecting = false;
         this.pendingUpdates = [];
         this.cursorThrottleTimer = null;
 
@@ -20,11 +26,18 @@ export class SyncManager {
         }
     }
 
-    connect() {
-        if (this.ws && (this.ws.readyState === WebSocket.CONNECTING || this.ws.readyState === WebSocket.OPEN)) {
+    async connect() {
+        if (this.connecting || (this.ws && (this.ws.readyState === WebSocket.CONNECTING || this.ws.readyState === WebSocket.OPEN))) {
             return;
         }
 
+        // Access tokens are short-lived; connect with a fresh one
+        if (this.token) {
+            this.connecting = true;
+            this.token = await ensureFreshToken();
+            this.connecting = false;
+        }
+
         const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
         let url = `${protocol}//${window.location.host}/ws/${this.boardId}`;
 
@@ -56,9 +69,17 @@ export class SyncManager {
                 this.handleMessage(event.data);
             };
 
-            this.ws.onclose = () => {
+            this.ws.onclose = (event) => {
                 this.connected = false;
                 console.log('WebSocket disconnected');
+                // Access revoked or board deleted: reconnecting would only be rejected
+                if (event.code === 4403) {
+                    this.app.uiManager?.showToast('Your access to this board was removed');
+                    return;
+                }
+                if (event.code === 4404) {
+                    return;
+                }
                 this.scheduleReconnect();
             };
 
@@ -77,7 +98,12 @@ export class SyncManager {
             return;
         }
 
-        const delay = this.reconnectDelay * Math.pow(2, this.reconnectAttempts);
+        // After a server shutdown, wait as long as the server asked before the first retry
+        const delay = this.shutdownReconnectMs
+            ?? this.reconnectDelay * Math.pow(2, this.reconnectAttempts);
+        this.shutdownReconnectMs = null;
+        this.role = null;
+        this.readOnlyNotified = false;
         this.reconnectAttempts++;
         console.log(`Reconnecting in ${delay}ms (attempt ${this.reconnectAttempts})`);
         setTimeout(() => this.connect(), delay);
@@ -135,6 +161,18 @@ export class SyncManager {
                 case 'leave':
                     this.handleLeave(msg);
                     break;
+                case 'error':
+                    this.handleError(msg);
+                    break;
+                case 'role_changed':
+                    this.handleRoleChanged(msg);
+                    break;
+                case 'board_deleted':
+                    this.handleBoardDeleted();
+                    break;
+                case 'server_shutdown':
+                    this.handleServerShutdown(msg);
+                    break;
             }
         } catch (e) {
             // Ignore parse errors for binary messages
@@ -187,6 +225,7 @@ export class SyncManager {
     // === Incoming Message Handlers ===
 
     handleSyncState(msg) {
+        if (msg.role) this.role = msg.role;
         if (msg.elements && Array.isArray(msg.elements)) {
             // Merge remote state - prefer remote for elements we don't have
             const localIds = new Set(this.app.elements.map(e => e.id));
@@ -243,6 +282,33 @@ export class SyncManager {
         this.updatePresenceBar(msg.users);
     }
 
+    handleError(msg) {
+        console.warn(`Server rejected message: ${msg.message}`);
+        if (msg.code === 'read_only' && !this.readOnlyNotified) {
+            this.readOnlyNotified = true;
+            this.app.uiManager?.showToast(msg.message);
+        }
+    }
+
+    handleRoleChanged(msg) {
+        this.role = msg.role;
+        this.readOnlyNotified = false;
+        this.app.uiManager?.showToast(`Your role on this board is now ${msg.role}`);
+    }
+
+    handleBoardDeleted() {
+        this.app.uiManager?.showToast('This board was deleted');
+        setTimeout(() => { window.location.href = '/'; }, 2000);
+    }
+
+    handleServerShutdown(msg) {
+        console.log('Server is shutting down');
+        this.reconnectAttempts = 0;
+        // Spread reconnects so clients don't all return at once
+        const base = msg.reconnectAfterMs || this.reconnectDelay;
+        this.shutdownReconnectMs = base + Math.floor(Math.random() * base);
+    }
+
     updatePresenceBar(users) {
         const bar = document.getElementById('presence-bar');
         if (!bar || !users) return;
@@ -133,6 +133,9 @@ export class UIManager {
                     const shareUrl = `${window.location.origin}/board.html?id=${this.app.boardId}&share=${link.token}`;
                     await navigator.clipboard.writeText(shareUrl).catch(() => {});
                     this.showToast('Share link created and copied!');
+                } else {
+                    const data = await res.json().catch(() => ({}));
+                    this.showToast(data.error || 'Failed to create share link');
                 }
             } catch (e) {
                 this.showToast('Failed to create share link');